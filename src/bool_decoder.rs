@@ -0,0 +1,275 @@
+//! Boolean (arithmetic) decoder used by the compressed header and the tile data.
+
+use std::convert::TryFrom;
+
+use crate::{Result, Vp9ParserError};
+
+/// Number of bits the window of the decoder can hold.
+const WINDOW_SIZE: u32 = 64;
+
+/// Number of bits of the window that are used for the actual value.
+const VALUE_SIZE: u32 = 8;
+
+/// Decodes boolean values as defined in spec "9.2 Boolean decoding process".
+///
+/// The compressed header and every tile are arithmetic coded. The decoder is initialized
+/// on such a byte slice and then reads boolean values with a given probability.
+///
+/// Reading never fails. Reading past the end of the data feeds zero bits, which is a
+/// violation of the bitstream conformance and is reported when calling `exit()`.
+#[derive(Clone, Debug)]
+pub struct BoolDecoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    /// Holds `BoolValue` in the upper 8 bits and the bits that are read ahead below it.
+    value: u64,
+    /// Number of bits that are read ahead inside the window.
+    count: u32,
+    range: u32,
+    /// Number of bits that can be shifted in before the data is exhausted (`BoolMaxBits`).
+    max_bits: usize,
+    overrun: bool,
+}
+
+impl<'a> BoolDecoder<'a> {
+    /// Initializes the boolean decoder with the given data (spec `init_bool()`).
+    ///
+    /// Returns an error if the data is empty or if the marker bit is not zero.
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        if data.is_empty() {
+            return Err(Vp9ParserError::BoolDecoderDataTooShort);
+        }
+
+        let mut decoder = Self {
+            data,
+            position: 1,
+            value: u64::from(data[0]) << (WINDOW_SIZE - VALUE_SIZE),
+            count: 0,
+            range: 255,
+            max_bits: 8 * data.len() - 8,
+            overrun: false,
+        };
+
+        if decoder.read_bool(128) {
+            return Err(Vp9ParserError::InvalidBoolMarker);
+        }
+
+        Ok(decoder)
+    }
+
+    /// Reads a boolean value that is `false` with the given probability (in 1/256).
+    pub fn read_bool(&mut self, probability: u8) -> bool {
+        let split = 1 + (((self.range - 1) * u32::from(probability)) >> 8);
+        let big_split = u64::from(split) << (WINDOW_SIZE - VALUE_SIZE);
+
+        let bit = if self.value < big_split {
+            self.range = split;
+            false
+        } else {
+            self.range -= split;
+            self.value -= big_split;
+            true
+        };
+
+        // The range is always in the interval [1, 255] and needs to be renormalized to [128, 255].
+        let shift = self.range.leading_zeros() - (u32::BITS - VALUE_SIZE);
+        if shift > 0 {
+            if self.count < shift {
+                self.fill();
+            }
+            self.value <<= shift;
+            self.range <<= shift;
+            self.count -= shift;
+
+            let shift = usize::try_from(shift).unwrap_or(usize::MAX);
+            if shift > self.max_bits {
+                self.max_bits = 0;
+                self.overrun = true;
+            } else {
+                self.max_bits -= shift;
+            }
+        }
+
+        bit
+    }
+
+    /// Reads an unsigned literal of the given number of bits (spec `L(n)`). Supports up to 8 bits.
+    pub fn read_literal(&mut self, bits: u8) -> u8 {
+        debug_assert!(bits <= 8);
+
+        let mut x = 0;
+        for _ in 0..bits {
+            x = (x << 1) | u8::from(self.read_bool(128));
+        }
+        x
+    }
+
+    /// Reads a value that is coded with the given tree (spec `T`).
+    ///
+    /// Positive tree entries are the index of the next node pair, all other entries are
+    /// negated leaf values. The probabilities are indexed by the node pair.
+    pub(crate) fn read_tree(&mut self, tree: &[i8], probabilities: &[u8]) -> u8 {
+        let mut node = 0;
+        loop {
+            let next = tree[node + usize::from(self.read_bool(probabilities[node >> 1]))];
+            if next <= 0 {
+                return next.unsigned_abs();
+            }
+            node = usize::from(next.unsigned_abs());
+        }
+    }
+
+    /// Returns `true` if the decoder had to read past the end of the data.
+    pub fn is_overrun(&self) -> bool {
+        self.overrun
+    }
+
+    /// Finishes the decoding (spec `exit_bool()`).
+    ///
+    /// Returns an error if the decoder read past the end of the data or if the
+    /// remaining padding bits are not zero.
+    pub fn exit(self) -> Result<()> {
+        if self.overrun {
+            return Err(Vp9ParserError::BoolDecoderOverrun);
+        }
+
+        // The padding consists of the bits that were read ahead and the bytes not yet read.
+        let read_ahead = self.value << VALUE_SIZE;
+        if read_ahead != 0 || self.data[self.position..].iter().any(|byte| *byte != 0) {
+            return Err(Vp9ParserError::InvalidBoolPadding);
+        }
+
+        Ok(())
+    }
+
+    /// Fills the window with as many bytes as possible. Zeros are shifted in once the data is exhausted.
+    fn fill(&mut self) {
+        while self.count <= WINDOW_SIZE - VALUE_SIZE - 8 {
+            let shift = WINDOW_SIZE - VALUE_SIZE - 8 - self.count;
+            if let Some(byte) = self.data.get(self.position) {
+                self.value |= u64::from(*byte) << shift;
+                self.position += 1;
+            }
+            self.count += 8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    /// Boolean encoder following the libvpx implementation.
+    struct BoolEncoder {
+        buffer: Vec<u8>,
+        low: u32,
+        range: u32,
+        count: i32,
+    }
+
+    impl BoolEncoder {
+        fn new() -> Self {
+            let mut encoder = Self {
+                buffer: vec![],
+                low: 0,
+                range: 255,
+                count: -24,
+            };
+            encoder.write_bool(false, 128);
+            encoder
+        }
+
+        fn write_bool(&mut self, bit: bool, probability: u8) {
+            let split = 1 + (((self.range - 1) * u32::from(probability)) >> 8);
+            let mut range = split;
+            if bit {
+                self.low += split;
+                range = self.range - split;
+            }
+
+            let mut shift = range.leading_zeros() - 24;
+            self.range = range << shift;
+            self.count += i32::try_from(shift).unwrap();
+
+            if self.count >= 0 {
+                let offset = shift - u32::try_from(self.count).unwrap();
+                if (self.low << (offset - 1)) & 0x8000_0000 != 0 {
+                    let mut x = self.buffer.len() - 1;
+                    while self.buffer[x] == 0xFF {
+                        self.buffer[x] = 0;
+                        x -= 1;
+                    }
+                    self.buffer[x] += 1;
+                }
+                self.buffer
+                    .push(u8::try_from((self.low >> (24 - offset)) & 0xFF).unwrap());
+                self.low = (self.low << offset) & 0xFF_FFFF;
+                shift = u32::try_from(self.count).unwrap();
+                self.count -= 8;
+            }
+
+            self.low <<= shift;
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            for _ in 0..32 {
+                self.write_bool(false, 128);
+            }
+            self.buffer
+        }
+    }
+
+    #[test]
+    fn read_bools_and_literals() -> Result<()> {
+        let mut encoder = BoolEncoder::new();
+        let symbols: Vec<(bool, u8)> = (0..1000u32)
+            .map(|i| {
+                let probability = u8::try_from((i * 37) % 255 + 1).unwrap();
+                ((i * 7919) % 3 == 0, probability)
+            })
+            .collect();
+        for (bit, probability) in symbols.iter() {
+            encoder.write_bool(*bit, *probability);
+        }
+        for bit in [true, false, true, true, false, false, true, false] {
+            encoder.write_bool(bit, 128);
+        }
+        let data = encoder.finish();
+
+        let mut decoder = BoolDecoder::new(&data)?;
+        for (bit, probability) in symbols.iter() {
+            assert_eq!(decoder.read_bool(*probability), *bit);
+        }
+        assert_eq!(decoder.read_literal(8), 0b1011_0010);
+        decoder.exit()
+    }
+
+    #[test]
+    fn invalid_data() {
+        assert!(matches!(
+            BoolDecoder::new(&[]),
+            Err(Vp9ParserError::BoolDecoderDataTooShort)
+        ));
+        assert!(matches!(
+            BoolDecoder::new(&[0xFF, 0xFF]),
+            Err(Vp9ParserError::InvalidBoolMarker)
+        ));
+
+        let mut decoder = BoolDecoder::new(&[0x00, 0x01]).unwrap();
+        let _ = decoder.read_bool(128);
+        assert!(matches!(
+            decoder.exit(),
+            Err(Vp9ParserError::InvalidBoolPadding)
+        ));
+
+        let mut decoder = BoolDecoder::new(&[0x00]).unwrap();
+        let _ = decoder.read_literal(8);
+        assert!(decoder.is_overrun());
+        assert!(matches!(
+            decoder.exit(),
+            Err(Vp9ParserError::BoolDecoderOverrun)
+        ));
+    }
+}
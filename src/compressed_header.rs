@@ -0,0 +1,608 @@
+//! Parsing of the compressed header.
+
+use crate::{
+    BoolDecoder, Frame, InterpolationFilter, Result, ALTREF_FRAME, GOLDEN_FRAME, LAST_FRAME,
+};
+
+/// Number of contexts for the transform size probabilities.
+pub(crate) const TX_SIZE_CONTEXTS: usize = 2;
+/// Number of transform sizes.
+pub(crate) const TX_SIZES: usize = 4;
+/// Number of contexts for the skip probabilities.
+pub(crate) const SKIP_CONTEXTS: usize = 3;
+/// Number of contexts for the inter mode probabilities.
+pub(crate) const INTER_MODE_CONTEXTS: usize = 7;
+/// Number of inter modes.
+pub(crate) const INTER_MODES: usize = 4;
+/// Number of contexts for the interpolation filter probabilities.
+pub(crate) const INTERP_FILTER_CONTEXTS: usize = 4;
+/// Number of switchable interpolation filters.
+pub(crate) const SWITCHABLE_FILTERS: usize = 3;
+/// Number of contexts for the is_inter probabilities.
+pub(crate) const IS_INTER_CONTEXTS: usize = 4;
+/// Number of contexts for the compound mode probabilities.
+pub(crate) const COMP_MODE_CONTEXTS: usize = 5;
+/// Number of contexts for the reference frame probabilities.
+pub(crate) const REF_CONTEXTS: usize = 5;
+/// Number of block size groups for the y mode probabilities.
+pub(crate) const BLOCK_SIZE_GROUPS: usize = 4;
+/// Number of intra modes.
+pub(crate) const INTRA_MODES: usize = 10;
+/// Number of contexts for the partition probabilities.
+pub(crate) const PARTITION_CONTEXTS: usize = 16;
+/// Number of partition types.
+pub(crate) const PARTITION_TYPES: usize = 4;
+/// Number of motion vector joints.
+pub(crate) const MV_JOINTS: usize = 4;
+/// Number of motion vector classes.
+pub(crate) const MV_CLASSES: usize = 11;
+/// Number of class 0 motion vector offsets.
+pub(crate) const CLASS0_SIZE: usize = 2;
+/// Number of bits of the integer motion vector offset.
+pub(crate) const MV_OFFSET_BITS: usize = 10;
+/// Number of fractional motion vector values.
+pub(crate) const MV_FR_SIZE: usize = 4;
+/// Number of planes types (luma and chroma) for the coefficient probabilities.
+pub(crate) const BLOCK_TYPES: usize = 2;
+/// Number of reference types (intra and inter) for the coefficient probabilities.
+pub(crate) const REF_TYPES: usize = 2;
+/// Number of coefficient bands.
+pub(crate) const COEF_BANDS: usize = 6;
+/// Number of contexts of the coefficient probabilities.
+pub(crate) const PREV_COEF_CONTEXTS: usize = 6;
+/// Number of coefficient probabilities that are explicitly coded.
+pub(crate) const UNCONSTRAINED_NODES: usize = 3;
+
+/// Coefficient probability updates indexed by transform size, plane type, reference type,
+/// band, context and node.
+pub type CoefProbUpdates = [[[[[[Option<u8>; UNCONSTRAINED_NODES]; PREV_COEF_CONTEXTS]; COEF_BANDS];
+    REF_TYPES]; BLOCK_TYPES]; TX_SIZES];
+
+/// Specifies how the transform size is determined.
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
+pub enum TxMode {
+    /// Only 4x4 transforms are used.
+    Only4x4,
+    /// Transforms up to 8x8 are allowed.
+    Allow8x8,
+    /// Transforms up to 16x16 are allowed.
+    Allow16x16,
+    /// Transforms up to 32x32 are allowed.
+    Allow32x32,
+    /// The transform size is specified for every block.
+    TxModeSelect,
+}
+
+impl From<u8> for TxMode {
+    fn from(i: u8) -> Self {
+        match i {
+            0 => TxMode::Only4x4,
+            1 => TxMode::Allow8x8,
+            2 => TxMode::Allow16x16,
+            3 => TxMode::Allow32x32,
+            _ => TxMode::TxModeSelect,
+        }
+    }
+}
+
+/// Specifies the type of the inter prediction.
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
+pub enum ReferenceMode {
+    /// All inter blocks use a single reference frame.
+    SingleReference,
+    /// All inter blocks use compound prediction with two reference frames.
+    CompoundReference,
+    /// The reference mode is specified for every block.
+    ReferenceModeSelect,
+}
+
+/// The compressed header of a frame.
+///
+/// Contains the transform mode, the reference mode and the updates of the probabilities.
+///
+/// Probability updates are stored as they are coded inside the bitstream. For all probabilities
+/// that are coded as a difference (`diff_update_prob()`), the decoded `deltaProb` is stored.
+/// The motion vector probabilities (`update_mv_prob()`) store the new probability directly.
+/// `None` marks a probability that is not updated by the frame.
+#[derive(Clone, Debug)]
+pub struct CompressedHeader {
+    tx_mode: TxMode,
+    tx_probs_8x8: [[Option<u8>; TX_SIZES - 3]; TX_SIZE_CONTEXTS],
+    tx_probs_16x16: [[Option<u8>; TX_SIZES - 2]; TX_SIZE_CONTEXTS],
+    tx_probs_32x32: [[Option<u8>; TX_SIZES - 1]; TX_SIZE_CONTEXTS],
+    coef_probs: Box<CoefProbUpdates>,
+    skip_prob: [Option<u8>; SKIP_CONTEXTS],
+    inter_mode_probs: [[Option<u8>; INTER_MODES - 1]; INTER_MODE_CONTEXTS],
+    interp_filter_probs: [[Option<u8>; SWITCHABLE_FILTERS - 1]; INTERP_FILTER_CONTEXTS],
+    is_inter_prob: [Option<u8>; IS_INTER_CONTEXTS],
+    reference_mode: ReferenceMode,
+    comp_fixed_ref: usize,
+    comp_var_ref: [usize; 2],
+    comp_mode_prob: [Option<u8>; COMP_MODE_CONTEXTS],
+    single_ref_prob: [[Option<u8>; 2]; REF_CONTEXTS],
+    comp_ref_prob: [Option<u8>; REF_CONTEXTS],
+    y_mode_probs: [[Option<u8>; INTRA_MODES - 1]; BLOCK_SIZE_GROUPS],
+    partition_probs: [[Option<u8>; PARTITION_TYPES - 1]; PARTITION_CONTEXTS],
+    mv_joint_probs: [Option<u8>; MV_JOINTS - 1],
+    mv_sign_prob: [Option<u8>; 2],
+    mv_class_probs: [[Option<u8>; MV_CLASSES - 1]; 2],
+    mv_class0_bit_prob: [Option<u8>; 2],
+    mv_bits_prob: [[Option<u8>; MV_OFFSET_BITS]; 2],
+    mv_class0_fr_probs: [[[Option<u8>; MV_FR_SIZE - 1]; CLASS0_SIZE]; 2],
+    mv_fr_probs: [[Option<u8>; MV_FR_SIZE - 1]; 2],
+    mv_class0_hp_prob: [Option<u8>; 2],
+    mv_hp_prob: [Option<u8>; 2],
+}
+
+impl CompressedHeader {
+    /// Parses the compressed header of the given frame.
+    pub(crate) fn new(frame: &Frame) -> Result<Self> {
+        let mut bd = BoolDecoder::new(frame.compressed_header_data())?;
+
+        let mut header = Self {
+            tx_mode: TxMode::Only4x4,
+            tx_probs_8x8: Default::default(),
+            tx_probs_16x16: Default::default(),
+            tx_probs_32x32: Default::default(),
+            coef_probs: Box::new(
+                [[[[[[None; UNCONSTRAINED_NODES]; PREV_COEF_CONTEXTS]; COEF_BANDS]; REF_TYPES];
+                    BLOCK_TYPES]; TX_SIZES],
+            ),
+            skip_prob: Default::default(),
+            inter_mode_probs: Default::default(),
+            interp_filter_probs: Default::default(),
+            is_inter_prob: Default::default(),
+            reference_mode: ReferenceMode::SingleReference,
+            comp_fixed_ref: ALTREF_FRAME,
+            comp_var_ref: [LAST_FRAME, GOLDEN_FRAME],
+            comp_mode_prob: Default::default(),
+            single_ref_prob: Default::default(),
+            comp_ref_prob: Default::default(),
+            y_mode_probs: Default::default(),
+            partition_probs: Default::default(),
+            mv_joint_probs: Default::default(),
+            mv_sign_prob: Default::default(),
+            mv_class_probs: Default::default(),
+            mv_class0_bit_prob: Default::default(),
+            mv_bits_prob: Default::default(),
+            mv_class0_fr_probs: Default::default(),
+            mv_fr_probs: Default::default(),
+            mv_class0_hp_prob: Default::default(),
+            mv_hp_prob: Default::default(),
+        };
+
+        header.read_tx_mode(&mut bd, frame.lossless());
+        if header.tx_mode == TxMode::TxModeSelect {
+            header.tx_mode_probs(&mut bd);
+        }
+        header.read_coef_probs(&mut bd);
+        read_diff_updates(&mut bd, &mut header.skip_prob);
+
+        if !frame.is_intra() {
+            for probs in header.inter_mode_probs.iter_mut() {
+                read_diff_updates(&mut bd, probs);
+            }
+            if frame.interpolation_filter() == InterpolationFilter::Switchable {
+                for probs in header.interp_filter_probs.iter_mut() {
+                    read_diff_updates(&mut bd, probs);
+                }
+            }
+            read_diff_updates(&mut bd, &mut header.is_inter_prob);
+            header.frame_reference_mode(&mut bd, frame.ref_frame_sign_bias());
+            header.frame_reference_mode_probs(&mut bd);
+            for probs in header.y_mode_probs.iter_mut() {
+                read_diff_updates(&mut bd, probs);
+            }
+            for probs in header.partition_probs.iter_mut() {
+                read_diff_updates(&mut bd, probs);
+            }
+            header.mv_probs(&mut bd, frame.allow_high_precision_mv());
+        }
+
+        bd.exit()?;
+
+        Ok(header)
+    }
+
+    fn read_tx_mode(&mut self, bd: &mut BoolDecoder, lossless: bool) {
+        if lossless {
+            self.tx_mode = TxMode::Only4x4;
+        } else {
+            let mut tx_mode = bd.read_literal(2);
+            if tx_mode == 3 {
+                tx_mode += bd.read_literal(1);
+            }
+            self.tx_mode = tx_mode.into();
+        }
+    }
+
+    fn tx_mode_probs(&mut self, bd: &mut BoolDecoder) {
+        for probs in self.tx_probs_8x8.iter_mut() {
+            read_diff_updates(bd, probs);
+        }
+        for probs in self.tx_probs_16x16.iter_mut() {
+            read_diff_updates(bd, probs);
+        }
+        for probs in self.tx_probs_32x32.iter_mut() {
+            read_diff_updates(bd, probs);
+        }
+    }
+
+    fn read_coef_probs(&mut self, bd: &mut BoolDecoder) {
+        let max_tx_size = match self.tx_mode {
+            TxMode::Only4x4 => 0,
+            TxMode::Allow8x8 => 1,
+            TxMode::Allow16x16 => 2,
+            TxMode::Allow32x32 | TxMode::TxModeSelect => 3,
+        };
+
+        for tx_size in self.coef_probs.iter_mut().take(max_tx_size + 1) {
+            let update_probs = bd.read_literal(1) == 1;
+            if update_probs {
+                for bands in tx_size.iter_mut().flatten() {
+                    for (band, contexts) in bands.iter_mut().enumerate() {
+                        let context_count = if band == 0 { 3 } else { 6 };
+                        for probs in contexts.iter_mut().take(context_count) {
+                            read_diff_updates(bd, probs);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn frame_reference_mode(&mut self, bd: &mut BoolDecoder, sign_bias: &[bool; 4]) {
+        let compound_reference_allowed = (GOLDEN_FRAME..=ALTREF_FRAME)
+            .any(|reference| sign_bias[reference] != sign_bias[LAST_FRAME]);
+
+        self.reference_mode = if compound_reference_allowed {
+            let non_single_reference = bd.read_literal(1) == 1;
+            if !non_single_reference {
+                ReferenceMode::SingleReference
+            } else {
+                let reference_select = bd.read_literal(1) == 1;
+                if reference_select {
+                    ReferenceMode::ReferenceModeSelect
+                } else {
+                    ReferenceMode::CompoundReference
+                }
+            }
+        } else {
+            ReferenceMode::SingleReference
+        };
+
+        // Implements spec "setup_compound_reference_mode()".
+        if sign_bias[LAST_FRAME] == sign_bias[GOLDEN_FRAME] {
+            self.comp_fixed_ref = ALTREF_FRAME;
+            self.comp_var_ref = [LAST_FRAME, GOLDEN_FRAME];
+        } else if sign_bias[LAST_FRAME] == sign_bias[ALTREF_FRAME] {
+            self.comp_fixed_ref = GOLDEN_FRAME;
+            self.comp_var_ref = [LAST_FRAME, ALTREF_FRAME];
+        } else {
+            self.comp_fixed_ref = LAST_FRAME;
+            self.comp_var_ref = [GOLDEN_FRAME, ALTREF_FRAME];
+        }
+    }
+
+    fn frame_reference_mode_probs(&mut self, bd: &mut BoolDecoder) {
+        if self.reference_mode == ReferenceMode::ReferenceModeSelect {
+            read_diff_updates(bd, &mut self.comp_mode_prob);
+        }
+        if self.reference_mode != ReferenceMode::CompoundReference {
+            for probs in self.single_ref_prob.iter_mut() {
+                read_diff_updates(bd, probs);
+            }
+        }
+        if self.reference_mode != ReferenceMode::SingleReference {
+            read_diff_updates(bd, &mut self.comp_ref_prob);
+        }
+    }
+
+    fn mv_probs(&mut self, bd: &mut BoolDecoder, allow_high_precision_mv: bool) {
+        read_mv_updates(bd, &mut self.mv_joint_probs);
+        for i in 0..2 {
+            read_mv_updates(bd, &mut self.mv_sign_prob[i..=i]);
+            read_mv_updates(bd, &mut self.mv_class_probs[i]);
+            read_mv_updates(bd, &mut self.mv_class0_bit_prob[i..=i]);
+            read_mv_updates(bd, &mut self.mv_bits_prob[i]);
+        }
+        for i in 0..2 {
+            for probs in self.mv_class0_fr_probs[i].iter_mut() {
+                read_mv_updates(bd, probs);
+            }
+            read_mv_updates(bd, &mut self.mv_fr_probs[i]);
+        }
+        if allow_high_precision_mv {
+            for i in 0..2 {
+                read_mv_updates(bd, &mut self.mv_class0_hp_prob[i..=i]);
+                read_mv_updates(bd, &mut self.mv_hp_prob[i..=i]);
+            }
+        }
+    }
+
+    /// Specifies how the transform size is determined.
+    pub fn tx_mode(&self) -> TxMode {
+        self.tx_mode
+    }
+
+    /// Updates of the probabilities of the 8x8 transform size.
+    pub fn tx_probs_8x8(&self) -> &[[Option<u8>; TX_SIZES - 3]; TX_SIZE_CONTEXTS] {
+        &self.tx_probs_8x8
+    }
+
+    /// Updates of the probabilities of the transform sizes up to 16x16.
+    pub fn tx_probs_16x16(&self) -> &[[Option<u8>; TX_SIZES - 2]; TX_SIZE_CONTEXTS] {
+        &self.tx_probs_16x16
+    }
+
+    /// Updates of the probabilities of the transform sizes up to 32x32.
+    pub fn tx_probs_32x32(&self) -> &[[Option<u8>; TX_SIZES - 1]; TX_SIZE_CONTEXTS] {
+        &self.tx_probs_32x32
+    }
+
+    /// Updates of the coefficient probabilities.
+    pub fn coef_probs(&self) -> &CoefProbUpdates {
+        &self.coef_probs
+    }
+
+    /// Updates of the skip probabilities.
+    pub fn skip_prob(&self) -> &[Option<u8>; SKIP_CONTEXTS] {
+        &self.skip_prob
+    }
+
+    /// Updates of the inter mode probabilities.
+    pub fn inter_mode_probs(&self) -> &[[Option<u8>; INTER_MODES - 1]; INTER_MODE_CONTEXTS] {
+        &self.inter_mode_probs
+    }
+
+    /// Updates of the interpolation filter probabilities.
+    pub fn interp_filter_probs(
+        &self,
+    ) -> &[[Option<u8>; SWITCHABLE_FILTERS - 1]; INTERP_FILTER_CONTEXTS] {
+        &self.interp_filter_probs
+    }
+
+    /// Updates of the probabilities that a block is inter predicted.
+    pub fn is_inter_prob(&self) -> &[Option<u8>; IS_INTER_CONTEXTS] {
+        &self.is_inter_prob
+    }
+
+    /// Specifies the type of the inter prediction.
+    pub fn reference_mode(&self) -> ReferenceMode {
+        self.reference_mode
+    }
+
+    /// The reference frame that is always used by compound prediction.
+    pub fn comp_fixed_ref(&self) -> usize {
+        self.comp_fixed_ref
+    }
+
+    /// The reference frames that can be selected by compound prediction.
+    pub fn comp_var_ref(&self) -> &[usize; 2] {
+        &self.comp_var_ref
+    }
+
+    /// Updates of the compound mode probabilities.
+    pub fn comp_mode_prob(&self) -> &[Option<u8>; COMP_MODE_CONTEXTS] {
+        &self.comp_mode_prob
+    }
+
+    /// Updates of the single reference probabilities.
+    pub fn single_ref_prob(&self) -> &[[Option<u8>; 2]; REF_CONTEXTS] {
+        &self.single_ref_prob
+    }
+
+    /// Updates of the compound reference probabilities.
+    pub fn comp_ref_prob(&self) -> &[Option<u8>; REF_CONTEXTS] {
+        &self.comp_ref_prob
+    }
+
+    /// Updates of the y mode probabilities.
+    pub fn y_mode_probs(&self) -> &[[Option<u8>; INTRA_MODES - 1]; BLOCK_SIZE_GROUPS] {
+        &self.y_mode_probs
+    }
+
+    /// Updates of the partition probabilities.
+    pub fn partition_probs(&self) -> &[[Option<u8>; PARTITION_TYPES - 1]; PARTITION_CONTEXTS] {
+        &self.partition_probs
+    }
+
+    /// New motion vector joint probabilities.
+    pub fn mv_joint_probs(&self) -> &[Option<u8>; MV_JOINTS - 1] {
+        &self.mv_joint_probs
+    }
+
+    /// New motion vector sign probabilities.
+    pub fn mv_sign_prob(&self) -> &[Option<u8>; 2] {
+        &self.mv_sign_prob
+    }
+
+    /// New motion vector class probabilities.
+    pub fn mv_class_probs(&self) -> &[[Option<u8>; MV_CLASSES - 1]; 2] {
+        &self.mv_class_probs
+    }
+
+    /// New motion vector class 0 bit probabilities.
+    pub fn mv_class0_bit_prob(&self) -> &[Option<u8>; 2] {
+        &self.mv_class0_bit_prob
+    }
+
+    /// New motion vector integer bits probabilities.
+    pub fn mv_bits_prob(&self) -> &[[Option<u8>; MV_OFFSET_BITS]; 2] {
+        &self.mv_bits_prob
+    }
+
+    /// New motion vector class 0 fractional probabilities.
+    pub fn mv_class0_fr_probs(&self) -> &[[[Option<u8>; MV_FR_SIZE - 1]; CLASS0_SIZE]; 2] {
+        &self.mv_class0_fr_probs
+    }
+
+    /// New motion vector fractional probabilities.
+    pub fn mv_fr_probs(&self) -> &[[Option<u8>; MV_FR_SIZE - 1]; 2] {
+        &self.mv_fr_probs
+    }
+
+    /// New motion vector class 0 high precision probabilities.
+    pub fn mv_class0_hp_prob(&self) -> &[Option<u8>; 2] {
+        &self.mv_class0_hp_prob
+    }
+
+    /// New motion vector high precision probabilities.
+    pub fn mv_hp_prob(&self) -> &[Option<u8>; 2] {
+        &self.mv_hp_prob
+    }
+}
+
+// Implements spec "diff_update_prob()" for all given probabilities.
+fn read_diff_updates(bd: &mut BoolDecoder, updates: &mut [Option<u8>]) {
+    for update in updates.iter_mut() {
+        let update_prob = bd.read_bool(252);
+        if update_prob {
+            *update = Some(decode_term_subexp(bd));
+        }
+    }
+}
+
+// Implements spec "update_mv_prob()" for all given probabilities.
+fn read_mv_updates(bd: &mut BoolDecoder, updates: &mut [Option<u8>]) {
+    for update in updates.iter_mut() {
+        let update_mv_prob = bd.read_bool(252);
+        if update_mv_prob {
+            let mv_prob = bd.read_literal(7);
+            *update = Some((mv_prob << 1) | 1);
+        }
+    }
+}
+
+fn decode_term_subexp(bd: &mut BoolDecoder) -> u8 {
+    if bd.read_literal(1) == 0 {
+        return bd.read_literal(4);
+    }
+    if bd.read_literal(1) == 0 {
+        return bd.read_literal(4) + 16;
+    }
+    if bd.read_literal(1) == 0 {
+        return bd.read_literal(5) + 32;
+    }
+    let v = bd.read_literal(7);
+    if v < 65 {
+        return v + 64;
+    }
+    let bit = bd.read_literal(1);
+    (v << 1) - 1 + bit
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::{ivf::Ivf, Vp9Parser, Vp9ParserError};
+
+    fn parse_frames(path: &str, packets: usize) -> Vec<Frame> {
+        let mut ivf = Ivf::new(std::fs::File::open(path).unwrap()).unwrap();
+        let mut parser = Vp9Parser::default();
+        (0..packets)
+            .flat_map(|_| {
+                let packet = ivf.read_frame().unwrap().unwrap().packet;
+                parser.parse_packet(packet).unwrap()
+            })
+            .collect()
+    }
+
+    fn coef_updates(header: &CompressedHeader) -> usize {
+        header
+            .coef_probs()
+            .iter()
+            .flatten()
+            .flatten()
+            .flatten()
+            .flatten()
+            .flatten()
+            .filter(|update| update.is_some())
+            .count()
+    }
+
+    #[test]
+    fn parse_key_frame() {
+        let frames = parse_frames("tests/data/320-24-cq.ivf", 1);
+        let header = CompressedHeader::new(&frames[0]).unwrap();
+
+        assert_eq!(header.tx_mode(), TxMode::TxModeSelect);
+        assert_eq!(header.tx_probs_8x8(), &[[Some(12)], [Some(8)]]);
+        assert_eq!(
+            header.tx_probs_16x16(),
+            &[[None, Some(19)], [None, Some(12)]]
+        );
+        assert_eq!(header.tx_probs_32x32(), &[[None; 3]; 2]);
+        assert_eq!(header.skip_prob(), &[Some(4), None, None]);
+        assert_eq!(coef_updates(&header), 30);
+        assert_eq!(header.coef_probs()[0][0][0][0][1], [None, None, Some(9)]);
+        assert_eq!(
+            header.coef_probs()[0][0][0][0][2],
+            [None, Some(2), Some(72)]
+        );
+        // Band 0 only has three contexts.
+        assert_eq!(header.coef_probs()[0][0][0][0][3], [None; 3]);
+
+        // Intra frames don't update the inter probabilities.
+        assert_eq!(header.reference_mode(), ReferenceMode::SingleReference);
+        assert!(header
+            .inter_mode_probs()
+            .iter()
+            .flatten()
+            .all(Option::is_none));
+        assert!(header.y_mode_probs().iter().flatten().all(Option::is_none));
+        assert!(header.mv_joint_probs().iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn parse_inter_frames() {
+        // The second packet is a super frame with two inter frames.
+        let frames = parse_frames("tests/data/320-24-cq.ivf", 2);
+        assert_eq!(frames.len(), 3);
+
+        let header = CompressedHeader::new(&frames[1]).unwrap();
+        assert_eq!(header.tx_mode(), TxMode::TxModeSelect);
+        assert_eq!(coef_updates(&header), 7);
+        assert_eq!(header.coef_probs()[0][0][0][1][1][0], Some(19));
+        assert_eq!(header.coef_probs()[1][0][0][4][0][0], Some(15));
+        assert_eq!(header.reference_mode(), ReferenceMode::SingleReference);
+        assert_eq!(header.single_ref_prob()[4], [Some(2), None]);
+        // The motion vector probabilities are coded with 7 bits and are always odd.
+        assert_eq!(header.mv_joint_probs(), &[Some(3), Some(23), None]);
+        assert_eq!(header.mv_class0_fr_probs()[0][0], [Some(49), None, None]);
+
+        let header = CompressedHeader::new(&frames[2]).unwrap();
+        assert_eq!(header.tx_mode(), TxMode::Allow8x8);
+        assert_eq!(coef_updates(&header), 0);
+        assert!(header.mv_joint_probs().iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn check_padding() {
+        // All compressed headers of the bundled streams end in zero padding.
+        for path in [
+            "tests/data/320-24-crf.ivf",
+            "tests/data/320-24-cq.ivf",
+            "tests/data/320-444-10bit.ivf",
+            "tests/data/320-444-12bit.ivf",
+        ] {
+            for frame in parse_frames(path, 10) {
+                if !frame.show_existing_frame() {
+                    assert!(CompressedHeader::new(&frame).is_ok(), "{}", path);
+                }
+            }
+        }
+
+        // A set bit in the padding of the key frame is rejected.
+        let mut frame = parse_frames("tests/data/320-24-cq.ivf", 1).remove(0);
+        let last = frame.uncompressed_header_size() + frame.compressed_header_size() - 1;
+        assert_eq!(frame.data[last], 0);
+        frame.data[last] = 1;
+        assert!(matches!(
+            CompressedHeader::new(&frame),
+            Err(Vp9ParserError::InvalidBoolPadding)
+        ));
+    }
+}
@@ -0,0 +1,360 @@
+//! Debug visualization of the decoded mode info.
+//!
+//! The block partitioning, the prediction modes, the segment ids, the skip flags and the
+//! motion vectors of a frame are drawn as layers onto an RGB image, which can be written as
+//! a binary PPM file. Only the mode info decoded from the tile data is needed, the picture
+//! of the frame can optionally be used as the background.
+
+use std::{convert::TryFrom, io::Write};
+
+use crate::{
+    Frame, ModeInfoGrid, Plane, PredictionMode, Result, ALTREF_FRAME, GOLDEN_FRAME, LAST_FRAME,
+    MAX_SEGMENTS,
+};
+
+/// Color of the block borders.
+const BORDER_COLOR: [u8; 3] = [255, 255, 255];
+
+/// Colors of the prediction modes. Intra modes use warm colors, inter modes cold colors.
+const MODE_COLORS: [[u8; 3]; 14] = [
+    [255, 0, 0],     // DC_PRED
+    [255, 128, 0],   // V_PRED
+    [255, 255, 0],   // H_PRED
+    [255, 0, 128],   // D45_PRED
+    [192, 64, 64],   // D135_PRED
+    [255, 128, 128], // D117_PRED
+    [192, 128, 0],   // D153_PRED
+    [255, 192, 64],  // D207_PRED
+    [192, 0, 192],   // D63_PRED
+    [128, 64, 0],    // TM_PRED
+    [0, 128, 255],   // NEARESTMV
+    [0, 255, 255],   // NEARMV
+    [0, 64, 128],    // ZEROMV
+    [0, 255, 0],     // NEWMV
+];
+
+/// Colors of the segment ids.
+const SEGMENT_COLORS: [[u8; 3]; MAX_SEGMENTS] = [
+    [0, 0, 0],
+    [230, 25, 75],
+    [60, 180, 75],
+    [255, 225, 25],
+    [0, 130, 200],
+    [245, 130, 48],
+    [145, 30, 180],
+    [70, 240, 240],
+];
+
+/// Color of skipped blocks.
+const SKIP_COLOR: [u8; 3] = [0, 0, 0];
+
+/// Colors of the motion vectors of the LAST, GOLDEN and ALTREF frame.
+const MV_COLORS: [[u8; 3]; 3] = [[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+
+/// The information drawn onto the image.
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
+pub enum Layer {
+    /// The borders of the blocks.
+    Partition,
+    /// The luma prediction mode of the blocks, tinted with the colors of `mode_color()`.
+    PredictionMode,
+    /// The segment ids of the blocks, tinted with the colors of `segment_color()`.
+    SegmentId,
+    /// Skipped blocks, which have no residual, are darkened.
+    Skip,
+    /// The motion vectors from the center of the blocks, red for LAST, green for GOLDEN and
+    /// blue for ALTREF.
+    MotionVectors,
+}
+
+/// An RGB image with 8 bits per channel.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Image {
+    /// Creates an image filled with the given color.
+    pub fn new(width: usize, height: usize, color: [u8; 3]) -> Self {
+        Self {
+            width,
+            height,
+            data: color.repeat(width * height),
+        }
+    }
+
+    /// Creates a grey image from the visible samples of a plane.
+    pub fn from_plane(plane: &Plane) -> Self {
+        let shift = plane.bit_depth().saturating_sub(8);
+        let mut data = Vec::with_capacity(plane.width() * plane.height() * 3);
+        for y in 0..plane.height() {
+            for sample in plane.row(y).unwrap_or_default() {
+                let value = u8::try_from(sample >> shift).unwrap_or(u8::MAX);
+                data.extend([value; 3]);
+            }
+        }
+        Self {
+            width: plane.width(),
+            height: plane.height(),
+            data,
+        }
+    }
+
+    /// The width in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The height in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The RGB values of all pixels in raster order.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The RGB value of a pixel. Returns `None` if the pixel is outside of the image.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<[u8; 3]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let start = (y * self.width + x) * 3;
+        Some([self.data[start], self.data[start + 1], self.data[start + 2]])
+    }
+
+    /// Writes the image as a binary PPM (P6) file.
+    pub fn write_ppm<W: Write>(&self, mut writer: W) -> Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        writer.write_all(&self.data)?;
+        Ok(())
+    }
+
+    fn set_pixel(&mut self, x: i64, y: i64, color: [u8; 3]) {
+        let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) else {
+            return;
+        };
+        if x < self.width && y < self.height {
+            let start = (y * self.width + x) * 3;
+            self.data[start..start + 3].copy_from_slice(&color);
+        }
+    }
+
+    /// Mixes the pixels of the rectangle half and half with the color.
+    fn tint(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        for row in y..(y + height).min(self.height) {
+            for column in x..(x + width).min(self.width) {
+                let start = (row * self.width + column) * 3;
+                for (value, tint) in self.data[start..start + 3].iter_mut().zip(color) {
+                    *value =
+                        u8::try_from((u16::from(*value) + u16::from(tint)) / 2).unwrap_or(u8::MAX);
+                }
+            }
+        }
+    }
+
+    /// Draws the top and left border of the rectangle.
+    fn border(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        let (Ok(x), Ok(y)) = (i64::try_from(x), i64::try_from(y)) else {
+            return;
+        };
+        let (Ok(width), Ok(height)) = (i64::try_from(width), i64::try_from(height)) else {
+            return;
+        };
+        for i in 0..width {
+            self.set_pixel(x + i, y, color);
+        }
+        for i in 0..height {
+            self.set_pixel(x, y + i, color);
+        }
+    }
+
+    /// Draws a line with Bresenham's algorithm.
+    fn line(&mut self, (x0, y0): (i64, i64), (x1, y1): (i64, i64), color: [u8; 3]) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let (mut x, mut y) = (x0, y0);
+        let mut error = dx + dy;
+        loop {
+            self.set_pixel(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let error2 = 2 * error;
+            if error2 >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if error2 <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+}
+
+/// Returns the color of a prediction mode.
+pub fn mode_color(mode: PredictionMode) -> [u8; 3] {
+    MODE_COLORS[usize::from(u8::from(mode))]
+}
+
+/// Returns the color of a segment id.
+pub fn segment_color(segment_id: u8) -> [u8; 3] {
+    SEGMENT_COLORS[usize::from(segment_id) % MAX_SEGMENTS]
+}
+
+/// Renders the layers of the mode info of a frame in the given order.
+///
+/// The image has the size of the frame. The background is the luma plane of the decoded
+/// picture if given, otherwise mid-grey. Returns an error if the tile data of the frame
+/// wasn't decoded.
+pub fn render(frame: &Frame, background: Option<&Plane>, layers: &[Layer]) -> Result<Image> {
+    let mode_info = frame.mode_info().ok_or_else(|| frame.missing_tile_data())?;
+    let mut image = match background {
+        Some(plane) => Image::from_plane(plane),
+        None => Image::new(
+            usize::from(frame.width()),
+            usize::from(frame.height()),
+            [128; 3],
+        ),
+    };
+
+    for layer in layers {
+        match layer {
+            Layer::MotionVectors => draw_motion_vectors(&mut image, mode_info),
+            layer => {
+                for block in mode_info.blocks() {
+                    let x = usize::from(block.mi_col()) * 8;
+                    let y = usize::from(block.mi_row()) * 8;
+                    let width = usize::from(block.block_size().width());
+                    let height = usize::from(block.block_size().height());
+                    match layer {
+                        Layer::Partition => image.border(x, y, width, height, BORDER_COLOR),
+                        Layer::PredictionMode => {
+                            image.tint(x, y, width, height, mode_color(block.y_mode()))
+                        }
+                        Layer::SegmentId => {
+                            image.tint(x, y, width, height, segment_color(block.segment_id()))
+                        }
+                        Layer::Skip if block.skip() => image.tint(x, y, width, height, SKIP_COLOR),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+/// Draws the motion vectors of all blocks and sub-blocks from their center.
+fn draw_motion_vectors(image: &mut Image, mode_info: &ModeInfoGrid) {
+    for mv in mode_info.motion_vectors() {
+        let color = match mv.ref_frame() {
+            LAST_FRAME => MV_COLORS[0],
+            GOLDEN_FRAME => MV_COLORS[1],
+            ALTREF_FRAME => MV_COLORS[2],
+            _ => continue,
+        };
+        let x = i64::from(mv.x()) + i64::from(mv.width() / 2);
+        let y = i64::from(mv.y()) + i64::from(mv.height() / 2);
+        // Motion vectors are in 1/8 pixel.
+        let end = (
+            x + i64::from(mv.mv().col()) / 8,
+            y + i64::from(mv.mv().row()) / 8,
+        );
+        image.line((x, y), end, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use std::fs::File;
+
+    use super::*;
+    use crate::{ivf::Ivf, BlockSize, ColorDepth, Vp9Parser};
+
+    #[test]
+    fn draw_primitives() {
+        let mut image = Image::new(4, 3, [10, 20, 30]);
+        image.tint(1, 1, 8, 8, [30, 40, 50]);
+        assert_eq!(image.pixel(0, 0), Some([10, 20, 30]));
+        assert_eq!(image.pixel(3, 2), Some([20, 30, 40]));
+        assert_eq!(image.pixel(4, 0), None);
+
+        image.line((0, 0), (3, 2), [255, 0, 0]);
+        assert_eq!(image.pixel(0, 0), Some([255, 0, 0]));
+        assert_eq!(image.pixel(3, 2), Some([255, 0, 0]));
+
+        let mut ppm = Vec::new();
+        image.write_ppm(&mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n4 3\n255\n"));
+        assert_eq!(ppm.len(), b"P6\n4 3\n255\n".len() + 4 * 3 * 3);
+
+        let mut plane = Plane::new(2, 1, false, false, ColorDepth::Depth10);
+        plane.data_mut()[1] = 1020;
+        let image = Image::from_plane(&plane);
+        assert_eq!(image.data(), &[0, 0, 0, 255, 255, 255]);
+    }
+
+    #[test]
+    fn render_layers() {
+        let file = File::open("tests/data/320-24-crf.ivf").unwrap();
+        let mut ivf = Ivf::new(file).unwrap();
+        let mut parser = Vp9Parser::default();
+        parser.set_decode_tiles(true);
+        let frames = parser
+            .parse_packet(ivf.read_frame().unwrap().unwrap().packet)
+            .unwrap();
+        let frame = &frames[0];
+
+        let image = render(frame, None, &[Layer::Partition]).unwrap();
+        assert_eq!((image.width(), image.height()), (320, 180));
+        assert_eq!(image.pixel(0, 0), Some(BORDER_COLOR));
+        assert_eq!(image.pixel(1, 1), Some([128; 3]));
+
+        let image = render(frame, None, &[Layer::PredictionMode, Layer::SegmentId]).unwrap();
+        let mode = frame.mode_info().unwrap().get(0, 0).unwrap().y_mode();
+        let mut expected = [0; 3];
+        for ((expected, mode), segment) in expected
+            .iter_mut()
+            .zip(mode_color(mode))
+            .zip(segment_color(0))
+        {
+            *expected =
+                u8::try_from(((128 + u16::from(mode)) / 2 + u16::from(segment)) / 2).unwrap();
+        }
+        assert_eq!(image.pixel(0, 0), Some(expected));
+
+        // Key frames have no motion vectors.
+        let image = render(frame, None, &[Layer::MotionVectors]).unwrap();
+        assert!(image.data().iter().all(|value| *value == 128));
+
+        // Inter frames draw a line from the center of every inter block.
+        let frame = std::iter::from_fn(|| ivf.read_frame().unwrap())
+            .flat_map(|ivf_frame| parser.parse_packet(ivf_frame.packet).unwrap())
+            .find(|frame| {
+                frame
+                    .mode_info()
+                    .is_some_and(|mode_info| mode_info.blocks().any(|block| block.is_inter()))
+            })
+            .unwrap();
+        let image = render(&frame, None, &[Layer::MotionVectors]).unwrap();
+        let block = frame
+            .mode_info()
+            .unwrap()
+            .blocks()
+            .find(|block| block.is_inter() && block.block_size() >= BlockSize::Block8x8)
+            .unwrap();
+        let x = usize::from(block.mi_col()) * 8 + usize::from(block.block_size().width()) / 2;
+        let y = usize::from(block.mi_row()) * 8 + usize::from(block.block_size().height()) / 2;
+        assert!(MV_COLORS.contains(&image.pixel(x, y).unwrap()));
+    }
+}
@@ -0,0 +1,478 @@
+//! Decoder that reconstructs the pictures of VP9 frames.
+
+use std::sync::Arc;
+
+use crate::{
+    inter_prediction, intra_prediction, loop_filter,
+    md5::{self, Md5Digest},
+    scale, transform, Frame, ModeInfo, ModeInfoGrid, OutputSize, Plane, Result, TransformBlock,
+    Vp9Parser, Vp9ParserError, REF_SCALE_SHIFT,
+};
+
+/// The number of reference frame slots.
+pub(crate) const NUM_REF_FRAMES: usize = 8;
+
+/// Size of a super block in mode info units.
+const MI_BLOCK_SIZE: u16 = 8;
+
+/// Size of a super block in luma samples.
+const SUPERBLOCK_SIZE: usize = 64;
+
+/// The number of luma rows above a super block row that are modified by the loop filter of
+/// the row. Covers the 7 rows of the widest filter in chroma planes with vertical subsampling.
+const LOOP_FILTER_ROWS: usize = 16;
+
+/// The number of luma rows below a predicted block that are read by the interpolation
+/// filters, including the rounding of chroma motion vectors.
+const INTERPOLATION_ROWS: i64 = 16;
+
+/// A decoded picture with its Y, U and V planes.
+#[derive(Clone, Debug)]
+pub struct Picture {
+    frame: Frame,
+    planes: Arc<[Plane; 3]>,
+    output_size: OutputSize,
+}
+
+impl Picture {
+    /// The header of the frame that produced the picture. For a shown existing frame this is
+    /// the header signaling `show_existing_frame`.
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    /// The Y, U and V planes. The planes hold the samples, the strides and the visible sizes.
+    pub fn planes(&self) -> &[Plane; 3] {
+        &self.planes
+    }
+
+    /// The output size that was applied to the planes. Pictures are only scaled if the render
+    /// size of the frame differs from its coded size, otherwise they are reported as cropped.
+    pub fn output_size(&self) -> OutputSize {
+        self.output_size
+    }
+
+    /// The number of bits per sample.
+    pub fn bit_depth(&self) -> u8 {
+        self.planes[0].bit_depth()
+    }
+
+    /// The visible width of the luma plane.
+    pub fn width(&self) -> usize {
+        self.planes[0].width()
+    }
+
+    /// The visible height of the luma plane.
+    pub fn height(&self) -> usize {
+        self.planes[0].height()
+    }
+
+    /// The MD5 checksum of the visible samples, as calculated by `vpxdec --md5 --i420` and
+    /// listed in the `.md5` files of the libvpx test vectors.
+    pub fn md5(&self) -> Md5Digest {
+        md5::planes_md5(&self.planes[..])
+    }
+}
+
+impl Picture {
+    /// Creates the picture of a decoded frame in the requested output size.
+    pub(crate) fn new(
+        frame: Frame,
+        planes: Arc<[Plane; 3]>,
+        output_size: OutputSize,
+    ) -> Result<Self> {
+        let (planes, output_size) = scale::output_planes(&frame, planes, output_size)?;
+        Ok(Self {
+            frame,
+            planes,
+            output_size,
+        })
+    }
+}
+
+/// Decodes VP9 packets into pictures.
+///
+/// The decoder uses a `Vp9Parser` for the headers and the tile data, which also loads and
+/// saves the frame contexts. The decoder reconstructs the frames, keeps the pictures of the
+/// eight reference frame slots and returns the pictures that are to be shown.
+#[derive(Clone, Debug)]
+pub struct Vp9Decoder {
+    parser: Vp9Parser,
+    ref_pictures: [Option<Arc<[Plane; 3]>>; NUM_REF_FRAMES],
+    output_size: OutputSize,
+}
+
+impl Default for Vp9Decoder {
+    fn default() -> Self {
+        let mut parser = Vp9Parser::default();
+        parser.set_decode_tiles(true);
+        Self {
+            parser,
+            ref_pictures: Default::default(),
+            output_size: OutputSize::default(),
+        }
+    }
+}
+
+impl Vp9Decoder {
+    /// Creates a new decoder.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Resets the state of the decoder. Used when switching the bitstream or seeking.
+    ///
+    /// The number of threads, the error concealment and the output size are kept.
+    pub fn reset(&mut self) {
+        self.parser.reset();
+        self.ref_pictures = Default::default();
+    }
+
+    /// The number of threads used to decode the tile data.
+    pub fn threads(&self) -> usize {
+        self.parser.threads()
+    }
+
+    /// Sets the number of threads used to decode the tile data. Defaults to 1.
+    ///
+    /// Frames with more than one tile column decode the columns concurrently. Streams with a
+    /// width of 1080p and above usually use two to four tile columns. The decoded pictures
+    /// don't depend on the number of threads. A value of 0 is treated as 1.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.parser.set_threads(threads);
+    }
+
+    /// Indicates if superblocks that fail to decode are concealed.
+    pub fn error_concealment(&self) -> bool {
+        self.parser.error_concealment()
+    }
+
+    /// Enables the concealment of corrupted or truncated tile data. Disabled by default.
+    ///
+    /// Superblocks that fail to decode are copied from the co-located area of the LAST
+    /// reference frame, or filled with mid-grey in intra frames, instead of failing the frame.
+    /// `Picture::frame()` reports them with `Frame::concealed_region()`.
+    pub fn set_error_concealment(&mut self, error_concealment: bool) {
+        self.parser.set_error_concealment(error_concealment);
+    }
+
+    /// The size of the returned pictures.
+    pub fn output_size(&self) -> OutputSize {
+        self.output_size
+    }
+
+    /// Sets the size of the returned pictures. Defaults to `OutputSize::Decoded`.
+    ///
+    /// Pictures can be cropped to the coded size of the frame or be scaled to its render size,
+    /// which is the intended display size. The reference frames keep the decoded planes.
+    pub fn set_output_size(&mut self, output_size: OutputSize) {
+        self.output_size = output_size;
+    }
+
+    /// The parser used to parse the frames.
+    pub fn parser(&self) -> &Vp9Parser {
+        &self.parser
+    }
+
+    /// The pictures held by the eight reference frame slots.
+    pub fn ref_pictures(&self) -> &[Option<Arc<[Plane; 3]>>; NUM_REF_FRAMES] {
+        &self.ref_pictures
+    }
+
+    /// Decodes a VP9 bitstream packet and returns the pictures that are to be shown.
+    ///
+    /// Packets needs to be supplied in the order they are appearing in the bitstream. Super
+    /// frames can return more than one picture, while hidden frames only update the reference
+    /// frame slots. If a frame can't be reconstructed, the slots it would refresh are emptied,
+    /// so that following frames referencing them return an error too.
+    pub fn decode_packet(&mut self, packet: Vec<u8>) -> Result<Vec<Picture>> {
+        let frames = self.parser.parse_packet(packet)?;
+        let mut pictures = Vec::with_capacity(frames.len());
+        for frame in frames {
+            if let Some(picture) = self.decode_frame(frame)? {
+                pictures.push(picture);
+            }
+        }
+        Ok(pictures)
+    }
+
+    fn decode_frame(&mut self, frame: Frame) -> Result<Option<Picture>> {
+        if frame.show_existing_frame() {
+            let index = frame
+                .frame_to_show_map_idx()
+                .ok_or(Vp9ParserError::InvalidRefFrameIndex)?;
+            let planes = self.ref_pictures[usize::from(index)]
+                .clone()
+                .ok_or(Vp9ParserError::MissingRefPicture(index))?;
+            return Picture::new(frame, planes, self.output_size).map(Some);
+        }
+
+        let planes = match self.reconstruct(&frame) {
+            Ok(planes) => Arc::new(planes),
+            Err(err) => {
+                self.refresh_ref_pictures(frame.refresh_frame_flags(), None);
+                return Err(err);
+            }
+        };
+        self.refresh_ref_pictures(frame.refresh_frame_flags(), Some(planes.clone()));
+
+        if !frame.show_frame() {
+            return Ok(None);
+        }
+        Picture::new(frame, planes, self.output_size).map(Some)
+    }
+
+    // Implements spec "8.4 Decoding process" after the tile data was decoded: the prediction
+    // and reconstruction of all blocks, followed by the loop filter.
+    fn reconstruct(&self, frame: &Frame) -> Result<[Plane; 3]> {
+        let rows = SuperblockRows::new(frame)?;
+        let mut planes = [0, 1, 2].map(|plane| Plane::from_frame(frame, plane));
+        let references = if frame.is_intra() {
+            None
+        } else {
+            Some(self.references(frame)?)
+        };
+        let references = references.as_ref().map_or([&[][..]; 3], |planes| {
+            planes.each_ref().map(|planes| &planes[..])
+        });
+
+        for sb_row in 0..rows.len() {
+            rows.reconstruct_row(&mut planes, sb_row, &references)?;
+            if let Some(above) = sb_row.checked_sub(1) {
+                rows.filter_row(&mut planes, above)?;
+            }
+        }
+        if let Some(last) = rows.len().checked_sub(1) {
+            rows.filter_row(&mut planes, last)?;
+        }
+
+        Ok(planes)
+    }
+
+    /// Returns the pictures of the LAST, GOLDEN and ALTREF frame.
+    fn references(&self, frame: &Frame) -> Result<[Arc<[Plane; 3]>; 3]> {
+        let reference = |index: u8| {
+            self.ref_pictures
+                .get(usize::from(index))
+                .cloned()
+                .flatten()
+                .ok_or(Vp9ParserError::MissingRefPicture(index))
+        };
+        let [last, golden, altref] = *frame.ref_frame_indices();
+        Ok([reference(last)?, reference(golden)?, reference(altref)?])
+    }
+
+    // Implements spec "8.10 Reference frame update process" for the pictures.
+    fn refresh_ref_pictures(&mut self, flags: u8, planes: Option<Arc<[Plane; 3]>>) {
+        for (i, slot) in self.ref_pictures.iter_mut().enumerate() {
+            if (flags >> i) & 1 == 1 {
+                *slot = planes.clone();
+            }
+        }
+    }
+}
+
+/// The blocks of a frame grouped by super block rows.
+///
+/// Reconstructing the rows in order and filtering each row after the row below it was
+/// reconstructed gives the same result as reconstructing the whole frame before filtering
+/// it, since intra prediction only reads the unfiltered bottom row of the row above. After a
+/// row is filtered, all rows above it are final and can be referenced by other frames.
+#[derive(Debug)]
+pub(crate) struct SuperblockRows<'a> {
+    frame: &'a Frame,
+    mode_info: &'a ModeInfoGrid,
+    rows: Vec<SuperblockRow<'a>>,
+}
+
+/// The inter blocks and the transform blocks of a super block row in decode order.
+#[derive(Debug, Default)]
+struct SuperblockRow<'a> {
+    /// The concealed super blocks of intra frames, which are filled with mid-grey.
+    concealed_blocks: Vec<&'a ModeInfo>,
+    inter_blocks: Vec<&'a ModeInfo>,
+    transform_blocks: Vec<&'a TransformBlock>,
+}
+
+impl<'a> SuperblockRows<'a> {
+    pub(crate) fn new(frame: &'a Frame) -> Result<Self> {
+        let mode_info = frame.mode_info().ok_or_else(|| frame.missing_tile_data())?;
+        let transform_blocks = frame
+            .transform_blocks()
+            .ok_or_else(|| frame.missing_tile_data())?;
+
+        let mut rows: Vec<SuperblockRow> = (0..frame.mi_rows().div_ceil(MI_BLOCK_SIZE))
+            .map(|_| SuperblockRow::default())
+            .collect();
+        let outside = |mi_row: u16, mi_col: u16| {
+            Vp9ParserError::BlockOutsidePlane(usize::from(mi_col), usize::from(mi_row))
+        };
+        for block in mode_info.blocks().filter(|block| block.is_inter) {
+            rows.get_mut(usize::from(block.mi_row / MI_BLOCK_SIZE))
+                .ok_or(outside(block.mi_row, block.mi_col))?
+                .inter_blocks
+                .push(block);
+        }
+        for transform_block in transform_blocks {
+            let (mi_row, mi_col) = (transform_block.mi_row(), transform_block.mi_col());
+            rows.get_mut(usize::from(mi_row / MI_BLOCK_SIZE))
+                .ok_or(outside(mi_row, mi_col))?
+                .transform_blocks
+                .push(transform_block);
+        }
+        for &(mi_row, mi_col) in frame
+            .concealed_region()
+            .map_or(&[][..], |region| region.superblocks())
+        {
+            let block = mode_info
+                .get(mi_row, mi_col)
+                .ok_or(outside(mi_row, mi_col))?;
+            if !block.is_inter {
+                rows.get_mut(usize::from(mi_row / MI_BLOCK_SIZE))
+                    .ok_or(outside(mi_row, mi_col))?
+                    .concealed_blocks
+                    .push(block);
+            }
+        }
+
+        Ok(Self {
+            frame,
+            mode_info,
+            rows,
+        })
+    }
+
+    /// The number of super block rows.
+    pub(crate) fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Predicts and reconstructs the blocks of a super block row.
+    ///
+    /// The references hold the planes of the LAST, GOLDEN and ALTREF frame and only need to
+    /// provide the rows returned by `reference_rows()`.
+    pub(crate) fn reconstruct_row(
+        &self,
+        planes: &mut [Plane; 3],
+        sb_row: usize,
+        references: &[&[Plane]; 3],
+    ) -> Result<()> {
+        let row = self
+            .rows
+            .get(sb_row)
+            .ok_or(Vp9ParserError::MissingTileData)?;
+
+        // Concealed super blocks of intra frames have no reference to copy from.
+        for block in row.concealed_blocks.iter() {
+            for (index, plane) in planes.iter_mut().enumerate() {
+                let (ss_x, ss_y) = if index > 0 {
+                    (self.frame.subsampling_x(), self.frame.subsampling_y())
+                } else {
+                    (false, false)
+                };
+                let x = (usize::from(block.mi_col) * 8) >> u8::from(ss_x);
+                let y = (usize::from(block.mi_row) * 8) >> u8::from(ss_y);
+                let width =
+                    (SUPERBLOCK_SIZE >> u8::from(ss_x)).min(plane.stride().saturating_sub(x));
+                let height =
+                    (SUPERBLOCK_SIZE >> u8::from(ss_y)).min(plane.rows().saturating_sub(y));
+                let mid_grey = 1 << (plane.bit_depth() - 1);
+                let stride = plane.stride();
+                for row in plane
+                    .data_mut()
+                    .chunks_exact_mut(stride)
+                    .skip(y)
+                    .take(height)
+                {
+                    row[x..x + width].fill(mid_grey);
+                }
+            }
+        }
+
+        // Inter blocks are predicted at once for all planes, intra blocks per transform block,
+        // since their edges depend on the reconstruction of the previous transform blocks.
+        for block in row.inter_blocks.iter() {
+            inter_prediction::predict_inter(planes, self.frame, block, references)?;
+        }
+
+        for transform_block in row.transform_blocks.iter() {
+            let block = self
+                .mode_info
+                .get(transform_block.mi_row(), transform_block.mi_col())
+                .ok_or(Vp9ParserError::BlockOutsidePlane(
+                    usize::from(transform_block.mi_col()),
+                    usize::from(transform_block.mi_row()),
+                ))?;
+            let plane = planes
+                .get_mut(transform_block.plane())
+                .ok_or(Vp9ParserError::MissingTileData)?;
+            if !block.is_inter {
+                intra_prediction::predict_transform_block(
+                    plane,
+                    self.frame,
+                    block,
+                    transform_block,
+                )?;
+            }
+            transform::reconstruct(plane, transform_block, self.frame.lossless())?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies the loop filter to a super block row.
+    pub(crate) fn filter_row(&self, planes: &mut [Plane; 3], sb_row: usize) -> Result<()> {
+        let mi_row = u16::try_from(sb_row)? * MI_BLOCK_SIZE;
+        loop_filter::loop_filter_rows(
+            planes,
+            self.frame,
+            self.mode_info,
+            mi_row..mi_row.saturating_add(MI_BLOCK_SIZE),
+        )
+    }
+
+    /// The number of luma rows that are final after the given super block row was filtered.
+    /// Chroma planes with vertical subsampling have half as many final rows.
+    pub(crate) fn final_rows(sb_row: usize) -> usize {
+        ((sb_row + 1) * SUPERBLOCK_SIZE).saturating_sub(LOOP_FILTER_ROWS)
+    }
+
+    /// The number of luma rows of the LAST, GOLDEN and ALTREF frame that are read by the
+    /// inter prediction of a super block row. Zero if a reference frame is not used.
+    pub(crate) fn reference_rows(&self, sb_row: usize) -> [usize; 3] {
+        let mut reference_rows = [0; 3];
+        let Some(row) = self.rows.get(sb_row) else {
+            return reference_rows;
+        };
+        let Some(scales) = self.frame.ref_scales() else {
+            return reference_rows;
+        };
+
+        for block in row.inter_blocks.iter() {
+            let bottom = (i64::from(block.mi_row) + i64::from(block.block_size.num_8x8_high())) * 8;
+            for (ref_list, ref_frame) in block.ref_frame.iter().enumerate() {
+                let Some((index, scale)) = ref_frame
+                    .checked_sub(1)
+                    .and_then(|index| Some((index, scales.get(index)?)))
+                else {
+                    continue;
+                };
+                // Motion vectors are in 1/8 luma samples, positive rows point down.
+                let mv_row = block
+                    .sub_mvs
+                    .iter()
+                    .map(|mvs| i64::from(mvs[ref_list].row))
+                    .max()
+                    .unwrap_or(0)
+                    .max(0);
+                let rows = bottom + ((mv_row + 7) >> 3) + INTERPOLATION_ROWS;
+                let rows =
+                    ((rows * i64::from(scale.y_scale())) >> REF_SCALE_SHIFT) + INTERPOLATION_ROWS;
+                let rows = usize::try_from(rows).unwrap_or(usize::MAX);
+                reference_rows[index] = reference_rows[index].max(rows);
+            }
+        }
+
+        reference_rows
+    }
+}
@@ -1,6 +1,8 @@
 //! VP9 parser errors.
 
-use std::error::Error;
+use std::{error::Error, sync::Arc};
+
+use crate::{BlockSize, InterpolationFilter, PredictionMode, TxSize};
 
 /// Errors that can occur when parsing VP9 frames.
 #[derive(Debug)]
@@ -25,6 +27,54 @@ pub enum Vp9ParserError {
     InvalidMetadata,
     /// Invalid frame_size byte size.
     InvalidFrameSizeByteSize(usize),
+    /// Invalid size of the compressed header.
+    InvalidCompressedHeaderSize(usize),
+    /// The data of the boolean decoder is too short.
+    BoolDecoderDataTooShort,
+    /// Invalid marker bit of the boolean decoder.
+    InvalidBoolMarker,
+    /// Invalid padding of the boolean decoder.
+    InvalidBoolPadding,
+    /// The boolean decoder read past the end of its data.
+    BoolDecoderOverrun,
+    /// The tile data is too short to hold the tile size marker.
+    TileDataTooShort,
+    /// The tile size is larger than the remaining tile data.
+    InvalidTileSize(usize),
+    /// The reference frame slot was never filled by a frame.
+    EmptyRefSlot(u8),
+    /// The color depth of the reference frame slot differs from the color depth of the frame.
+    RefSlotColorDepthMismatch(u8),
+    /// The subsampling of the reference frame slot differs from the subsampling of the frame.
+    RefSlotSubsamplingMismatch(u8),
+    /// The size of the reference frame in the slot exceeds the scaling limits.
+    InvalidRefFrameScale(u8),
+    /// The block size is invalid for the chroma subsampling of the frame.
+    InvalidBlockSize(BlockSize),
+    /// A decoded motion vector is outside of the valid range.
+    InvalidMotionVector,
+    /// The number of coefficients doesn't match the size of the transform.
+    InvalidCoefficientCount(usize),
+    /// The transform size is not supported by the transform.
+    InvalidTransformSize(TxSize),
+    /// The prediction mode is not an intra prediction mode.
+    InvalidIntraMode(PredictionMode),
+    /// The block at the given position is outside of the plane.
+    BlockOutsidePlane(usize, usize),
+    /// The reference frame can't be used for the inter prediction.
+    InvalidRefFrame(usize),
+    /// The interpolation filter can't be used for the inter prediction.
+    InvalidInterpolationFilter(InterpolationFilter),
+    /// The tile data of the frame could not be decoded.
+    MissingTileData,
+    /// The decoding of the tile data of the frame failed with the contained error.
+    TileDataError(Arc<Vp9ParserError>),
+    /// The reference frame slot holds no decoded picture.
+    MissingRefPicture(u8),
+    /// A worker thread of the decoder panicked.
+    WorkerThreadPanicked,
+    /// The size, subsampling or bit depth of the picture differs from the Y4M stream header.
+    Y4mFormatChanged,
 }
 
 impl std::fmt::Display for Vp9ParserError {
@@ -60,6 +110,106 @@ impl std::fmt::Display for Vp9ParserError {
             Vp9ParserError::InvalidFrameSizeByteSize(size) => {
                 write!(f, "invalid frame_size byte size: {}", size)
             }
+            Vp9ParserError::InvalidCompressedHeaderSize(size) => {
+                write!(f, "invalid compressed header size: {}", size)
+            }
+            Vp9ParserError::BoolDecoderDataTooShort => {
+                write!(f, "data of the boolean decoder is too short")
+            }
+            Vp9ParserError::InvalidBoolMarker => {
+                write!(f, "invalid marker bit of the boolean decoder")
+            }
+            Vp9ParserError::InvalidBoolPadding => {
+                write!(f, "invalid padding of the boolean decoder")
+            }
+            Vp9ParserError::BoolDecoderOverrun => {
+                write!(f, "boolean decoder read past the end of its data")
+            }
+            Vp9ParserError::TileDataTooShort => {
+                write!(f, "tile data is too short to hold the tile size marker")
+            }
+            Vp9ParserError::InvalidTileSize(size) => {
+                write!(
+                    f,
+                    "tile size is larger than the remaining tile data: {}",
+                    size
+                )
+            }
+            Vp9ParserError::EmptyRefSlot(index) => {
+                write!(f, "reference frame slot {} is empty", index)
+            }
+            Vp9ParserError::RefSlotColorDepthMismatch(index) => {
+                write!(f, "color depth of reference frame slot {} differs", index)
+            }
+            Vp9ParserError::RefSlotSubsamplingMismatch(index) => {
+                write!(f, "subsampling of reference frame slot {} differs", index)
+            }
+            Vp9ParserError::InvalidRefFrameScale(index) => {
+                write!(
+                    f,
+                    "size of reference frame slot {} exceeds the scaling limits",
+                    index
+                )
+            }
+            Vp9ParserError::InvalidBlockSize(block_size) => {
+                write!(
+                    f,
+                    "block size {:?} is invalid for the chroma subsampling",
+                    block_size
+                )
+            }
+            Vp9ParserError::InvalidMotionVector => {
+                write!(f, "motion vector is outside of the valid range")
+            }
+            Vp9ParserError::InvalidCoefficientCount(count) => {
+                write!(
+                    f,
+                    "coefficient count {} doesn't match the transform size",
+                    count
+                )
+            }
+            Vp9ParserError::InvalidTransformSize(tx_size) => {
+                write!(
+                    f,
+                    "transform size {:?} is not supported by the transform",
+                    tx_size
+                )
+            }
+            Vp9ParserError::InvalidIntraMode(mode) => {
+                write!(f, "prediction mode {:?} is not an intra mode", mode)
+            }
+            Vp9ParserError::BlockOutsidePlane(x, y) => {
+                write!(f, "block at {}x{} is outside of the plane", x, y)
+            }
+            Vp9ParserError::InvalidRefFrame(ref_frame) => {
+                write!(
+                    f,
+                    "reference frame {} can't be used for the inter prediction",
+                    ref_frame
+                )
+            }
+            Vp9ParserError::InvalidInterpolationFilter(filter) => {
+                write!(
+                    f,
+                    "interpolation filter {:?} can't be used for the inter prediction",
+                    filter
+                )
+            }
+            Vp9ParserError::MissingTileData => {
+                write!(f, "tile data of the frame could not be decoded")
+            }
+            Vp9ParserError::TileDataError(err) => {
+                write!(f, "tile data of the frame failed to decode: {}", err)
+            }
+            Vp9ParserError::MissingRefPicture(index) => {
+                write!(f, "reference frame slot {} holds no decoded picture", index)
+            }
+            Vp9ParserError::WorkerThreadPanicked => {
+                write!(f, "worker thread of the decoder panicked")
+            }
+            Vp9ParserError::Y4mFormatChanged => {
+                write!(f, "picture format differs from the y4m stream header")
+            }
         }
     }
 }
@@ -95,6 +245,7 @@ impl std::error::Error for Vp9ParserError {
             Vp9ParserError::TryFromSliceError(ref e) => Some(e),
             Vp9ParserError::TryFromIntError(ref e) => Some(e),
             Vp9ParserError::BitReaderError(ref e) => Some(e),
+            Vp9ParserError::TileDataError(ref e) => Some(e.as_ref()),
             _ => None,
         }
     }
@@ -0,0 +1,969 @@
+//! Probability tables used for the entropy decoding.
+
+use crate::{
+    compressed_header::{
+        BLOCK_SIZE_GROUPS, BLOCK_TYPES, CLASS0_SIZE, COEF_BANDS, COMP_MODE_CONTEXTS,
+        INTERP_FILTER_CONTEXTS, INTER_MODES, INTER_MODE_CONTEXTS, INTRA_MODES, IS_INTER_CONTEXTS,
+        MV_CLASSES, MV_FR_SIZE, MV_JOINTS, MV_OFFSET_BITS, PARTITION_CONTEXTS, PARTITION_TYPES,
+        PREV_COEF_CONTEXTS, REF_CONTEXTS, REF_TYPES, SKIP_CONTEXTS, SWITCHABLE_FILTERS, TX_SIZES,
+        TX_SIZE_CONTEXTS, UNCONSTRAINED_NODES,
+    },
+    default_probs::*,
+    tile_decoder::{
+        INTERP_FILTER_TREE, INTER_MODE_TREE, INTRA_MODE_TREE, MV_CLASS_TREE, MV_FR_TREE,
+        MV_JOINT_TREE, PARTITION_TREE,
+    },
+    CompressedHeader, Frame, FrameType, InterpolationFilter, TxMode,
+};
+
+/// Number of frame contexts that can be saved.
+pub(crate) const FRAME_CONTEXTS: usize = 4;
+
+/// Saturation of the counts for the adaptation of the coefficient probabilities.
+const COEF_COUNT_SAT: u32 = 24;
+
+/// Maximum update factor of the coefficient probabilities.
+const COEF_MAX_UPDATE_FACTOR: u32 = 112;
+
+/// Maximum update factor of the coefficient probabilities in the frame after a key frame.
+const COEF_MAX_UPDATE_FACTOR_AFTER_KEY: u32 = 128;
+
+/// Saturation of the counts for the adaptation of the mode and motion vector probabilities.
+const MODE_MV_COUNT_SAT: u32 = 20;
+
+/// Maximum update factor of the mode and motion vector probabilities.
+const MODE_MV_MAX_UPDATE_FACTOR: u32 = 128;
+
+/// Coefficient probabilities indexed by transform size, plane type, reference type,
+/// band, context and node.
+pub type CoefProbs = [[[[[[u8; UNCONSTRAINED_NODES]; PREV_COEF_CONTEXTS]; COEF_BANDS]; REF_TYPES];
+    BLOCK_TYPES]; TX_SIZES];
+
+/// Maps the decoded `deltaProb` to the value used by `inv_recenter_nonneg()`.
+const INV_MAP_TABLE: [u8; 255] = [
+    7, 20, 33, 46, 59, 72, 85, 98, 111, 124, 137, 150, 163, 176, 189, 202, 215, 228, 241, 254, 1,
+    2, 3, 4, 5, 6, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 21, 22, 23, 24, 25, 26, 27, 28,
+    29, 30, 31, 32, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 47, 48, 49, 50, 51, 52, 53, 54,
+    55, 56, 57, 58, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 73, 74, 75, 76, 77, 78, 79, 80,
+    81, 82, 83, 84, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95, 96, 97, 99, 100, 101, 102, 103, 104,
+    105, 106, 107, 108, 109, 110, 112, 113, 114, 115, 116, 117, 118, 119, 120, 121, 122, 123, 125,
+    126, 127, 128, 129, 130, 131, 132, 133, 134, 135, 136, 138, 139, 140, 141, 142, 143, 144, 145,
+    146, 147, 148, 149, 151, 152, 153, 154, 155, 156, 157, 158, 159, 160, 161, 162, 164, 165, 166,
+    167, 168, 169, 170, 171, 172, 173, 174, 175, 177, 178, 179, 180, 181, 182, 183, 184, 185, 186,
+    187, 188, 190, 191, 192, 193, 194, 195, 196, 197, 198, 199, 200, 201, 203, 204, 205, 206, 207,
+    208, 209, 210, 211, 212, 213, 214, 216, 217, 218, 219, 220, 221, 222, 223, 224, 225, 226, 227,
+    229, 230, 231, 232, 233, 234, 235, 236, 237, 238, 239, 240, 242, 243, 244, 245, 246, 247, 248,
+    249, 250, 251, 252, 253, 253,
+];
+
+/// A frame context holds the probabilities that are used for the entropy decoding of a frame.
+///
+/// The decoder keeps four saved frame contexts. Every frame loads one of them,
+/// applies the updates of its compressed header and can save the result back.
+#[derive(Clone, Debug)]
+pub struct FrameContext {
+    tx_probs_8x8: [[u8; TX_SIZES - 3]; TX_SIZE_CONTEXTS],
+    tx_probs_16x16: [[u8; TX_SIZES - 2]; TX_SIZE_CONTEXTS],
+    tx_probs_32x32: [[u8; TX_SIZES - 1]; TX_SIZE_CONTEXTS],
+    coef_probs: CoefProbs,
+    skip_prob: [u8; SKIP_CONTEXTS],
+    inter_mode_probs: [[u8; INTER_MODES - 1]; INTER_MODE_CONTEXTS],
+    interp_filter_probs: [[u8; SWITCHABLE_FILTERS - 1]; INTERP_FILTER_CONTEXTS],
+    is_inter_prob: [u8; IS_INTER_CONTEXTS],
+    comp_mode_prob: [u8; COMP_MODE_CONTEXTS],
+    single_ref_prob: [[u8; 2]; REF_CONTEXTS],
+    comp_ref_prob: [u8; REF_CONTEXTS],
+    y_mode_probs: [[u8; INTRA_MODES - 1]; BLOCK_SIZE_GROUPS],
+    uv_mode_probs: [[u8; INTRA_MODES - 1]; INTRA_MODES],
+    partition_probs: [[u8; PARTITION_TYPES - 1]; PARTITION_CONTEXTS],
+    mv_joint_probs: [u8; MV_JOINTS - 1],
+    mv_sign_prob: [u8; 2],
+    mv_class_probs: [[u8; MV_CLASSES - 1]; 2],
+    mv_class0_bit_prob: [u8; 2],
+    mv_bits_prob: [[u8; MV_OFFSET_BITS]; 2],
+    mv_class0_fr_probs: [[[u8; MV_FR_SIZE - 1]; CLASS0_SIZE]; 2],
+    mv_fr_probs: [[u8; MV_FR_SIZE - 1]; 2],
+    mv_class0_hp_prob: [u8; 2],
+    mv_hp_prob: [u8; 2],
+}
+
+/// Counts of the coefficient tokens indexed like `CoefProbs`, followed by the decoded value.
+type CoefCounts<const N: usize> =
+    [[[[[[u32; N]; PREV_COEF_CONTEXTS]; COEF_BANDS]; REF_TYPES]; BLOCK_TYPES]; TX_SIZES];
+
+/// Counts of the symbols decoded in the tiles of a frame.
+///
+/// The counts are used by the backward adaptation of the probabilities at the end of a frame.
+/// Every count array is indexed like the probabilities it adapts, followed by the decoded value.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FrameCounts {
+    /// Counts of the ZERO_TOKEN, ONE_TOKEN and all larger tokens.
+    pub(crate) tokens: CoefCounts<UNCONSTRAINED_NODES>,
+    /// Counts of the checks for the end of block, which are not done after a ZERO_TOKEN.
+    pub(crate) more_coefs: CoefCounts<2>,
+    pub(crate) tx_8x8: [[u32; TX_SIZES - 2]; TX_SIZE_CONTEXTS],
+    pub(crate) tx_16x16: [[u32; TX_SIZES - 1]; TX_SIZE_CONTEXTS],
+    pub(crate) tx_32x32: [[u32; TX_SIZES]; TX_SIZE_CONTEXTS],
+    pub(crate) skip: [[u32; 2]; SKIP_CONTEXTS],
+    pub(crate) inter_mode: [[u32; INTER_MODES]; INTER_MODE_CONTEXTS],
+    pub(crate) interp_filter: [[u32; SWITCHABLE_FILTERS]; INTERP_FILTER_CONTEXTS],
+    pub(crate) is_inter: [[u32; 2]; IS_INTER_CONTEXTS],
+    pub(crate) comp_mode: [[u32; 2]; COMP_MODE_CONTEXTS],
+    pub(crate) single_ref: [[[u32; 2]; 2]; REF_CONTEXTS],
+    pub(crate) comp_ref: [[u32; 2]; REF_CONTEXTS],
+    pub(crate) y_mode: [[u32; INTRA_MODES]; BLOCK_SIZE_GROUPS],
+    pub(crate) uv_mode: [[u32; INTRA_MODES]; INTRA_MODES],
+    pub(crate) partition: [[u32; PARTITION_TYPES]; PARTITION_CONTEXTS],
+    pub(crate) mv_joint: [u32; MV_JOINTS],
+    pub(crate) mv_sign: [[u32; 2]; 2],
+    pub(crate) mv_class: [[u32; MV_CLASSES]; 2],
+    pub(crate) mv_class0_bit: [[u32; CLASS0_SIZE]; 2],
+    pub(crate) mv_bits: [[[u32; 2]; MV_OFFSET_BITS]; 2],
+    pub(crate) mv_class0_fr: [[[u32; MV_FR_SIZE]; CLASS0_SIZE]; 2],
+    pub(crate) mv_fr: [[u32; MV_FR_SIZE]; 2],
+    pub(crate) mv_class0_hp: [[u32; 2]; 2],
+    pub(crate) mv_hp: [[u32; 2]; 2],
+}
+
+impl FrameCounts {
+    /// Adds the counts of another tile column.
+    pub(crate) fn add(&mut self, other: &FrameCounts) {
+        add_counts(
+            self.tokens
+                .iter_mut()
+                .flatten()
+                .flatten()
+                .flatten()
+                .flatten()
+                .flatten(),
+            other
+                .tokens
+                .iter()
+                .flatten()
+                .flatten()
+                .flatten()
+                .flatten()
+                .flatten(),
+        );
+        add_counts(
+            self.more_coefs
+                .iter_mut()
+                .flatten()
+                .flatten()
+                .flatten()
+                .flatten()
+                .flatten(),
+            other
+                .more_coefs
+                .iter()
+                .flatten()
+                .flatten()
+                .flatten()
+                .flatten()
+                .flatten(),
+        );
+        add_counts(
+            self.tx_8x8.iter_mut().flatten(),
+            other.tx_8x8.iter().flatten(),
+        );
+        add_counts(
+            self.tx_16x16.iter_mut().flatten(),
+            other.tx_16x16.iter().flatten(),
+        );
+        add_counts(
+            self.tx_32x32.iter_mut().flatten(),
+            other.tx_32x32.iter().flatten(),
+        );
+        add_counts(self.skip.iter_mut().flatten(), other.skip.iter().flatten());
+        add_counts(
+            self.inter_mode.iter_mut().flatten(),
+            other.inter_mode.iter().flatten(),
+        );
+        add_counts(
+            self.interp_filter.iter_mut().flatten(),
+            other.interp_filter.iter().flatten(),
+        );
+        add_counts(
+            self.is_inter.iter_mut().flatten(),
+            other.is_inter.iter().flatten(),
+        );
+        add_counts(
+            self.comp_mode.iter_mut().flatten(),
+            other.comp_mode.iter().flatten(),
+        );
+        add_counts(
+            self.single_ref.iter_mut().flatten().flatten(),
+            other.single_ref.iter().flatten().flatten(),
+        );
+        add_counts(
+            self.comp_ref.iter_mut().flatten(),
+            other.comp_ref.iter().flatten(),
+        );
+        add_counts(
+            self.y_mode.iter_mut().flatten(),
+            other.y_mode.iter().flatten(),
+        );
+        add_counts(
+            self.uv_mode.iter_mut().flatten(),
+            other.uv_mode.iter().flatten(),
+        );
+        add_counts(
+            self.partition.iter_mut().flatten(),
+            other.partition.iter().flatten(),
+        );
+        add_counts(self.mv_joint.iter_mut(), other.mv_joint.iter());
+        add_counts(
+            self.mv_sign.iter_mut().flatten(),
+            other.mv_sign.iter().flatten(),
+        );
+        add_counts(
+            self.mv_class.iter_mut().flatten(),
+            other.mv_class.iter().flatten(),
+        );
+        add_counts(
+            self.mv_class0_bit.iter_mut().flatten(),
+            other.mv_class0_bit.iter().flatten(),
+        );
+        add_counts(
+            self.mv_bits.iter_mut().flatten().flatten(),
+            other.mv_bits.iter().flatten().flatten(),
+        );
+        add_counts(
+            self.mv_class0_fr.iter_mut().flatten().flatten(),
+            other.mv_class0_fr.iter().flatten().flatten(),
+        );
+        add_counts(
+            self.mv_fr.iter_mut().flatten(),
+            other.mv_fr.iter().flatten(),
+        );
+        add_counts(
+            self.mv_class0_hp.iter_mut().flatten(),
+            other.mv_class0_hp.iter().flatten(),
+        );
+        add_counts(
+            self.mv_hp.iter_mut().flatten(),
+            other.mv_hp.iter().flatten(),
+        );
+    }
+}
+
+impl Default for FrameContext {
+    /// Creates a frame context with the default probabilities (spec `setup_past_independence()`).
+    fn default() -> Self {
+        Self {
+            tx_probs_8x8: DEFAULT_TX_PROBS_8X8,
+            tx_probs_16x16: DEFAULT_TX_PROBS_16X16,
+            tx_probs_32x32: DEFAULT_TX_PROBS_32X32,
+            coef_probs: DEFAULT_COEF_PROBS,
+            skip_prob: DEFAULT_SKIP_PROB,
+            inter_mode_probs: DEFAULT_INTER_MODE_PROBS,
+            interp_filter_probs: DEFAULT_INTERP_FILTER_PROBS,
+            is_inter_prob: DEFAULT_IS_INTER_PROB,
+            comp_mode_prob: DEFAULT_COMP_MODE_PROB,
+            single_ref_prob: DEFAULT_SINGLE_REF_PROB,
+            comp_ref_prob: DEFAULT_COMP_REF_PROB,
+            y_mode_probs: DEFAULT_Y_MODE_PROBS,
+            uv_mode_probs: DEFAULT_UV_MODE_PROBS,
+            partition_probs: DEFAULT_PARTITION_PROBS,
+            mv_joint_probs: DEFAULT_MV_JOINT_PROBS,
+            mv_sign_prob: DEFAULT_MV_SIGN_PROB,
+            mv_class_probs: DEFAULT_MV_CLASS_PROBS,
+            mv_class0_bit_prob: DEFAULT_MV_CLASS0_BIT_PROB,
+            mv_bits_prob: DEFAULT_MV_BITS_PROB,
+            mv_class0_fr_probs: DEFAULT_MV_CLASS0_FR_PROBS,
+            mv_fr_probs: DEFAULT_MV_FR_PROBS,
+            mv_class0_hp_prob: DEFAULT_MV_CLASS0_HP_PROB,
+            mv_hp_prob: DEFAULT_MV_HP_PROB,
+        }
+    }
+}
+
+impl FrameContext {
+    /// Applies the probability updates of the compressed header.
+    pub(crate) fn apply_updates(&mut self, header: &CompressedHeader) {
+        for (probs, updates) in self.tx_probs_8x8.iter_mut().zip(header.tx_probs_8x8()) {
+            apply_diff_updates(probs, updates);
+        }
+        for (probs, updates) in self.tx_probs_16x16.iter_mut().zip(header.tx_probs_16x16()) {
+            apply_diff_updates(probs, updates);
+        }
+        for (probs, updates) in self.tx_probs_32x32.iter_mut().zip(header.tx_probs_32x32()) {
+            apply_diff_updates(probs, updates);
+        }
+        for (probs, updates) in self
+            .coef_probs
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .flatten()
+            .flatten()
+            .zip(
+                header
+                    .coef_probs()
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .flatten()
+                    .flatten(),
+            )
+        {
+            apply_diff_updates(probs, updates);
+        }
+        apply_diff_updates(&mut self.skip_prob, header.skip_prob());
+        for (probs, updates) in self
+            .inter_mode_probs
+            .iter_mut()
+            .zip(header.inter_mode_probs())
+        {
+            apply_diff_updates(probs, updates);
+        }
+        for (probs, updates) in self
+            .interp_filter_probs
+            .iter_mut()
+            .zip(header.interp_filter_probs())
+        {
+            apply_diff_updates(probs, updates);
+        }
+        apply_diff_updates(&mut self.is_inter_prob, header.is_inter_prob());
+        apply_diff_updates(&mut self.comp_mode_prob, header.comp_mode_prob());
+        for (probs, updates) in self
+            .single_ref_prob
+            .iter_mut()
+            .zip(header.single_ref_prob())
+        {
+            apply_diff_updates(probs, updates);
+        }
+        apply_diff_updates(&mut self.comp_ref_prob, header.comp_ref_prob());
+        for (probs, updates) in self.y_mode_probs.iter_mut().zip(header.y_mode_probs()) {
+            apply_diff_updates(probs, updates);
+        }
+        for (probs, updates) in self
+            .partition_probs
+            .iter_mut()
+            .zip(header.partition_probs())
+        {
+            apply_diff_updates(probs, updates);
+        }
+
+        apply_mv_updates(&mut self.mv_joint_probs, header.mv_joint_probs());
+        apply_mv_updates(&mut self.mv_sign_prob, header.mv_sign_prob());
+        for (probs, updates) in self.mv_class_probs.iter_mut().zip(header.mv_class_probs()) {
+            apply_mv_updates(probs, updates);
+        }
+        apply_mv_updates(&mut self.mv_class0_bit_prob, header.mv_class0_bit_prob());
+        for (probs, updates) in self.mv_bits_prob.iter_mut().zip(header.mv_bits_prob()) {
+            apply_mv_updates(probs, updates);
+        }
+        for (probs, updates) in self
+            .mv_class0_fr_probs
+            .iter_mut()
+            .flatten()
+            .zip(header.mv_class0_fr_probs().iter().flatten())
+        {
+            apply_mv_updates(probs, updates);
+        }
+        for (probs, updates) in self.mv_fr_probs.iter_mut().zip(header.mv_fr_probs()) {
+            apply_mv_updates(probs, updates);
+        }
+        apply_mv_updates(&mut self.mv_class0_hp_prob, header.mv_class0_hp_prob());
+        apply_mv_updates(&mut self.mv_hp_prob, header.mv_hp_prob());
+    }
+
+    /// Adapts the probabilities to the symbol counts of a decoded frame.
+    ///
+    /// The adapted probabilities are merged from the probabilities of the saved context the
+    /// frame started with and the counts. Intra frames only adapt the coefficient probabilities.
+    /// All probabilities that are not adapted keep the values the frame was decoded with.
+    pub(crate) fn adapt(
+        &mut self,
+        saved: &FrameContext,
+        counts: &FrameCounts,
+        frame: &Frame,
+        header: &CompressedHeader,
+    ) {
+        let update_factor = if !frame.is_intra() && frame.last_frame_type() == FrameType::KeyFrame {
+            COEF_MAX_UPDATE_FACTOR_AFTER_KEY
+        } else {
+            COEF_MAX_UPDATE_FACTOR
+        };
+        self.adapt_coef_probs(saved, counts, update_factor);
+
+        if !frame.is_intra() {
+            self.adapt_noncoef_probs(saved, counts, frame, header);
+        }
+    }
+
+    // Implements spec "8.4.2 Coefficient probability adaptation process".
+    fn adapt_coef_probs(&mut self, saved: &FrameContext, counts: &FrameCounts, update_factor: u32) {
+        let probs = self
+            .coef_probs
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .flatten()
+            .flatten();
+        let saved = saved
+            .coef_probs
+            .iter()
+            .flatten()
+            .flatten()
+            .flatten()
+            .flatten();
+        let tokens = counts.tokens.iter().flatten().flatten().flatten().flatten();
+        let more_coefs = counts
+            .more_coefs
+            .iter()
+            .flatten()
+            .flatten()
+            .flatten()
+            .flatten();
+        for (((probs, saved), tokens), more_coefs) in probs.zip(saved).zip(tokens).zip(more_coefs) {
+            let branches = [
+                *more_coefs,
+                [tokens[0], tokens[1] + tokens[2]],
+                [tokens[1], tokens[2]],
+            ];
+            for ((prob, saved), branch) in probs.iter_mut().zip(saved).zip(branches) {
+                *prob = merge_prob(*saved, branch, COEF_COUNT_SAT, update_factor);
+            }
+        }
+    }
+
+    // Implements spec "8.4.3 Non coefficient probability adaptation process".
+    fn adapt_noncoef_probs(
+        &mut self,
+        saved: &FrameContext,
+        counts: &FrameCounts,
+        frame: &Frame,
+        header: &CompressedHeader,
+    ) {
+        merge_binary_probs(
+            &mut self.is_inter_prob,
+            &saved.is_inter_prob,
+            &counts.is_inter,
+        );
+        merge_binary_probs(
+            &mut self.comp_mode_prob,
+            &saved.comp_mode_prob,
+            &counts.comp_mode,
+        );
+        merge_binary_probs(
+            &mut self.comp_ref_prob,
+            &saved.comp_ref_prob,
+            &counts.comp_ref,
+        );
+        for ((probs, saved), counts) in self
+            .single_ref_prob
+            .iter_mut()
+            .zip(&saved.single_ref_prob)
+            .zip(&counts.single_ref)
+        {
+            merge_binary_probs(probs, saved, counts);
+        }
+        for ((probs, saved), counts) in self
+            .inter_mode_probs
+            .iter_mut()
+            .zip(&saved.inter_mode_probs)
+            .zip(&counts.inter_mode)
+        {
+            let _ = merge_tree_probs(&INTER_MODE_TREE, 0, probs, saved, counts);
+        }
+        for ((probs, saved), counts) in self
+            .y_mode_probs
+            .iter_mut()
+            .zip(&saved.y_mode_probs)
+            .zip(&counts.y_mode)
+        {
+            let _ = merge_tree_probs(&INTRA_MODE_TREE, 0, probs, saved, counts);
+        }
+        for ((probs, saved), counts) in self
+            .uv_mode_probs
+            .iter_mut()
+            .zip(&saved.uv_mode_probs)
+            .zip(&counts.uv_mode)
+        {
+            let _ = merge_tree_probs(&INTRA_MODE_TREE, 0, probs, saved, counts);
+        }
+        for ((probs, saved), counts) in self
+            .partition_probs
+            .iter_mut()
+            .zip(&saved.partition_probs)
+            .zip(&counts.partition)
+        {
+            let _ = merge_tree_probs(&PARTITION_TREE, 0, probs, saved, counts);
+        }
+
+        if frame.interpolation_filter() == InterpolationFilter::Switchable {
+            for ((probs, saved), counts) in self
+                .interp_filter_probs
+                .iter_mut()
+                .zip(&saved.interp_filter_probs)
+                .zip(&counts.interp_filter)
+            {
+                let _ = merge_tree_probs(&INTERP_FILTER_TREE, 0, probs, saved, counts);
+            }
+        }
+
+        if header.tx_mode() == TxMode::TxModeSelect {
+            for ctx in 0..TX_SIZE_CONTEXTS {
+                merge_tx_probs(
+                    &mut self.tx_probs_8x8[ctx],
+                    &saved.tx_probs_8x8[ctx],
+                    &counts.tx_8x8[ctx],
+                );
+                merge_tx_probs(
+                    &mut self.tx_probs_16x16[ctx],
+                    &saved.tx_probs_16x16[ctx],
+                    &counts.tx_16x16[ctx],
+                );
+                merge_tx_probs(
+                    &mut self.tx_probs_32x32[ctx],
+                    &saved.tx_probs_32x32[ctx],
+                    &counts.tx_32x32[ctx],
+                );
+            }
+        }
+
+        merge_binary_probs(&mut self.skip_prob, &saved.skip_prob, &counts.skip);
+
+        let _ = merge_tree_probs(
+            &MV_JOINT_TREE,
+            0,
+            &mut self.mv_joint_probs,
+            &saved.mv_joint_probs,
+            &counts.mv_joint,
+        );
+        merge_binary_probs(&mut self.mv_sign_prob, &saved.mv_sign_prob, &counts.mv_sign);
+        merge_binary_probs(
+            &mut self.mv_class0_bit_prob,
+            &saved.mv_class0_bit_prob,
+            &counts.mv_class0_bit,
+        );
+        for comp in 0..2 {
+            let _ = merge_tree_probs(
+                &MV_CLASS_TREE,
+                0,
+                &mut self.mv_class_probs[comp],
+                &saved.mv_class_probs[comp],
+                &counts.mv_class[comp],
+            );
+            merge_binary_probs(
+                &mut self.mv_bits_prob[comp],
+                &saved.mv_bits_prob[comp],
+                &counts.mv_bits[comp],
+            );
+            for ((probs, saved), counts) in self.mv_class0_fr_probs[comp]
+                .iter_mut()
+                .zip(&saved.mv_class0_fr_probs[comp])
+                .zip(&counts.mv_class0_fr[comp])
+            {
+                let _ = merge_tree_probs(&MV_FR_TREE, 0, probs, saved, counts);
+            }
+            let _ = merge_tree_probs(
+                &MV_FR_TREE,
+                0,
+                &mut self.mv_fr_probs[comp],
+                &saved.mv_fr_probs[comp],
+                &counts.mv_fr[comp],
+            );
+        }
+
+        if frame.allow_high_precision_mv() {
+            merge_binary_probs(
+                &mut self.mv_class0_hp_prob,
+                &saved.mv_class0_hp_prob,
+                &counts.mv_class0_hp,
+            );
+            merge_binary_probs(&mut self.mv_hp_prob, &saved.mv_hp_prob, &counts.mv_hp);
+        }
+    }
+
+    /// Probabilities of the transform size for blocks with a maximum transform size of 8x8.
+    pub fn tx_probs_8x8(&self) -> &[[u8; TX_SIZES - 3]; TX_SIZE_CONTEXTS] {
+        &self.tx_probs_8x8
+    }
+
+    /// Probabilities of the transform size for blocks with a maximum transform size of 16x16.
+    pub fn tx_probs_16x16(&self) -> &[[u8; TX_SIZES - 2]; TX_SIZE_CONTEXTS] {
+        &self.tx_probs_16x16
+    }
+
+    /// Probabilities of the transform size for blocks with a maximum transform size of 32x32.
+    pub fn tx_probs_32x32(&self) -> &[[u8; TX_SIZES - 1]; TX_SIZE_CONTEXTS] {
+        &self.tx_probs_32x32
+    }
+
+    /// Probabilities of the coefficient tokens.
+    pub fn coef_probs(&self) -> &CoefProbs {
+        &self.coef_probs
+    }
+
+    /// Probabilities of the skip flag.
+    pub fn skip_prob(&self) -> &[u8; SKIP_CONTEXTS] {
+        &self.skip_prob
+    }
+
+    /// Probabilities of the inter modes.
+    pub fn inter_mode_probs(&self) -> &[[u8; INTER_MODES - 1]; INTER_MODE_CONTEXTS] {
+        &self.inter_mode_probs
+    }
+
+    /// Probabilities of the interpolation filters.
+    pub fn interp_filter_probs(&self) -> &[[u8; SWITCHABLE_FILTERS - 1]; INTERP_FILTER_CONTEXTS] {
+        &self.interp_filter_probs
+    }
+
+    /// Probabilities of the is_inter flag.
+    pub fn is_inter_prob(&self) -> &[u8; IS_INTER_CONTEXTS] {
+        &self.is_inter_prob
+    }
+
+    /// Probabilities of the compound mode flag.
+    pub fn comp_mode_prob(&self) -> &[u8; COMP_MODE_CONTEXTS] {
+        &self.comp_mode_prob
+    }
+
+    /// Probabilities of the single reference frames.
+    pub fn single_ref_prob(&self) -> &[[u8; 2]; REF_CONTEXTS] {
+        &self.single_ref_prob
+    }
+
+    /// Probabilities of the compound reference frames.
+    pub fn comp_ref_prob(&self) -> &[u8; REF_CONTEXTS] {
+        &self.comp_ref_prob
+    }
+
+    /// Probabilities of the luma intra modes of inter frames.
+    pub fn y_mode_probs(&self) -> &[[u8; INTRA_MODES - 1]; BLOCK_SIZE_GROUPS] {
+        &self.y_mode_probs
+    }
+
+    /// Probabilities of the chroma intra modes of inter frames.
+    pub fn uv_mode_probs(&self) -> &[[u8; INTRA_MODES - 1]; INTRA_MODES] {
+        &self.uv_mode_probs
+    }
+
+    /// Probabilities of the partition types of inter frames.
+    pub fn partition_probs(&self) -> &[[u8; PARTITION_TYPES - 1]; PARTITION_CONTEXTS] {
+        &self.partition_probs
+    }
+
+    /// Probabilities of the motion vector joints.
+    pub fn mv_joint_probs(&self) -> &[u8; MV_JOINTS - 1] {
+        &self.mv_joint_probs
+    }
+
+    /// Probabilities of the motion vector signs.
+    pub fn mv_sign_prob(&self) -> &[u8; 2] {
+        &self.mv_sign_prob
+    }
+
+    /// Probabilities of the motion vector classes.
+    pub fn mv_class_probs(&self) -> &[[u8; MV_CLASSES - 1]; 2] {
+        &self.mv_class_probs
+    }
+
+    /// Probabilities of the motion vector class 0 bit.
+    pub fn mv_class0_bit_prob(&self) -> &[u8; 2] {
+        &self.mv_class0_bit_prob
+    }
+
+    /// Probabilities of the motion vector integer bits.
+    pub fn mv_bits_prob(&self) -> &[[u8; MV_OFFSET_BITS]; 2] {
+        &self.mv_bits_prob
+    }
+
+    /// Probabilities of the fractional part of class 0 motion vectors.
+    pub fn mv_class0_fr_probs(&self) -> &[[[u8; MV_FR_SIZE - 1]; CLASS0_SIZE]; 2] {
+        &self.mv_class0_fr_probs
+    }
+
+    /// Probabilities of the fractional part of motion vectors.
+    pub fn mv_fr_probs(&self) -> &[[u8; MV_FR_SIZE - 1]; 2] {
+        &self.mv_fr_probs
+    }
+
+    /// Probabilities of the high precision bit of class 0 motion vectors.
+    pub fn mv_class0_hp_prob(&self) -> &[u8; 2] {
+        &self.mv_class0_hp_prob
+    }
+
+    /// Probabilities of the high precision bit of motion vectors.
+    pub fn mv_hp_prob(&self) -> &[u8; 2] {
+        &self.mv_hp_prob
+    }
+}
+
+// Implements spec "merge_prob()".
+fn merge_prob(saved_prob: u8, counts: [u32; 2], count_sat: u32, max_update_factor: u32) -> u8 {
+    let den = u64::from(counts[0]) + u64::from(counts[1]);
+    let prob = (u64::from(counts[0]) * 256 + (den >> 1))
+        .checked_div(den)
+        .map_or(128, |prob| prob.clamp(1, 255));
+    let count = den.min(u64::from(count_sat));
+    let factor = u64::from(max_update_factor) * count / u64::from(count_sat);
+    let merged = (u64::from(saved_prob) * (256 - factor) + prob * factor + 128) >> 8;
+    u8::try_from(merged).unwrap_or(u8::MAX)
+}
+
+// Implements spec "merge_probs()" for the mode and motion vector probabilities. Returns the
+// sum of the counts below the node.
+fn merge_tree_probs(
+    tree: &[i8],
+    node: usize,
+    probs: &mut [u8],
+    saved: &[u8],
+    counts: &[u32],
+) -> u32 {
+    let mut branch = [0; 2];
+    for (count, next) in branch.iter_mut().zip(&tree[node..node + 2]) {
+        let index = usize::from(next.unsigned_abs());
+        *count = if *next <= 0 {
+            counts[index]
+        } else {
+            merge_tree_probs(tree, index, probs, saved, counts)
+        };
+    }
+    probs[node >> 1] = merge_prob(
+        saved[node >> 1],
+        branch,
+        MODE_MV_COUNT_SAT,
+        MODE_MV_MAX_UPDATE_FACTOR,
+    );
+    branch[0] + branch[1]
+}
+
+/// Merges probabilities of boolean values, each with the counts of `false` and `true`.
+fn merge_binary_probs(probs: &mut [u8], saved: &[u8], counts: &[[u32; 2]]) {
+    for ((prob, saved), counts) in probs.iter_mut().zip(saved).zip(counts) {
+        *prob = merge_prob(
+            *saved,
+            *counts,
+            MODE_MV_COUNT_SAT,
+            MODE_MV_MAX_UPDATE_FACTOR,
+        );
+    }
+}
+
+/// Merges the transform size probabilities. Every node decides between the transform size
+/// of the node and all larger transform sizes.
+fn merge_tx_probs(probs: &mut [u8], saved: &[u8], counts: &[u32]) {
+    for (node, (prob, saved)) in probs.iter_mut().zip(saved).enumerate() {
+        let branch = [counts[node], counts[node + 1..].iter().sum()];
+        *prob = merge_prob(*saved, branch, MODE_MV_COUNT_SAT, MODE_MV_MAX_UPDATE_FACTOR);
+    }
+}
+
+fn add_counts<'a>(counts: impl Iterator<Item = &'a mut u32>, other: impl Iterator<Item = &'a u32>) {
+    for (count, other) in counts.zip(other) {
+        *count += other;
+    }
+}
+
+fn apply_diff_updates(probs: &mut [u8], updates: &[Option<u8>]) {
+    for (prob, update) in probs.iter_mut().zip(updates) {
+        if let Some(delta_prob) = update {
+            *prob = inv_remap_prob(*delta_prob, *prob);
+        }
+    }
+}
+
+fn apply_mv_updates(probs: &mut [u8], updates: &[Option<u8>]) {
+    for (prob, update) in probs.iter_mut().zip(updates) {
+        if let Some(new_prob) = update {
+            *prob = *new_prob;
+        }
+    }
+}
+
+// Implements spec "inv_remap_prob()".
+fn inv_remap_prob(delta_prob: u8, prob: u8) -> u8 {
+    let v = INV_MAP_TABLE[usize::from(delta_prob)];
+    let m = prob.saturating_sub(1);
+    if u16::from(m) << 1 <= 255 {
+        1 + inv_recenter_nonneg(v, m)
+    } else {
+        255 - inv_recenter_nonneg(v, 254 - m)
+    }
+}
+
+fn inv_recenter_nonneg(v: u8, m: u8) -> u8 {
+    if u16::from(v) > 2 * u16::from(m) {
+        v
+    } else if v & 1 == 1 {
+        m - ((v >> 1) + 1)
+    } else {
+        m + (v >> 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::{ivf::Ivf, tile_decoder, PredictionMode, Vp9Parser};
+
+    #[test]
+    fn remap_probabilities() {
+        // Small deltas move the probability around its old value.
+        assert_eq!(inv_remap_prob(20, 128), 127);
+        assert_eq!(inv_remap_prob(21, 128), 129);
+        assert_eq!(inv_remap_prob(22, 128), 126);
+        assert_eq!(inv_remap_prob(20, 200), 201);
+        assert_eq!(inv_remap_prob(21, 200), 199);
+        assert_eq!(inv_remap_prob(22, 200), 202);
+        // Large deltas reach the far end of the range.
+        assert_eq!(inv_remap_prob(19, 1), 255);
+        assert_eq!(inv_remap_prob(19, 255), 1);
+    }
+
+    #[test]
+    fn merge_probabilities() {
+        // Without counts the saved probability is kept.
+        assert_eq!(
+            merge_prob(100, [0, 0], COEF_COUNT_SAT, COEF_MAX_UPDATE_FACTOR),
+            100
+        );
+        // Saturated counts move the probability by the maximum update factor.
+        assert_eq!(
+            merge_prob(128, [30, 10], COEF_COUNT_SAT, COEF_MAX_UPDATE_FACTOR),
+            156
+        );
+        // Fewer counts move it less.
+        assert_eq!(
+            merge_prob(128, [6, 0], MODE_MV_COUNT_SAT, MODE_MV_MAX_UPDATE_FACTOR),
+            147
+        );
+
+        let mut probs = [0; PARTITION_TYPES - 1];
+        let counts = [10, 0, 0, 10];
+        assert_eq!(
+            merge_tree_probs(&PARTITION_TREE, 0, &mut probs, &[128; 3], &counts),
+            20
+        );
+        assert_eq!(probs, [128, 96, 96]);
+    }
+
+    #[test]
+    fn adapt_synthetic_counts() {
+        // An inter frame that doesn't follow a key frame.
+        let file = std::fs::File::open("tests/data/320-24-cq.ivf").unwrap();
+        let mut ivf = Ivf::new(file).unwrap();
+        let mut parser = Vp9Parser::default();
+        let frame = std::iter::from_fn(|| ivf.read_frame().unwrap())
+            .flat_map(|ivf_frame| parser.parse_packet(ivf_frame.packet).unwrap())
+            .find(|frame| !frame.is_intra() && frame.last_frame_type() == FrameType::NonKeyFrame)
+            .unwrap();
+        let header = frame.compressed_header().unwrap().clone();
+
+        let mut counts = FrameCounts::default();
+        counts.more_coefs[1][0][0][2][3] = [3, 9];
+        counts.tokens[1][0][0][2][3] = [4, 3, 2];
+        counts.skip[0] = [20, 20];
+        counts.y_mode[0][usize::from(u8::from(PredictionMode::DcPred))] = 10;
+        counts.y_mode[0][usize::from(u8::from(PredictionMode::VPred))] = 5;
+        counts.mv_joint = [0, 6, 2, 2];
+
+        let saved = FrameContext::default();
+        let mut adapted = saved.clone();
+        adapted.adapt(&saved, &counts, &frame, &header);
+
+        // The expected values follow the merge_probs() of libvpx for the default
+        // probabilities and the counts above.
+        assert_eq!(adapted.coef_probs()[1][0][0][2][3], [16, 80, 118]);
+        assert_eq!(
+            adapted.coef_probs()[1][0][0][2][2],
+            saved.coef_probs()[1][0][0][2][2]
+        );
+        assert_eq!(adapted.skip_prob(), &[160, 128, 64]);
+        assert_eq!(
+            adapted.y_mode_probs()[0],
+            [105, 28, 48, 144, 162, 194, 41, 51, 98]
+        );
+        assert_eq!(adapted.y_mode_probs()[1], saved.y_mode_probs()[1]);
+        assert_eq!(adapted.mv_joint_probs(), &[24, 87, 99]);
+        assert_eq!(adapted.mv_class_probs(), saved.mv_class_probs());
+
+        // The frame after a key frame adapts the coefficients faster, intra frames only adapt
+        // the coefficients.
+        let mut after_key = frame.clone();
+        after_key.last_frame_type = FrameType::KeyFrame;
+        let mut adapted = saved.clone();
+        adapted.adapt(&saved, &counts, &after_key, &header);
+        assert_eq!(adapted.coef_probs()[1][0][0][2][3][0], 18);
+
+        let mut intra = frame;
+        intra.intra_only = true;
+        let mut adapted = saved.clone();
+        adapted.adapt(&saved, &counts, &intra, &header);
+        assert_eq!(adapted.coef_probs()[1][0][0][2][3], [16, 80, 118]);
+        assert_eq!(adapted.skip_prob(), saved.skip_prob());
+        assert_eq!(adapted.mv_joint_probs(), saved.mv_joint_probs());
+    }
+
+    #[test]
+    fn adapt_consecutive_frames() {
+        let file = std::fs::File::open("tests/data/320-24-cq.ivf").unwrap();
+        let mut ivf = Ivf::new(file).unwrap();
+        let mut parser = Vp9Parser::default();
+
+        let mut adapted_frames = 0;
+        for _ in 0..10 {
+            let packet = ivf.read_frame().unwrap().unwrap().packet;
+            let saved_contexts = parser.frame_contexts().clone();
+            // Only the first frame of a super frame starts with the saved contexts from before.
+            let frame = parser.parse_packet(packet).unwrap().remove(0);
+            let (Some(context), Some(header)) = (frame.frame_context(), frame.compressed_header())
+            else {
+                continue;
+            };
+            let Ok(tile_decoder::DecodedTiles { counts, .. }) =
+                tile_decoder::decode_tiles(&frame, header, context, None, None, 1, false, true)
+            else {
+                continue;
+            };
+            let saved = &saved_contexts[usize::from(frame.frame_context_idx())];
+            let mut adapted = context.clone();
+            adapted.adapt(saved, &counts, &frame, header);
+            adapted_frames += 1;
+
+            if frame.is_intra() {
+                assert!(adapted.coef_probs() != context.coef_probs());
+                assert_eq!(adapted.skip_prob(), context.skip_prob());
+                assert_eq!(adapted.y_mode_probs(), context.y_mode_probs());
+                assert_eq!(adapted.partition_probs(), context.partition_probs());
+                continue;
+            }
+
+            // Every adapted probability moves from the saved probability towards the counts.
+            for ((adapted, saved), counts) in adapted
+                .skip_prob()
+                .iter()
+                .zip(saved.skip_prob())
+                .zip(counts.skip)
+            {
+                let measured = merge_prob(*saved, counts, 1, 256);
+                assert!(*adapted >= measured.min(*saved));
+                assert!(*adapted <= measured.max(*saved));
+            }
+            // The coefficient probabilities adapt faster in the frame after a key frame.
+            let update_factor = if frame.last_frame_type() == FrameType::KeyFrame {
+                COEF_MAX_UPDATE_FACTOR_AFTER_KEY
+            } else {
+                COEF_MAX_UPDATE_FACTOR
+            };
+            let more_coefs = counts.more_coefs[0][0][1][0][0];
+            assert_eq!(
+                adapted.coef_probs()[0][0][1][0][0][0],
+                merge_prob(
+                    saved.coef_probs()[0][0][1][0][0][0],
+                    more_coefs,
+                    COEF_COUNT_SAT,
+                    update_factor
+                )
+            );
+        }
+        assert!(adapted_frames > 1);
+    }
+}
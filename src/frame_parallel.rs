@@ -0,0 +1,408 @@
+//! Decoder that reconstructs several frames at the same time.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, PoisonError, RwLock, RwLockReadGuard},
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    decoder::{SuperblockRows, NUM_REF_FRAMES},
+    Frame, OutputSize, Picture, Plane, Result, Vp9Parser, Vp9ParserError,
+};
+
+/// The reconstruction state of a frame buffer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Progress {
+    /// The number of final luma rows.
+    Rows(usize),
+    /// All rows are final.
+    Done,
+    /// The frame couldn't be reconstructed.
+    Failed,
+}
+
+/// The planes of a frame that are written by its reconstruction thread, while the threads of
+/// the following frames wait for the rows they reference.
+#[derive(Debug)]
+struct FrameBuffer {
+    /// The position of the frame in decode order. Locks of frame buffers are always taken
+    /// in this order, so that threads waiting for each other can't deadlock.
+    sequence: u64,
+    planes: RwLock<[Plane; 3]>,
+    progress: Mutex<Progress>,
+    progress_changed: Condvar,
+}
+
+impl FrameBuffer {
+    fn new(frame: &Frame, sequence: u64) -> Self {
+        Self {
+            sequence,
+            planes: RwLock::new([0, 1, 2].map(|plane| Plane::from_frame(frame, plane))),
+            progress: Mutex::new(Progress::Rows(0)),
+            progress_changed: Condvar::new(),
+        }
+    }
+
+    fn publish(&self, progress: Progress) {
+        *self.progress.lock().unwrap_or_else(PoisonError::into_inner) = progress;
+        self.progress_changed.notify_all();
+    }
+
+    /// Waits until the given number of luma rows is final. Returns an error with the given
+    /// slot index if the frame couldn't be reconstructed.
+    fn wait_for(&self, rows: usize, index: u8) -> Result<()> {
+        let mut progress = self.progress.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            match *progress {
+                Progress::Done => return Ok(()),
+                Progress::Failed => return Err(Vp9ParserError::MissingRefPicture(index)),
+                Progress::Rows(done) if done >= rows => return Ok(()),
+                Progress::Rows(_) => {
+                    progress = self
+                        .progress_changed
+                        .wait(progress)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+            }
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, [Plane; 3]> {
+        self.planes.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A frame waiting to be returned in display order.
+#[derive(Debug)]
+struct PendingFrame {
+    frame: Arc<Frame>,
+    buffer: Arc<FrameBuffer>,
+    /// The reconstruction thread. `None` for shown existing frames.
+    reconstruction: Option<JoinHandle<Result<()>>>,
+}
+
+/// Decodes VP9 packets into pictures, reconstructing several frames at the same time.
+///
+/// The headers and the tile data are parsed on the calling thread, while every frame is
+/// reconstructed on its own thread. The parsing of a frame therefore overlaps with the
+/// reconstruction of the frames before it. The reconstruction proceeds in super block rows
+/// and waits only for the rows of the reference frames that the motion vectors of a row
+/// point to.
+///
+/// Frames with `frame_parallel_decoding_mode` don't adapt the probabilities to the decoded
+/// symbols, so their tile data only depends on the headers. Since the parser adapts the
+/// probabilities right after decoding the tile data, frames without it are pipelined the
+/// same way. The pictures are identical to the ones of `Vp9Decoder` and are returned in
+/// display order, but can be delayed by up to `frames_in_flight()` frames. `flush()` returns
+/// the remaining pictures at the end of the bitstream.
+#[derive(Debug)]
+pub struct FrameParallelDecoder {
+    parser: Vp9Parser,
+    ref_buffers: [Option<Arc<FrameBuffer>>; NUM_REF_FRAMES],
+    pending: VecDeque<PendingFrame>,
+    /// The pictures and errors of the finished frames in display order.
+    finished: VecDeque<Result<Picture>>,
+    frames_in_flight: usize,
+    output_size: OutputSize,
+    sequence: u64,
+}
+
+impl Default for FrameParallelDecoder {
+    fn default() -> Self {
+        let mut parser = Vp9Parser::default();
+        parser.set_decode_tiles(true);
+        Self {
+            parser,
+            ref_buffers: Default::default(),
+            pending: VecDeque::new(),
+            finished: VecDeque::new(),
+            frames_in_flight: 4,
+            output_size: OutputSize::Decoded,
+            sequence: 0,
+        }
+    }
+}
+
+impl FrameParallelDecoder {
+    /// Creates a new decoder.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Resets the state of the decoder. Used when switching the bitstream or seeking.
+    ///
+    /// Waits for the frames that are still reconstructed and drops their pictures. The number
+    /// of threads and frames in flight, the error concealment and the output size are kept.
+    pub fn reset(&mut self) {
+        for pending in self.pending.drain(..) {
+            if let Some(reconstruction) = pending.reconstruction {
+                let _ = reconstruction.join();
+            }
+        }
+        self.finished.clear();
+        self.parser.reset();
+        self.ref_buffers = Default::default();
+    }
+
+    /// The number of threads used to decode the tile data.
+    pub fn threads(&self) -> usize {
+        self.parser.threads()
+    }
+
+    /// Sets the number of threads used to decode the tile data. Defaults to 1.
+    ///
+    /// A value of 0 is treated as 1.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.parser.set_threads(threads);
+    }
+
+    /// The maximum number of frames that are reconstructed or waiting to be returned.
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames_in_flight
+    }
+
+    /// Sets the maximum number of frames that are reconstructed or waiting to be returned.
+    /// Defaults to 4.
+    ///
+    /// Decoding a packet blocks until the oldest frames are reconstructed if the limit is
+    /// reached. A value of 0 is treated as 1.
+    pub fn set_frames_in_flight(&mut self, frames_in_flight: usize) {
+        self.frames_in_flight = frames_in_flight.max(1);
+    }
+
+    /// Indicates if superblocks that fail to decode are concealed.
+    pub fn error_concealment(&self) -> bool {
+        self.parser.error_concealment()
+    }
+
+    /// Enables the concealment of corrupted or truncated tile data. Disabled by default.
+    ///
+    /// See `Vp9Decoder::set_error_concealment()`.
+    pub fn set_error_concealment(&mut self, error_concealment: bool) {
+        self.parser.set_error_concealment(error_concealment);
+    }
+
+    /// The size of the returned pictures.
+    pub fn output_size(&self) -> OutputSize {
+        self.output_size
+    }
+
+    /// Sets the size of the returned pictures. Defaults to `OutputSize::Decoded`.
+    ///
+    /// See `Vp9Decoder::set_output_size()`.
+    pub fn set_output_size(&mut self, output_size: OutputSize) {
+        self.output_size = output_size;
+    }
+
+    /// The parser used to parse the frames.
+    pub fn parser(&self) -> &Vp9Parser {
+        &self.parser
+    }
+
+    /// Decodes a VP9 bitstream packet and returns the pictures that are finished.
+    ///
+    /// Packets needs to be supplied in the order they are appearing in the bitstream. The
+    /// returned pictures can belong to earlier packets. If a frame can't be reconstructed, the
+    /// error is returned in its place of the display order and the following frames
+    /// referencing it return an error too. The pictures and errors after an error are
+    /// returned by the next calls.
+    pub fn decode_packet(&mut self, packet: Vec<u8>) -> Result<Vec<Picture>> {
+        let frames = self.parser.parse_packet(packet)?;
+        for frame in frames {
+            while self.pending.len() >= self.frames_in_flight {
+                self.finish_pending();
+            }
+            self.queue_frame(frame)?;
+        }
+
+        while self.pending.front().is_some_and(|pending| {
+            pending
+                .reconstruction
+                .as_ref()
+                .is_none_or(JoinHandle::is_finished)
+        }) {
+            self.finish_pending();
+        }
+
+        self.take_finished()
+    }
+
+    /// Waits for the reconstruction of all frames and returns the remaining pictures.
+    ///
+    /// Returns the pictures up to the next error. Needs to be called again after an error
+    /// until it returns no pictures.
+    pub fn flush(&mut self) -> Result<Vec<Picture>> {
+        while !self.pending.is_empty() {
+            self.finish_pending();
+        }
+        self.take_finished()
+    }
+
+    /// Returns the finished pictures up to the next error, or the error if it comes first.
+    fn take_finished(&mut self) -> Result<Vec<Picture>> {
+        if self.finished.front().is_some_and(Result::is_err) {
+            if let Some(Err(err)) = self.finished.pop_front() {
+                return Err(err);
+            }
+        }
+        let count = self
+            .finished
+            .iter()
+            .take_while(|result| result.is_ok())
+            .count();
+        Ok(self.finished.drain(..count).flatten().collect())
+    }
+
+    fn queue_frame(&mut self, frame: Frame) -> Result<()> {
+        if frame.show_existing_frame() {
+            let index = frame
+                .frame_to_show_map_idx()
+                .ok_or(Vp9ParserError::InvalidRefFrameIndex)?;
+            let buffer = self.ref_buffers[usize::from(index)]
+                .clone()
+                .ok_or(Vp9ParserError::MissingRefPicture(index))?;
+            self.pending.push_back(PendingFrame {
+                frame: Arc::new(frame),
+                buffer,
+                reconstruction: None,
+            });
+            return Ok(());
+        }
+
+        let frame = Arc::new(frame);
+        let buffer = Arc::new(FrameBuffer::new(&frame, self.sequence));
+        self.sequence += 1;
+        let references = if frame.is_intra() {
+            Default::default()
+        } else {
+            frame.ref_frame_indices().map(|index| {
+                let buffer = self.ref_buffers.get(usize::from(index)).cloned().flatten();
+                (index, buffer)
+            })
+        };
+
+        let reconstruction = {
+            let frame = frame.clone();
+            let buffer = buffer.clone();
+            thread::Builder::new().spawn(move || {
+                let result = reconstruct(&frame, &buffer, &references);
+                buffer.publish(match result {
+                    Ok(()) => Progress::Done,
+                    Err(_) => Progress::Failed,
+                });
+                result
+            })?
+        };
+
+        // Implements spec "8.10 Reference frame update process" for the frame buffers.
+        for (i, slot) in self.ref_buffers.iter_mut().enumerate() {
+            if (frame.refresh_frame_flags() >> i) & 1 == 1 {
+                *slot = Some(buffer.clone());
+            }
+        }
+        self.pending.push_back(PendingFrame {
+            frame,
+            buffer,
+            reconstruction: Some(reconstruction),
+        });
+
+        Ok(())
+    }
+
+    /// Waits for the oldest frame and keeps its picture or error for `take_finished()`.
+    fn finish_pending(&mut self) {
+        if let Some(result) = self.wait_pending().transpose() {
+            self.finished.push_back(result);
+        }
+    }
+
+    /// Waits for the oldest frame and returns its picture if it is to be shown.
+    fn wait_pending(&mut self) -> Result<Option<Picture>> {
+        let Some(pending) = self.pending.pop_front() else {
+            return Ok(None);
+        };
+        match pending.reconstruction {
+            Some(reconstruction) => reconstruction
+                .join()
+                .map_err(|_| Vp9ParserError::WorkerThreadPanicked)??,
+            None => {
+                let index = pending.frame.frame_to_show_map_idx().unwrap_or_default();
+                pending.buffer.wait_for(usize::MAX, index)?;
+            }
+        }
+        if !pending.frame.show_frame() && !pending.frame.show_existing_frame() {
+            return Ok(None);
+        }
+
+        let planes = Arc::new(pending.buffer.read().clone());
+        let frame = Arc::try_unwrap(pending.frame).unwrap_or_else(|frame| (*frame).clone());
+        Picture::new(frame, planes, self.output_size).map(Some)
+    }
+}
+
+/// The slot index and the frame buffer of a reference frame.
+type Reference = (u8, Option<Arc<FrameBuffer>>);
+
+/// Reconstructs a frame super block row by super block row and publishes the final rows.
+fn reconstruct(frame: &Frame, buffer: &FrameBuffer, references: &[Reference; 3]) -> Result<()> {
+    let rows = SuperblockRows::new(frame)?;
+    if !frame.is_intra() {
+        for (index, reference) in references.iter() {
+            if reference.is_none() {
+                return Err(Vp9ParserError::MissingRefPicture(*index));
+            }
+        }
+    }
+
+    for sb_row in 0..rows.len() {
+        let reference_rows = rows.reference_rows(sb_row);
+        let mut used = Vec::with_capacity(3);
+        for ((index, reference), rows) in references.iter().zip(reference_rows) {
+            if let Some(reference) = reference.as_ref().filter(|_| rows > 0) {
+                reference.wait_for(rows, *index)?;
+                used.push(reference);
+            }
+        }
+
+        // The same frame buffer can be referenced by more than one slot, but is locked once.
+        used.sort_by_key(|reference| reference.sequence);
+        used.dedup_by(|a, b| Arc::ptr_eq(a, b));
+        let guards: Vec<_> = used.iter().map(|reference| reference.read()).collect();
+        let planes = references.each_ref().map(|(_, reference)| {
+            reference
+                .as_ref()
+                .and_then(|reference| used.iter().position(|used| Arc::ptr_eq(used, reference)))
+                .and_then(|position| guards.get(position))
+                .map_or(&[][..], |planes| &planes[..])
+        });
+
+        let mut target = buffer
+            .planes
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        rows.reconstruct_row(&mut target, sb_row, &planes)?;
+        if let Some(above) = sb_row.checked_sub(1) {
+            rows.filter_row(&mut target, above)?;
+            drop(target);
+            buffer.publish(Progress::Rows(SuperblockRows::final_rows(above)));
+        }
+    }
+    if let Some(last) = rows.len().checked_sub(1) {
+        let mut target = buffer
+            .planes
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        rows.filter_row(&mut target, last)?;
+    }
+
+    // A frame referencing a frame that failed fails too, like in `Vp9Decoder`, even if the
+    // failure happened after the referenced rows were final.
+    for (index, reference) in references.iter() {
+        if let Some(reference) = reference {
+            reference.wait_for(usize::MAX, *index)?;
+        }
+    }
+
+    Ok(())
+}
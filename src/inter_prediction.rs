@@ -0,0 +1,610 @@
+//! Inter prediction as defined in spec "8.5.2 Inter prediction process".
+//!
+//! The prediction of a block is interpolated from up to two reference frames with the 8-tap
+//! filter of the block. Luma motion vectors have 1/8 sample precision, chroma motion vectors
+//! of subsampled planes 1/16 sample precision. References of a different size are stepped
+//! through with the scaling factors of the frame. Samples outside of the reference frame
+//! repeat the nearest edge sample.
+
+use std::convert::TryFrom;
+
+use crate::{
+    BlockSize, Frame, InterpolationFilter, ModeInfo, MotionVector, Plane, RefScale, Result,
+    Vp9ParserError, INTRA_FRAME, REF_SCALE_SHIFT,
+};
+
+/// Number of taps of the interpolation filters.
+const SUBPEL_TAPS: usize = 8;
+
+/// Number of samples the 8-tap filter reads beyond the block edges.
+const INTERP_EXTEND: i64 = 4;
+
+/// Largest supported block dimension in samples.
+const MAX_BLOCK_SIZE: usize = 64;
+
+/// Largest supported vertical step between predicted samples in 1/16 sample units.
+const MAX_STEP: usize = 32;
+
+/// Number of rows of the intermediate buffer of the largest block at the largest step.
+const MAX_INTERMEDIATE_HEIGHT: usize = (((MAX_BLOCK_SIZE - 1) * MAX_STEP + 15) >> 4) + SUBPEL_TAPS;
+
+/// The interpolation filter kernels (spec `subpel_filters`) in the order EIGHTTAP_SMOOTH,
+/// EIGHTTAP, EIGHTTAP_SHARP and BILINEAR, indexed by the 1/16 sample position.
+const SUBPEL_FILTERS: [[[i32; SUBPEL_TAPS]; 16]; 4] = [
+    [
+        [0, 0, 0, 128, 0, 0, 0, 0],
+        [-3, -1, 32, 64, 38, 1, -3, 0],
+        [-2, -2, 29, 63, 41, 2, -3, 0],
+        [-2, -2, 26, 63, 43, 4, -4, 0],
+        [-2, -3, 24, 62, 46, 5, -4, 0],
+        [-2, -3, 21, 60, 49, 7, -4, 0],
+        [-1, -4, 18, 59, 51, 9, -4, 0],
+        [-1, -4, 16, 57, 53, 12, -4, -1],
+        [-1, -4, 14, 55, 55, 14, -4, -1],
+        [-1, -4, 12, 53, 57, 16, -4, -1],
+        [0, -4, 9, 51, 59, 18, -4, -1],
+        [0, -4, 7, 49, 60, 21, -3, -2],
+        [0, -4, 5, 46, 62, 24, -3, -2],
+        [0, -4, 4, 43, 63, 26, -2, -2],
+        [0, -3, 2, 41, 63, 29, -2, -2],
+        [0, -3, 1, 38, 64, 32, -1, -3],
+    ],
+    [
+        [0, 0, 0, 128, 0, 0, 0, 0],
+        [0, 1, -5, 126, 8, -3, 1, 0],
+        [-1, 3, -10, 122, 18, -6, 2, 0],
+        [-1, 4, -13, 118, 27, -9, 3, -1],
+        [-1, 4, -16, 112, 37, -11, 4, -1],
+        [-1, 5, -18, 105, 48, -14, 4, -1],
+        [-1, 5, -19, 97, 58, -16, 5, -1],
+        [-1, 6, -19, 88, 68, -18, 5, -1],
+        [-1, 6, -19, 78, 78, -19, 6, -1],
+        [-1, 5, -18, 68, 88, -19, 6, -1],
+        [-1, 5, -16, 58, 97, -19, 5, -1],
+        [-1, 4, -14, 48, 105, -18, 5, -1],
+        [-1, 4, -11, 37, 112, -16, 4, -1],
+        [-1, 3, -9, 27, 118, -13, 4, -1],
+        [0, 2, -6, 18, 122, -10, 3, -1],
+        [0, 1, -3, 8, 126, -5, 1, 0],
+    ],
+    [
+        [0, 0, 0, 128, 0, 0, 0, 0],
+        [-1, 3, -7, 127, 8, -3, 1, 0],
+        [-2, 5, -13, 125, 17, -6, 3, -1],
+        [-3, 7, -17, 121, 27, -10, 5, -2],
+        [-4, 9, -20, 115, 37, -13, 6, -2],
+        [-4, 10, -23, 108, 48, -16, 8, -3],
+        [-4, 10, -24, 100, 59, -19, 9, -3],
+        [-4, 11, -24, 90, 70, -21, 10, -4],
+        [-4, 11, -23, 80, 80, -23, 11, -4],
+        [-4, 10, -21, 70, 90, -24, 11, -4],
+        [-3, 9, -19, 59, 100, -24, 10, -4],
+        [-3, 8, -16, 48, 108, -23, 10, -4],
+        [-2, 6, -13, 37, 115, -20, 9, -4],
+        [-2, 5, -10, 27, 121, -17, 7, -3],
+        [-1, 3, -6, 17, 125, -13, 5, -2],
+        [0, 1, -3, 8, 127, -7, 3, -1],
+    ],
+    [
+        [0, 0, 0, 128, 0, 0, 0, 0],
+        [0, 0, 0, 120, 8, 0, 0, 0],
+        [0, 0, 0, 112, 16, 0, 0, 0],
+        [0, 0, 0, 104, 24, 0, 0, 0],
+        [0, 0, 0, 96, 32, 0, 0, 0],
+        [0, 0, 0, 88, 40, 0, 0, 0],
+        [0, 0, 0, 80, 48, 0, 0, 0],
+        [0, 0, 0, 72, 56, 0, 0, 0],
+        [0, 0, 0, 64, 64, 0, 0, 0],
+        [0, 0, 0, 56, 72, 0, 0, 0],
+        [0, 0, 0, 48, 80, 0, 0, 0],
+        [0, 0, 0, 40, 88, 0, 0, 0],
+        [0, 0, 0, 32, 96, 0, 0, 0],
+        [0, 0, 0, 24, 104, 0, 0, 0],
+        [0, 0, 0, 16, 112, 0, 0, 0],
+        [0, 0, 0, 8, 120, 0, 0, 0],
+    ],
+];
+
+/// A block of a plane that is predicted with a single motion vector.
+#[derive(Clone, Copy, Debug)]
+struct PredictionBlock {
+    /// Position of the block inside the plane.
+    x: i64,
+    y: i64,
+    /// Offset of the block from the top left corner of the mode info block.
+    offset_x: i64,
+    offset_y: i64,
+    width: usize,
+    height: usize,
+}
+
+/// Predicts all planes of an inter block from its reference frames.
+///
+/// The references hold the planes of the LAST, GOLDEN and ALTREF frame. Blocks are predicted
+/// at their full size, even if they reach beyond the decoded area of the frame. Compound
+/// blocks average the predictions of both reference frames.
+pub fn predict_inter(
+    planes: &mut [Plane],
+    frame: &Frame,
+    mode_info: &ModeInfo,
+    references: &[&[Plane]; 3],
+) -> Result<()> {
+    if !mode_info.is_inter {
+        return Err(Vp9ParserError::InvalidRefFrame(INTRA_FRAME));
+    }
+    let scales = frame
+        .ref_scales()
+        .ok_or(Vp9ParserError::InvalidRefFrame(mode_info.ref_frame[0]))?;
+    let kernel = filter_kernel(mode_info.interp_filter)?;
+    let ref_count = if mode_info.ref_frame[1] > INTRA_FRAME {
+        2
+    } else {
+        1
+    };
+
+    let mut preds = [[0u16; MAX_BLOCK_SIZE * MAX_BLOCK_SIZE]; 2];
+    for (plane_index, plane) in planes.iter_mut().enumerate() {
+        let (subsampling_x, subsampling_y) = if plane_index > 0 {
+            (frame.subsampling_x(), frame.subsampling_y())
+        } else {
+            (false, false)
+        };
+        let width =
+            (usize::from(mode_info.block_size.num_8x8_wide()) * 8) >> u8::from(subsampling_x);
+        let height =
+            (usize::from(mode_info.block_size.num_8x8_high()) * 8) >> u8::from(subsampling_y);
+        let x = (usize::from(mode_info.mi_col) * 8) >> u8::from(subsampling_x);
+        let y = (usize::from(mode_info.mi_row) * 8) >> u8::from(subsampling_y);
+        if !plane.contains(x, y, width, height) {
+            return Err(Vp9ParserError::BlockOutsidePlane(x, y));
+        }
+
+        for (ref_list, pred) in preds.iter_mut().enumerate().take(ref_count) {
+            let ref_frame = mode_info.ref_frame[ref_list];
+            let index = ref_frame
+                .checked_sub(1)
+                .ok_or(Vp9ParserError::InvalidRefFrame(ref_frame))?;
+            let reference = references
+                .get(index)
+                .and_then(|planes| planes.get(plane_index))
+                .ok_or(Vp9ParserError::InvalidRefFrame(ref_frame))?;
+            let scale = &scales[index];
+            if reference.bit_depth() != plane.bit_depth()
+                || !(1..=MAX_STEP).contains(&usize::try_from(scale.x_step())?)
+                || !(1..=MAX_STEP).contains(&usize::try_from(scale.y_step())?)
+            {
+                return Err(Vp9ParserError::InvalidRefFrame(ref_frame));
+            }
+
+            // Blocks below 8x8 predict every 4x4 block with its own motion vector.
+            let (block_width, block_height) = if mode_info.block_size < BlockSize::Block8x8 {
+                (4, 4)
+            } else {
+                (width, height)
+            };
+            let mut block_index = 0;
+            for offset_y in (0..height).step_by(block_height) {
+                for offset_x in (0..width).step_by(block_width) {
+                    let mv = select_mv(
+                        mode_info,
+                        ref_list,
+                        block_index,
+                        subsampling_x,
+                        subsampling_y,
+                    );
+                    let block = PredictionBlock {
+                        x: i64::try_from(x + offset_x)?,
+                        y: i64::try_from(y + offset_y)?,
+                        offset_x: i64::try_from(offset_x)?,
+                        offset_y: i64::try_from(offset_y)?,
+                        width: block_width,
+                        height: block_height,
+                    };
+                    let clamped_mv = clamp_mv(frame, mode_info, subsampling_x, subsampling_y, mv);
+                    predict_block(
+                        reference,
+                        scale,
+                        kernel,
+                        (mode_info.mi_col, mode_info.mi_row),
+                        block,
+                        clamped_mv,
+                        &mut pred[offset_y * width + offset_x..],
+                        width,
+                    )?;
+                    block_index += 1;
+                }
+            }
+        }
+
+        let stride = plane.stride();
+        let data = plane.data_mut();
+        for row in 0..height {
+            let start = (y + row) * stride + x;
+            let samples = &mut data[start..start + width];
+            let pred = &preds[0][row * width..(row + 1) * width];
+            if ref_count == 2 {
+                let second = &preds[1][row * width..(row + 1) * width];
+                for ((sample, first), second) in samples.iter_mut().zip(pred).zip(second) {
+                    *sample = (first + second + 1) >> 1;
+                }
+            } else {
+                samples.copy_from_slice(pred);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the filter kernels of the interpolation filter.
+fn filter_kernel(filter: InterpolationFilter) -> Result<&'static [[i32; SUBPEL_TAPS]; 16]> {
+    let index = match filter {
+        InterpolationFilter::EighttapSmooth => 0,
+        InterpolationFilter::Eighttap => 1,
+        InterpolationFilter::EighttapSharp => 2,
+        InterpolationFilter::Bilinear => 3,
+        InterpolationFilter::Unknown | InterpolationFilter::Switchable => {
+            return Err(Vp9ParserError::InvalidInterpolationFilter(filter))
+        }
+    };
+    Ok(&SUBPEL_FILTERS[index])
+}
+
+// Implements spec "8.5.2.1 Motion vector selection process".
+//
+// Chroma blocks of subsampled planes cover several 4x4 luma blocks of blocks below 8x8 and
+// use the rounded average of their motion vectors.
+fn select_mv(
+    mode_info: &ModeInfo,
+    ref_list: usize,
+    block_index: usize,
+    subsampling_x: bool,
+    subsampling_y: bool,
+) -> MotionVector {
+    let mv = |block: usize| mode_info.sub_mvs[block.min(3)][ref_list];
+    if mode_info.block_size >= BlockSize::Block8x8 {
+        return mode_info.sub_mvs[3][ref_list];
+    }
+
+    match (subsampling_x, subsampling_y) {
+        (false, false) => mv(block_index),
+        (false, true) => average_mvs(&[mv(block_index), mv(block_index + 2)]),
+        (true, false) => average_mvs(&[mv(block_index), mv(block_index + 1)]),
+        (true, true) => average_mvs(&[mv(0), mv(1), mv(2), mv(3)]),
+    }
+}
+
+/// Averages two or four motion vectors, rounding away from zero.
+fn average_mvs(mvs: &[MotionVector]) -> MotionVector {
+    let count = i32::try_from(mvs.len()).unwrap_or(1);
+    let average = |sum: i32| {
+        let rounded = if sum < 0 {
+            (sum - count / 2) / count
+        } else {
+            (sum + count / 2) / count
+        };
+        i16::try_from(rounded).unwrap_or_default()
+    };
+    MotionVector {
+        row: average(mvs.iter().map(|mv| i32::from(mv.row)).sum()),
+        col: average(mvs.iter().map(|mv| i32::from(mv.col)).sum()),
+    }
+}
+
+// Implements spec "8.5.2.2 Motion vector clamping process".
+//
+// Returns the motion vector in 1/16 sample units of the plane as (row, column). Motion
+// vectors pointing further outside of the frame than the filter can reach are limited,
+// which doesn't change the prediction.
+fn clamp_mv(
+    frame: &Frame,
+    mode_info: &ModeInfo,
+    subsampling_x: bool,
+    subsampling_y: bool,
+    mv: MotionVector,
+) -> (i64, i64) {
+    let ss_x = u8::from(subsampling_x);
+    let ss_y = u8::from(subsampling_y);
+    let bw = i64::from(mode_info.block_size.num_8x8_wide());
+    let bh = i64::from(mode_info.block_size.num_8x8_high());
+    let mi_row = i64::from(mode_info.mi_row);
+    let mi_col = i64::from(mode_info.mi_col);
+
+    let mb_to_left_edge = -(mi_col * 8 * 8);
+    let mb_to_right_edge = (i64::from(frame.mi_cols()) - bw - mi_col) * 8 * 8;
+    let mb_to_top_edge = -(mi_row * 8 * 8);
+    let mb_to_bottom_edge = (i64::from(frame.mi_rows()) - bh - mi_row) * 8 * 8;
+
+    let spel_left = (INTERP_EXTEND + ((bw * 8) >> ss_x)) << 4;
+    let spel_right = spel_left - 16;
+    let spel_top = (INTERP_EXTEND + ((bh * 8) >> ss_y)) << 4;
+    let spel_bottom = spel_top - 16;
+
+    let row = ((2 * i64::from(mv.row)) >> ss_y).clamp(
+        (mb_to_top_edge << (1 - ss_y)) - spel_top,
+        (mb_to_bottom_edge << (1 - ss_y)) + spel_bottom,
+    );
+    let col = ((2 * i64::from(mv.col)) >> ss_x).clamp(
+        (mb_to_left_edge << (1 - ss_x)) - spel_left,
+        (mb_to_right_edge << (1 - ss_x)) + spel_right,
+    );
+    (row, col)
+}
+
+// Implements spec "8.5.2.3 Motion vector scaling process" and "8.5.2.4 Block inter
+// prediction process".
+//
+// The fractional offset of the scaled block position is derived from the luma position of
+// the mode info block plus the offset of the block inside the plane.
+#[allow(clippy::too_many_arguments)]
+fn predict_block(
+    reference: &Plane,
+    scale: &RefScale,
+    kernel: &[[i32; SUBPEL_TAPS]; 16],
+    (mi_col, mi_row): (u16, u16),
+    block: PredictionBlock,
+    (mv_row, mv_col): (i64, i64),
+    pred: &mut [u16],
+    pred_stride: usize,
+) -> Result<()> {
+    let x_scale = i64::from(scale.x_scale());
+    let y_scale = i64::from(scale.y_scale());
+    let x_step = usize::try_from(scale.x_step())?;
+    let y_step = usize::try_from(scale.y_step())?;
+
+    let scaled = |value: i64, scale: i64| (value * scale) >> REF_SCALE_SHIFT;
+    let frac_x = scaled((i64::from(mi_col) * 8 + block.offset_x) << 4, x_scale) & 15;
+    let frac_y = scaled((i64::from(mi_row) * 8 + block.offset_y) << 4, y_scale) & 15;
+    let start_x = (scaled(block.x, x_scale) << 4) + scaled(mv_col, x_scale) + frac_x;
+    let start_y = (scaled(block.y, y_scale) << 4) + scaled(mv_row, y_scale) + frac_y;
+
+    let last_x = i64::try_from(reference.width())? - 1;
+    let last_y = i64::try_from(reference.height())? - 1;
+    let stride = reference.stride();
+    let data = reference.data();
+    let max_value = i32::from(reference.max_value());
+    let sample = |x: i64, y: i64| -> Result<i32> {
+        let x = usize::try_from(x.clamp(0, last_x))?;
+        let y = usize::try_from(y.clamp(0, last_y))?;
+        Ok(i32::from(data[y * stride + x]))
+    };
+
+    // Horizontal filter into the intermediate buffer, starting 3 rows above the block.
+    let width = block.width;
+    let intermediate_height = (((block.height - 1) * y_step + 15) >> 4) + SUBPEL_TAPS;
+    let mut intermediate = [0i32; MAX_BLOCK_SIZE * MAX_INTERMEDIATE_HEIGHT];
+    for r in 0..intermediate_height {
+        let y = (start_y >> 4) + i64::try_from(r)? - 3;
+        for c in 0..width {
+            let position = start_x + i64::try_from(x_step * c)?;
+            let filter = &kernel[usize::try_from(position & 15)?];
+            let mut sum = 0;
+            for (t, tap) in filter.iter().enumerate() {
+                sum += tap * sample((position >> 4) + i64::try_from(t)? - 3, y)?;
+            }
+            intermediate[r * width + c] = round2(sum, 7).clamp(0, max_value);
+        }
+    }
+
+    // Vertical filter from the intermediate buffer.
+    let frac_start_y = usize::try_from(start_y & 15)?;
+    for r in 0..block.height {
+        let position = frac_start_y + y_step * r;
+        let filter = &kernel[position & 15];
+        let first_row = position >> 4;
+        for c in 0..width {
+            let mut sum = 0;
+            for (t, tap) in filter.iter().enumerate() {
+                sum += tap * intermediate[(first_row + t) * width + c];
+            }
+            pred[r * pred_stride + c] = u16::try_from(round2(sum, 7).clamp(0, max_value))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn round2(value: i32, n: u32) -> i32 {
+    (value + (1 << (n - 1))) >> n
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::{ivf::Ivf, Vp9Parser, LAST_FRAME};
+
+    /// Parses the first inter frame of the test stream (320x180, 4:2:0).
+    fn inter_frame() -> Frame {
+        let file = std::fs::File::open("tests/data/320-24-crf.ivf").unwrap();
+        let mut ivf = Ivf::new(file).unwrap();
+        let mut parser = Vp9Parser::default();
+        let _ = parser
+            .parse_packet(ivf.read_frame().unwrap().unwrap().packet)
+            .unwrap();
+        parser
+            .parse_packet(ivf.read_frame().unwrap().unwrap().packet)
+            .unwrap()
+            .remove(0)
+    }
+
+    /// Creates the planes of a reference frame with a sample value for every position.
+    fn reference(frame: &Frame, value: impl Fn(usize, usize) -> u16) -> Vec<Plane> {
+        (0..3)
+            .map(|plane_index| {
+                let mut plane = Plane::from_frame(frame, plane_index);
+                let stride = plane.stride();
+                for (i, sample) in plane.data_mut().iter_mut().enumerate() {
+                    *sample = value(i % stride, i / stride);
+                }
+                plane
+            })
+            .collect()
+    }
+
+    fn mode_info(mv: MotionVector, filter: InterpolationFilter) -> ModeInfo {
+        ModeInfo {
+            mi_row: 2,
+            mi_col: 2,
+            block_size: BlockSize::Block16x16,
+            is_inter: true,
+            ref_frame: [LAST_FRAME, INTRA_FRAME],
+            interp_filter: filter,
+            sub_mvs: [[mv, MotionVector::default()]; 4],
+            ..Default::default()
+        }
+    }
+
+    fn sample(plane: &Plane, x: usize, y: usize) -> u16 {
+        plane.data()[y * plane.stride() + x]
+    }
+
+    #[test]
+    fn filter_kernels() {
+        for kernel in SUBPEL_FILTERS.iter() {
+            assert_eq!(kernel[0], [0, 0, 0, 128, 0, 0, 0, 0]);
+            for filter in kernel.iter() {
+                assert_eq!(filter.iter().sum::<i32>(), 128);
+            }
+            for position in 1..16 {
+                let mirrored: Vec<i32> = kernel[16 - position].iter().rev().copied().collect();
+                assert_eq!(kernel[position].to_vec(), mirrored);
+            }
+        }
+    }
+
+    #[test]
+    fn motion_vectors() {
+        let frame = inter_frame();
+        let value = |x: usize, y: usize| u16::try_from((x * 3 + y * 7) % 256).unwrap();
+        let last = reference(&frame, value);
+        let references: [&[Plane]; 3] = [&last, &last, &last];
+        let mut planes: Vec<Plane> = (0..3).map(|i| Plane::from_frame(&frame, i)).collect();
+
+        // 2 luma samples down and 3 to the left. The chroma motion vector has 1/16 precision
+        // and lands between two samples horizontally.
+        let mv = MotionVector { row: 16, col: -24 };
+        let block = mode_info(mv, InterpolationFilter::Bilinear);
+        predict_inter(&mut planes, &frame, &block, &references).unwrap();
+        for y in 16..32 {
+            for x in 16..32 {
+                assert_eq!(sample(&planes[0], x, y), value(x - 3, y + 2));
+            }
+        }
+        for y in 8..16 {
+            for x in 8..16 {
+                let expected = (value(x - 2, y + 1) + value(x - 1, y + 1) + 1) >> 1;
+                assert_eq!(sample(&planes[1], x, y), expected);
+            }
+        }
+
+        // Far outside of the frame only the left column of the reference is used.
+        let mv = MotionVector { row: 0, col: -8000 };
+        for filter in [
+            InterpolationFilter::EighttapSmooth,
+            InterpolationFilter::Eighttap,
+            InterpolationFilter::EighttapSharp,
+        ] {
+            predict_inter(&mut planes, &frame, &mode_info(mv, filter), &references).unwrap();
+            for y in 16..32 {
+                for x in 16..32 {
+                    assert_eq!(sample(&planes[0], x, y), value(0, y));
+                }
+            }
+        }
+
+        let block = mode_info(mv, InterpolationFilter::Switchable);
+        assert!(matches!(
+            predict_inter(&mut planes, &frame, &block, &references),
+            Err(Vp9ParserError::InvalidInterpolationFilter(
+                InterpolationFilter::Switchable
+            ))
+        ));
+    }
+
+    #[test]
+    fn compound_prediction() {
+        let frame = inter_frame();
+        let last = reference(&frame, |_, _| 100);
+        let golden = reference(&frame, |_, _| 51);
+        let references: [&[Plane]; 3] = [&last, &golden, &last];
+        let mut planes: Vec<Plane> = (0..3).map(|i| Plane::from_frame(&frame, i)).collect();
+
+        let mut block = mode_info(
+            MotionVector { row: 5, col: 3 },
+            InterpolationFilter::Eighttap,
+        );
+        block.ref_frame = [LAST_FRAME, crate::GOLDEN_FRAME];
+        predict_inter(&mut planes, &frame, &block, &references).unwrap();
+        assert_eq!(sample(&planes[0], 20, 20), 76);
+        assert_eq!(sample(&planes[2], 10, 10), 76);
+    }
+
+    #[test]
+    fn sub8x8_motion_vectors() {
+        let mvs = [
+            MotionVector { row: -3, col: 5 },
+            MotionVector { row: -2, col: 6 },
+            MotionVector { row: -2, col: 7 },
+            MotionVector { row: -2, col: 1 },
+        ];
+        assert_eq!(average_mvs(&mvs), MotionVector { row: -2, col: 5 });
+        assert_eq!(average_mvs(&mvs[..2]), MotionVector { row: -3, col: 6 });
+
+        let mut block = mode_info(MotionVector::default(), InterpolationFilter::Eighttap);
+        block.block_size = BlockSize::Block4x4;
+        for (sub_mvs, mv) in block.sub_mvs.iter_mut().zip(mvs) {
+            sub_mvs[0] = mv;
+        }
+        assert_eq!(select_mv(&block, 0, 2, false, false), mvs[2]);
+        assert_eq!(select_mv(&block, 0, 0, true, true), average_mvs(&mvs));
+        // The second 4x4 block of 4:2:2 averages the motion vectors of blocks 1 and 2.
+        assert_eq!(
+            select_mv(&block, 0, 1, true, false),
+            average_mvs(&mvs[1..3])
+        );
+        assert_eq!(
+            select_mv(&block, 0, 1, false, true),
+            average_mvs(&[mvs[1], mvs[3]])
+        );
+    }
+
+    #[test]
+    fn scaled_reference() {
+        // A reference of twice the size is stepped through with two samples per sample.
+        let mut reference = Plane::new(640, 360, false, false, crate::ColorDepth::Depth12);
+        let stride = reference.stride();
+        for (i, sample) in reference.data_mut().iter_mut().enumerate() {
+            *sample = u16::try_from(i % stride + i / stride).unwrap();
+        }
+        let scale = RefScale::new(640, 360, 320, 180);
+        assert_eq!((scale.x_step(), scale.y_step()), (32, 32));
+
+        let block = PredictionBlock {
+            x: 16,
+            y: 8,
+            offset_x: 0,
+            offset_y: 0,
+            width: 8,
+            height: 8,
+        };
+        let mut pred = [0; 64];
+        predict_block(
+            &reference,
+            &scale,
+            &SUBPEL_FILTERS[1],
+            (2, 1),
+            block,
+            (0, 0),
+            &mut pred,
+            8,
+        )
+        .unwrap();
+        for (r, row) in pred.chunks_exact(8).enumerate() {
+            for (c, value) in row.iter().enumerate() {
+                assert_eq!(usize::from(*value), 32 + 2 * c + 16 + 2 * r);
+            }
+        }
+    }
+}
@@ -0,0 +1,635 @@
+//! Intra prediction as defined in spec "8.5.1 Intra prediction process".
+//!
+//! The prediction of a transform block uses the row above and the column left of the block.
+//! Unavailable edges are replaced by constants derived from the bit depth. Edges that reach
+//! past the decoded area of the frame repeat the last decoded sample. The above right edge is
+//! only read for 4x4 transforms whose right neighbour is part of the same block, all other
+//! transforms repeat the last sample of the above edge.
+
+use std::convert::TryFrom;
+
+use crate::{
+    tile, BlockSize, Frame, ModeInfo, Plane, PredictionMode, Result, TransformBlock, TxSize,
+    Vp9ParserError,
+};
+
+/// Availability of the edges of a transform block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EdgeAvailability {
+    have_left: bool,
+    have_above: bool,
+    have_right: bool,
+}
+
+impl EdgeAvailability {
+    /// Creates the edge availability from its parts.
+    pub fn new(have_left: bool, have_above: bool, have_right: bool) -> Self {
+        Self {
+            have_left,
+            have_above,
+            have_right,
+        }
+    }
+
+    /// Derives the edge availability of a transform block of the given block.
+    ///
+    /// The left edge is available inside the block and at the left side of the block if it
+    /// belongs to the same tile column. The above edge is available inside the block and below
+    /// the first row of the frame, tile rows don't restrict it. The above right edge is
+    /// available if the transform block doesn't touch the right side of the block.
+    pub fn for_transform_block(
+        frame: &Frame,
+        mode_info: &ModeInfo,
+        transform_block: &TransformBlock,
+    ) -> Self {
+        let (subsampling_x, subsampling_y) = if transform_block.plane > 0 {
+            (frame.subsampling_x(), frame.subsampling_y())
+        } else {
+            (false, false)
+        };
+
+        let block_x4 = (u32::from(mode_info.mi_col) * 2) >> u8::from(subsampling_x);
+        let block_y4 = (u32::from(mode_info.mi_row) * 2) >> u8::from(subsampling_y);
+        let offset_x4 = (transform_block.x >> 2).saturating_sub(block_x4);
+        let offset_y4 = (transform_block.y >> 2).saturating_sub(block_y4);
+        let width4 =
+            (u32::from(mode_info.block_size.num_8x8_wide()) * 2) >> u8::from(subsampling_x);
+        let tx_width4 = 1 << u8::from(transform_block.tx_size);
+
+        let tile_mi_col_start =
+            tile::tile_mi_col_start(mode_info.mi_col, frame.mi_cols(), frame.tile_cols_log2());
+
+        Self {
+            have_left: offset_x4 > 0 || mode_info.mi_col > tile_mi_col_start,
+            have_above: offset_y4 > 0 || mode_info.mi_row > 0,
+            have_right: offset_x4 + tx_width4 < width4,
+        }
+    }
+
+    /// `true` if the column left of the transform block is available.
+    pub fn have_left(&self) -> bool {
+        self.have_left
+    }
+
+    /// `true` if the row above the transform block is available.
+    pub fn have_above(&self) -> bool {
+        self.have_above
+    }
+
+    /// `true` if the row above and right of the transform block is available.
+    pub fn have_right(&self) -> bool {
+        self.have_right
+    }
+}
+
+/// Returns the intra prediction mode used by a transform block of the given block.
+///
+/// Luma transform blocks of blocks below 8x8 use the mode of the 4x4 sub-block they cover.
+pub fn transform_block_mode(
+    mode_info: &ModeInfo,
+    transform_block: &TransformBlock,
+) -> PredictionMode {
+    if transform_block.plane > 0 {
+        mode_info.uv_mode
+    } else if mode_info.block_size < BlockSize::Block8x8 {
+        let column = (transform_block.x >> 2) & 1;
+        let row = (transform_block.y >> 2) & 1;
+        mode_info.sub_modes[usize::from(row == 1) * 2 + usize::from(column == 1)]
+    } else {
+        mode_info.y_mode
+    }
+}
+
+/// Predicts a transform block of an intra block into its plane.
+pub fn predict_transform_block(
+    plane: &mut Plane,
+    frame: &Frame,
+    mode_info: &ModeInfo,
+    transform_block: &TransformBlock,
+) -> Result<()> {
+    predict_intra(
+        plane,
+        usize::try_from(transform_block.x)?,
+        usize::try_from(transform_block.y)?,
+        transform_block.tx_size,
+        transform_block_mode(mode_info, transform_block),
+        EdgeAvailability::for_transform_block(frame, mode_info, transform_block),
+    )
+}
+
+/// Predicts a square block of the transform size at the given position of the plane.
+///
+/// The samples of the edges are read from the plane, so the blocks left and above need to
+/// be reconstructed already. Returns an error if the mode is not an intra mode or if the
+/// block is not inside the decoded area of the plane.
+pub fn predict_intra(
+    plane: &mut Plane,
+    x: usize,
+    y: usize,
+    tx_size: TxSize,
+    mode: PredictionMode,
+    edges: EdgeAvailability,
+) -> Result<()> {
+    let size = 4 << u8::from(tx_size);
+    if x >= plane.decoded_width()
+        || y >= plane.decoded_height()
+        || !plane.contains(x, y, size, size)
+        || (edges.have_left && x == 0)
+        || (edges.have_above && y == 0)
+    {
+        return Err(Vp9ParserError::BlockOutsidePlane(x, y));
+    }
+
+    let base = 1 << (plane.bit_depth() - 1);
+    let stride = plane.stride();
+    let max_x = plane.decoded_width() - 1;
+    let max_y = plane.decoded_height() - 1;
+    let data = plane.data();
+
+    // above[0] is the above left sample, above[1 + i] is sample i of the above row.
+    let mut above = [0i32; 65];
+    let mut left = [0i32; 32];
+    if edges.have_above {
+        let row = (y - 1) * stride;
+        for i in 0..size {
+            above[1 + i] = i32::from(data[row + max_x.min(x + i)]);
+        }
+        for i in size..2 * size {
+            above[1 + i] = if edges.have_right && size == 4 {
+                i32::from(data[row + max_x.min(x + i)])
+            } else {
+                above[size]
+            };
+        }
+        above[0] = if edges.have_left {
+            i32::from(data[row + x - 1])
+        } else {
+            base + 1
+        };
+    } else {
+        above[..=2 * size].fill(base - 1);
+    }
+    if edges.have_left {
+        for (i, value) in left[..size].iter_mut().enumerate() {
+            *value = i32::from(data[max_y.min(y + i) * stride + x - 1]);
+        }
+    } else {
+        left[..size].fill(base + 1);
+    }
+
+    let mut pred = [0i32; 32 * 32];
+    predict(
+        &mut pred[..size * size],
+        size,
+        mode,
+        &above,
+        &left[..size],
+        edges,
+        base,
+    )?;
+
+    let max_value = i32::from(plane.max_value());
+    let data = plane.data_mut();
+    for (i, row) in pred[..size * size].chunks_exact(size).enumerate() {
+        let start = (y + i) * stride + x;
+        for (sample, value) in data[start..start + size].iter_mut().zip(row) {
+            *sample = u16::try_from((*value).clamp(0, max_value))?;
+        }
+    }
+
+    Ok(())
+}
+
+// Implements the predictors of spec "8.5.1.1 Intra prediction process" on prepared edges.
+fn predict(
+    pred: &mut [i32],
+    size: usize,
+    mode: PredictionMode,
+    above: &[i32; 65],
+    left: &[i32],
+    edges: EdgeAvailability,
+    base: i32,
+) -> Result<()> {
+    // Sample i of the above row.
+    let a = |i: usize| above[1 + i];
+    let top_left = above[0];
+
+    match mode {
+        PredictionMode::DcPred => {
+            let sum_above: i32 = above[1..=size].iter().sum();
+            let sum_left: i32 = left.iter().sum();
+            let count = i32::try_from(size)?;
+            let dc = match (edges.have_left, edges.have_above) {
+                (true, true) => (sum_above + sum_left + count) / (2 * count),
+                (false, true) => (sum_above + (count >> 1)) / count,
+                (true, false) => (sum_left + (count >> 1)) / count,
+                (false, false) => base,
+            };
+            pred.fill(dc);
+        }
+        PredictionMode::VPred => {
+            for row in pred.chunks_exact_mut(size) {
+                row.copy_from_slice(&above[1..=size]);
+            }
+        }
+        PredictionMode::HPred => {
+            for (row, value) in pred.chunks_exact_mut(size).zip(left) {
+                row.fill(*value);
+            }
+        }
+        PredictionMode::D45Pred => {
+            for i in 0..size {
+                for j in 0..size {
+                    pred[i * size + j] = if i + j + 2 < 2 * size {
+                        avg3(a(i + j), a(i + j + 1), a(i + j + 2))
+                    } else {
+                        a(2 * size - 1)
+                    };
+                }
+            }
+        }
+        PredictionMode::D135Pred => {
+            pred[0] = avg3(left[0], top_left, a(0));
+            for j in 1..size {
+                pred[j] = avg3(above[j - 1], a(j - 1), a(j));
+            }
+            pred[size] = avg3(top_left, left[0], left[1]);
+            for i in 2..size {
+                pred[i * size] = avg3(left[i - 2], left[i - 1], left[i]);
+            }
+            for i in 1..size {
+                for j in 1..size {
+                    pred[i * size + j] = pred[(i - 1) * size + j - 1];
+                }
+            }
+        }
+        PredictionMode::D117Pred => {
+            for j in 0..size {
+                pred[j] = avg2(above[j], a(j));
+            }
+            pred[size] = avg3(left[0], top_left, a(0));
+            for j in 1..size {
+                pred[size + j] = avg3(above[j - 1], above[j], a(j));
+            }
+            pred[2 * size] = avg3(top_left, left[0], left[1]);
+            for i in 3..size {
+                pred[i * size] = avg3(left[i - 3], left[i - 2], left[i - 1]);
+            }
+            for i in 2..size {
+                for j in 1..size {
+                    pred[i * size + j] = pred[(i - 2) * size + j - 1];
+                }
+            }
+        }
+        PredictionMode::D153Pred => {
+            pred[0] = avg2(left[0], top_left);
+            for i in 1..size {
+                pred[i * size] = avg2(left[i - 1], left[i]);
+            }
+            pred[1] = avg3(left[0], top_left, a(0));
+            pred[size + 1] = avg3(top_left, left[0], left[1]);
+            for i in 2..size {
+                pred[i * size + 1] = avg3(left[i - 2], left[i - 1], left[i]);
+            }
+            for j in 2..size {
+                pred[j] = avg3(above[j - 2], above[j - 1], above[j]);
+            }
+            for i in 1..size {
+                for j in 2..size {
+                    pred[i * size + j] = pred[(i - 1) * size + j - 2];
+                }
+            }
+        }
+        PredictionMode::D207Pred => {
+            for j in 0..size {
+                pred[(size - 1) * size + j] = left[size - 1];
+            }
+            for i in 0..size - 1 {
+                pred[i * size] = avg2(left[i], left[i + 1]);
+            }
+            for i in 0..size - 2 {
+                pred[i * size + 1] = avg3(left[i], left[i + 1], left[i + 2]);
+            }
+            pred[(size - 2) * size + 1] = avg3(left[size - 2], left[size - 1], left[size - 1]);
+            for i in (0..size - 1).rev() {
+                for j in 2..size {
+                    pred[i * size + j] = pred[(i + 1) * size + j - 2];
+                }
+            }
+        }
+        PredictionMode::D63Pred => {
+            for i in 0..size {
+                let i2 = i / 2;
+                for j in 0..size {
+                    pred[i * size + j] = if i & 1 == 1 {
+                        avg3(a(i2 + j), a(i2 + j + 1), a(i2 + j + 2))
+                    } else {
+                        avg2(a(i2 + j), a(i2 + j + 1))
+                    };
+                }
+            }
+        }
+        PredictionMode::TmPred => {
+            for (row, value) in pred.chunks_exact_mut(size).zip(left) {
+                for (j, sample) in row.iter_mut().enumerate() {
+                    *sample = value + a(j) - top_left;
+                }
+            }
+        }
+        PredictionMode::NearestMv
+        | PredictionMode::NearMv
+        | PredictionMode::ZeroMv
+        | PredictionMode::NewMv => return Err(Vp9ParserError::InvalidIntraMode(mode)),
+    }
+
+    Ok(())
+}
+
+fn avg2(a: i32, b: i32) -> i32 {
+    (a + b + 1) >> 1
+}
+
+fn avg3(a: i32, b: i32, c: i32) -> i32 {
+    (a + 2 * b + c + 2) >> 2
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::ColorDepth;
+
+    const LEFT: [u16; 4] = [10, 40, 90, 160];
+    const TOP_LEFT: u16 = 30;
+    const ABOVE: [u16; 8] = [50, 70, 20, 200, 120, 60, 250, 5];
+
+    /// Creates a plane with the edges of the 4x4 block at (4, 4).
+    fn plane_with_edges() -> Plane {
+        let mut plane = Plane::new(64, 64, false, false, ColorDepth::Depth8);
+        let stride = plane.stride();
+        let data = plane.data_mut();
+        data[3 * stride + 3] = TOP_LEFT;
+        data[3 * stride + 4..3 * stride + 12].copy_from_slice(&ABOVE);
+        for (i, value) in LEFT.iter().enumerate() {
+            data[(4 + i) * stride + 3] = *value;
+        }
+        plane
+    }
+
+    fn predict_4x4(mode: PredictionMode) -> [[u16; 4]; 4] {
+        let mut plane = plane_with_edges();
+        let edges = EdgeAvailability::new(true, true, true);
+        predict_intra(&mut plane, 4, 4, TxSize::Tx4x4, mode, edges).unwrap();
+        block(&plane, 4, 4)
+    }
+
+    fn block<const N: usize>(plane: &Plane, x: usize, y: usize) -> [[u16; N]; N] {
+        let mut block = [[0; N]; N];
+        for (i, row) in block.iter_mut().enumerate() {
+            let start = (y + i) * plane.stride() + x;
+            row.copy_from_slice(&plane.data()[start..start + N]);
+        }
+        block
+    }
+
+    fn avg2(a: u16, b: u16) -> u16 {
+        (a + b + 1) >> 1
+    }
+
+    fn avg3(a: u16, b: u16, c: u16) -> u16 {
+        (a + 2 * b + c + 2) >> 2
+    }
+
+    #[test]
+    fn directional_4x4() {
+        let [i, j, k, l] = LEFT;
+        let x = TOP_LEFT;
+        let [a, b, c, d, e, f, g, h] = ABOVE;
+
+        // The expected values follow the explicit 4x4 predictors of libvpx, indexed [row][column].
+        assert_eq!(
+            predict_4x4(PredictionMode::D45Pred),
+            [
+                [avg3(a, b, c), avg3(b, c, d), avg3(c, d, e), avg3(d, e, f)],
+                [avg3(b, c, d), avg3(c, d, e), avg3(d, e, f), avg3(e, f, g)],
+                [avg3(c, d, e), avg3(d, e, f), avg3(e, f, g), avg3(f, g, h)],
+                [avg3(d, e, f), avg3(e, f, g), avg3(f, g, h), h],
+            ]
+        );
+        assert_eq!(
+            predict_4x4(PredictionMode::D63Pred),
+            [
+                [avg2(a, b), avg2(b, c), avg2(c, d), avg2(d, e)],
+                [avg3(a, b, c), avg3(b, c, d), avg3(c, d, e), avg3(d, e, f)],
+                [avg2(b, c), avg2(c, d), avg2(d, e), avg2(e, f)],
+                [avg3(b, c, d), avg3(c, d, e), avg3(d, e, f), avg3(e, f, g)],
+            ]
+        );
+        assert_eq!(
+            predict_4x4(PredictionMode::D117Pred),
+            [
+                [avg2(x, a), avg2(a, b), avg2(b, c), avg2(c, d)],
+                [avg3(i, x, a), avg3(x, a, b), avg3(a, b, c), avg3(b, c, d)],
+                [avg3(j, i, x), avg2(x, a), avg2(a, b), avg2(b, c)],
+                [avg3(k, j, i), avg3(i, x, a), avg3(x, a, b), avg3(a, b, c)],
+            ]
+        );
+        assert_eq!(
+            predict_4x4(PredictionMode::D135Pred),
+            [
+                [avg3(a, x, i), avg3(b, a, x), avg3(c, b, a), avg3(d, c, b)],
+                [avg3(x, i, j), avg3(a, x, i), avg3(b, a, x), avg3(c, b, a)],
+                [avg3(i, j, k), avg3(x, i, j), avg3(a, x, i), avg3(b, a, x)],
+                [avg3(j, k, l), avg3(i, j, k), avg3(x, i, j), avg3(a, x, i)],
+            ]
+        );
+        assert_eq!(
+            predict_4x4(PredictionMode::D153Pred),
+            [
+                [avg2(i, x), avg3(i, x, a), avg3(x, a, b), avg3(a, b, c)],
+                [avg2(j, i), avg3(j, i, x), avg2(i, x), avg3(i, x, a)],
+                [avg2(k, j), avg3(k, j, i), avg2(j, i), avg3(j, i, x)],
+                [avg2(l, k), avg3(l, k, j), avg2(k, j), avg3(k, j, i)],
+            ]
+        );
+        assert_eq!(
+            predict_4x4(PredictionMode::D207Pred),
+            [
+                [avg2(i, j), avg3(i, j, k), avg2(j, k), avg3(j, k, l)],
+                [avg2(j, k), avg3(j, k, l), avg2(k, l), avg3(k, l, l)],
+                [avg2(k, l), avg3(k, l, l), l, l],
+                [l, l, l, l],
+            ]
+        );
+    }
+
+    #[test]
+    fn non_directional_4x4() {
+        let dc = (ABOVE[..4].iter().sum::<u16>() + LEFT.iter().sum::<u16>() + 4) / 8;
+        assert_eq!(predict_4x4(PredictionMode::DcPred), [[dc; 4]; 4]);
+        assert_eq!(predict_4x4(PredictionMode::VPred), [[50, 70, 20, 200]; 4]);
+        assert_eq!(
+            predict_4x4(PredictionMode::HPred),
+            [[10; 4], [40; 4], [90; 4], [160; 4]]
+        );
+        // left + above - top left, clamped to 8 bit.
+        assert_eq!(
+            predict_4x4(PredictionMode::TmPred),
+            [
+                [30, 50, 0, 180],
+                [60, 80, 30, 210],
+                [110, 130, 80, 255],
+                [180, 200, 150, 255],
+            ]
+        );
+        assert!(matches!(
+            predict_intra(
+                &mut plane_with_edges(),
+                4,
+                4,
+                TxSize::Tx4x4,
+                PredictionMode::NewMv,
+                EdgeAvailability::new(true, true, true),
+            ),
+            Err(Vp9ParserError::InvalidIntraMode(PredictionMode::NewMv))
+        ));
+    }
+
+    #[test]
+    fn unavailable_edges() {
+        for (color_depth, base) in [
+            (ColorDepth::Depth8, 128),
+            (ColorDepth::Depth10, 512),
+            (ColorDepth::Depth12, 2048),
+        ] {
+            let mut plane = Plane::new(64, 64, true, true, color_depth);
+            let none = EdgeAvailability::new(false, false, false);
+            for (mode, expected) in [
+                (PredictionMode::DcPred, base),
+                (PredictionMode::VPred, base - 1),
+                (PredictionMode::HPred, base + 1),
+                (PredictionMode::TmPred, base + 1),
+                (PredictionMode::D45Pred, base - 1),
+                (PredictionMode::D207Pred, base + 1),
+            ] {
+                predict_intra(&mut plane, 0, 0, TxSize::Tx16x16, mode, none).unwrap();
+                assert_eq!(block::<16>(&plane, 0, 0), [[expected; 16]; 16]);
+            }
+
+            // Only the left edge, DC averages the left column.
+            let stride = plane.stride();
+            for y in 0..8 {
+                plane.data_mut()[y * stride + 7] = 100 + u16::try_from(y).unwrap();
+            }
+            let left = EdgeAvailability::new(true, false, false);
+            predict_intra(
+                &mut plane,
+                8,
+                0,
+                TxSize::Tx8x8,
+                PredictionMode::DcPred,
+                left,
+            )
+            .unwrap();
+            assert_eq!(block::<8>(&plane, 8, 0), [[104; 8]; 8]);
+            // The above left sample is base + 1 if only the above edge is available.
+            let above = EdgeAvailability::new(false, true, false);
+            predict_intra(
+                &mut plane,
+                0,
+                16,
+                TxSize::Tx8x8,
+                PredictionMode::TmPred,
+                above,
+            )
+            .unwrap();
+            assert_eq!(block::<8>(&plane, 0, 16), [[base + 1; 8]; 8]);
+        }
+    }
+
+    #[test]
+    fn edge_extension() {
+        // 20x20 frame, the decoded area is 24x24 samples.
+        let mut plane = Plane::new(20, 20, false, false, ColorDepth::Depth10);
+        assert_eq!(
+            (plane.width(), plane.decoded_width(), plane.stride()),
+            (20, 24, 64)
+        );
+        let stride = plane.stride();
+        for x in 0..64 {
+            plane.data_mut()[15 * stride + x] = 1000 - u16::try_from(x).unwrap();
+        }
+        for y in 0..64 {
+            plane.data_mut()[y * stride + 15] = 10 * u16::try_from(y).unwrap();
+        }
+        let edges = EdgeAvailability::new(true, true, true);
+
+        // The above row repeats the last decoded sample at x = 23.
+        predict_intra(
+            &mut plane,
+            16,
+            16,
+            TxSize::Tx16x16,
+            PredictionMode::VPred,
+            edges,
+        )
+        .unwrap();
+        let expected: Vec<u16> = (16..32).map(|x: u16| 1000 - x.min(23)).collect();
+        assert_eq!(block::<16>(&plane, 16, 16)[5].to_vec(), expected);
+
+        // The left column repeats the last decoded sample at y = 23.
+        predict_intra(
+            &mut plane,
+            16,
+            16,
+            TxSize::Tx16x16,
+            PredictionMode::HPred,
+            edges,
+        )
+        .unwrap();
+        let expected: Vec<u16> = (16..32).map(|y: u16| 10 * y.min(23)).collect();
+        let column: Vec<u16> = block::<16>(&plane, 16, 16)
+            .iter()
+            .map(|row| row[0])
+            .collect();
+        assert_eq!(column, expected);
+
+        // Transforms larger than 4x4 never read the above right edge.
+        let mut plane = plane_with_edges();
+        predict_intra(
+            &mut plane,
+            4,
+            4,
+            TxSize::Tx8x8,
+            PredictionMode::D45Pred,
+            edges,
+        )
+        .unwrap();
+        assert_eq!(block::<8>(&plane, 4, 4)[7], [ABOVE[7]; 8]);
+        let mut plane = plane_with_edges();
+        let no_right = EdgeAvailability::new(true, true, false);
+        predict_intra(
+            &mut plane,
+            4,
+            4,
+            TxSize::Tx4x4,
+            PredictionMode::D45Pred,
+            no_right,
+        )
+        .unwrap();
+        assert_eq!(block::<4>(&plane, 4, 4)[3][3], ABOVE[3]);
+
+        assert!(matches!(
+            predict_intra(
+                &mut plane,
+                64,
+                0,
+                TxSize::Tx4x4,
+                PredictionMode::DcPred,
+                no_right
+            ),
+            Err(Vp9ParserError::BlockOutsidePlane(64, 0))
+        ));
+    }
+}
@@ -6,14 +6,49 @@
 #![deny(clippy::unwrap_used)]
 
 //! Provides tools to parse VP9 bitstreams and IVF containers.
-use std::{collections::HashMap, convert::TryInto};
+use std::{collections::HashMap, convert::TryInto, sync::Arc};
 
 use bitreader::BitReader;
+use frame_context::FRAME_CONTEXTS;
 
+pub use bool_decoder::BoolDecoder;
+pub use compressed_header::{CoefProbUpdates, CompressedHeader, ReferenceMode, TxMode};
+pub use decoder::{Picture, Vp9Decoder};
 pub use error::Vp9ParserError;
-
+pub use frame_context::{CoefProbs, FrameContext};
+pub use frame_parallel::FrameParallelDecoder;
+pub use mode_info::{
+    BlockMotionVector, BlockSize, ConcealedRegion, ModeInfo, ModeInfoGrid, MotionVector,
+    PartitionType, PredictionMode, SegmentMap, TxSize, TxType,
+};
+pub use plane::Plane;
+pub use residual::TransformBlock;
+pub use scale::{OutputSize, ScalingFilter};
+pub use tile::{Tile, Tiles};
+
+mod bool_decoder;
+mod compressed_header;
+pub mod debug;
+mod decoder;
+mod default_probs;
 mod error;
+mod frame_context;
+mod frame_parallel;
+pub mod inter_prediction;
+pub mod intra_prediction;
 pub mod ivf;
+pub mod loop_filter;
+pub mod md5;
+mod mode_info;
+mod plane;
+mod quantizer;
+mod residual;
+mod scale;
+mod scan;
+mod tile;
+mod tile_decoder;
+pub mod transform;
+pub mod y4m;
 
 type Result<T> = std::result::Result<T, Vp9ParserError>;
 
@@ -26,10 +61,20 @@ const MIN_TILE_WIDTH_B64: u8 = 4;
 /// Maximum width of a tile in units of super blocks.
 const MAX_TILE_WIDTH_B64: u8 = 64;
 
-const INTRA_FRAME: usize = 0;
-const LAST_FRAME: usize = 1;
-const GOLDEN_FRAME: usize = 2;
-const ALTREF_FRAME: usize = 3;
+/// Reference frame of intra blocks.
+pub const INTRA_FRAME: usize = 0;
+/// The LAST reference frame.
+pub const LAST_FRAME: usize = 1;
+/// The GOLDEN reference frame.
+pub const GOLDEN_FRAME: usize = 2;
+/// The ALTREF reference frame.
+pub const ALTREF_FRAME: usize = 3;
+
+/// Number of fractional bits of the reference scaling factors.
+const REF_SCALE_SHIFT: u32 = 14;
+
+/// Number of subpixel positions of the motion vectors.
+const SUBPEL_SHIFTS: u32 = 16;
 
 const SEG_LVL_ALT_Q: usize = 0;
 const SEG_LVL_ALT_L: usize = 1;
@@ -88,6 +133,17 @@ pub enum Subsampling {
     Yuv420,
 }
 
+impl Subsampling {
+    fn from_flags(subsampling_x: bool, subsampling_y: bool) -> Self {
+        match (subsampling_x, subsampling_y) {
+            (false, false) => Subsampling::Yuv444,
+            (false, true) => Subsampling::Yuv440,
+            (true, false) => Subsampling::Yuv422,
+            (true, true) => Subsampling::Yuv420,
+        }
+    }
+}
+
 /// Chroma subsampling as defined in the Metadata
 #[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub enum MetadataSubsampling {
@@ -391,6 +447,7 @@ pub struct Frame {
     reset_frame_context: ResetFrameContext,
     ref_frame_indices: [u8; 3],
     ref_frame_sign_bias: [bool; 4],
+    ref_scales: Option<[RefScale; 3]>,
     allow_high_precision_mv: bool,
     refresh_frame_context: bool,
     refresh_frame_flags: u8,
@@ -434,6 +491,14 @@ pub struct Frame {
     segmentation_abs_or_delta_update: bool,
     segment_feature_enabled: [[bool; 4]; 8],
     segment_feature_data: [[i16; 4]; 8],
+    compressed_header: Option<CompressedHeader>,
+    frame_context: Option<FrameContext>,
+    mode_info: Option<ModeInfoGrid>,
+    transform_blocks: Option<Vec<TransformBlock>>,
+    motion_vectors: Option<Vec<BlockMotionVector>>,
+    segment_map: Option<SegmentMap>,
+    concealed_region: Option<ConcealedRegion>,
+    tile_error: Option<Arc<Vp9ParserError>>,
 }
 
 impl Frame {
@@ -458,6 +523,7 @@ impl Frame {
             reset_frame_context: parser.reset_frame_context,
             ref_frame_indices: parser.ref_frame_indices,
             ref_frame_sign_bias: parser.ref_frame_sign_bias,
+            ref_scales: parser.ref_scales,
             allow_high_precision_mv: parser.allow_high_precision_mv,
             refresh_frame_context: parser.refresh_frame_context,
             refresh_frame_flags: parser.refresh_frame_flags,
@@ -501,6 +567,14 @@ impl Frame {
             segmentation_abs_or_delta_update: parser.segmentation_abs_or_delta_update,
             segment_feature_enabled: parser.segment_feature_enabled,
             segment_feature_data: parser.segment_feature_data,
+            compressed_header: None,
+            frame_context: None,
+            mode_info: None,
+            transform_blocks: None,
+            motion_vectors: None,
+            segment_map: None,
+            concealed_region: None,
+            tile_error: None,
         }
     }
 
@@ -520,6 +594,14 @@ impl Frame {
         &self.data[self.uncompressed_header_size + self.compressed_header_size..self.data.len()]
     }
 
+    /// Returns an iterator over the tiles of the tile data.
+    ///
+    /// Returns an error for a tile if its tile size marker is truncated
+    /// or if the tile size exceeds the remaining tile data.
+    pub fn tiles(&self) -> Tiles<'_> {
+        Tiles::new(self)
+    }
+
     /// The profile the frame is using.
     pub fn profile(&self) -> Profile {
         self.profile
@@ -597,6 +679,12 @@ impl Frame {
         &self.ref_frame_sign_bias
     }
 
+    /// The scaling factors of the last, golden and alt reference frame.
+    /// Only available for inter frames.
+    pub fn ref_scales(&self) -> Option<&[RefScale; 3]> {
+        self.ref_scales.as_ref()
+    }
+
     /// Specifies the precision of the motion vectors.
     ///
     /// False = quarter precision, True = eighth precision.
@@ -660,15 +748,7 @@ impl Frame {
 
     /// The subsampling the frame is using.
     pub fn subsampling(&self) -> Subsampling {
-        if !self.subsampling_x && !self.subsampling_y {
-            Subsampling::Yuv444
-        } else if !self.subsampling_x && self.subsampling_y {
-            Subsampling::Yuv440
-        } else if self.subsampling_x && !self.subsampling_y {
-            Subsampling::Yuv422
-        } else {
-            Subsampling::Yuv420
-        }
+        Subsampling::from_flags(self.subsampling_x, self.subsampling_y)
     }
 
     /// Indicates if sub sampling is used along the x axis.
@@ -838,6 +918,72 @@ impl Frame {
         &self.segment_feature_data
     }
 
+    /// The parsed compressed header. Frames that show an existing frame have no compressed header.
+    pub fn compressed_header(&self) -> Option<&CompressedHeader> {
+        self.compressed_header.as_ref()
+    }
+
+    /// The probabilities used to decode the frame. They consist of the loaded frame context
+    /// with the updates of the compressed header applied. Frames that show an existing frame
+    /// have no probabilities.
+    pub fn frame_context(&self) -> Option<&FrameContext> {
+        self.frame_context.as_ref()
+    }
+
+    /// The mode info of the decoded blocks in mode info units. Only available if the tile
+    /// decoding or the motion vector export is enabled. Frames that show an existing frame
+    /// and frames whose tile data fails to decode have no mode info.
+    pub fn mode_info(&self) -> Option<&ModeInfoGrid> {
+        self.mode_info.as_ref()
+    }
+
+    /// The transform blocks of all planes with their dequantized coefficients in decoding
+    /// order. Only available if the tile decoding is enabled with
+    /// `Vp9Parser::set_decode_tiles()` and the mode info is available.
+    pub fn transform_blocks(&self) -> Option<&[TransformBlock]> {
+        self.transform_blocks.as_deref()
+    }
+
+    /// The motion vectors of the inter blocks in raster order of the blocks, with one entry
+    /// per sub-block below 8x8 and per reference frame. Only available if the export is
+    /// enabled with `Vp9Parser::set_export_mvs()` and the mode info is available.
+    pub fn motion_vectors(&self) -> Option<&[BlockMotionVector]> {
+        self.motion_vectors.as_deref()
+    }
+
+    /// The segment ids of the blocks, which select the segmentation features like the
+    /// quantizer. All ids are 0 if segmentation is disabled. Available whenever the mode info
+    /// is available.
+    pub fn segment_map(&self) -> Option<&SegmentMap> {
+        self.segment_map.as_ref()
+    }
+
+    /// The superblocks that failed to decode and were concealed. Only available if the error
+    /// concealment is enabled with `Vp9Parser::set_error_concealment()`.
+    pub fn concealed_region(&self) -> Option<&ConcealedRegion> {
+        self.concealed_region.as_ref()
+    }
+
+    /// The error that stopped the decoding of the tile data. The frame has no mode info in
+    /// this case, but its headers are valid.
+    pub fn tile_error(&self) -> Option<&Vp9ParserError> {
+        self.tile_error.as_deref()
+    }
+
+    /// Returns the error to report when the mode info of the frame is needed but missing: the
+    /// stored tile error if the tile data failed to decode, `MissingTileData` otherwise.
+    pub(crate) fn missing_tile_data(&self) -> Vp9ParserError {
+        self.tile_error.clone().map_or(
+            Vp9ParserError::MissingTileData,
+            Vp9ParserError::TileDataError,
+        )
+    }
+
+    /// Indicates that the frame only uses intra prediction (spec `FrameIsIntra`).
+    pub(crate) fn is_intra(&self) -> bool {
+        self.frame_type == FrameType::KeyFrame || self.intra_only
+    }
+
     /// Destroys the frame and returns the underlying data buffer.
     pub fn into_vec(self) -> Vec<u8> {
         let Frame { data, .. } = self;
@@ -845,10 +991,136 @@ impl Frame {
     }
 }
 
+/// The scaling of a reference frame as defined in spec "8.5.2.3 Motion vector scaling process".
+///
+/// The reference frame can be at most two times larger and at most 16 times smaller
+/// than the frame that is referencing it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RefScale {
+    x_scale: u32,
+    y_scale: u32,
+    x_step: u32,
+    y_step: u32,
+}
+
+impl RefScale {
+    fn new(ref_width: u16, ref_height: u16, width: u16, height: u16) -> Self {
+        let x_scale = (u32::from(ref_width) << REF_SCALE_SHIFT) / u32::from(width);
+        let y_scale = (u32::from(ref_height) << REF_SCALE_SHIFT) / u32::from(height);
+        Self {
+            x_scale,
+            y_scale,
+            x_step: (SUBPEL_SHIFTS * x_scale) >> REF_SCALE_SHIFT,
+            y_step: (SUBPEL_SHIFTS * y_scale) >> REF_SCALE_SHIFT,
+        }
+    }
+
+    /// The horizontal scaling factor (`xScale`) with 14 fractional bits.
+    pub fn x_scale(&self) -> u32 {
+        self.x_scale
+    }
+
+    /// The vertical scaling factor (`yScale`) with 14 fractional bits.
+    pub fn y_scale(&self) -> u32 {
+        self.y_scale
+    }
+
+    /// The horizontal step (`xStep`) between predicted samples in 1/16 sample units.
+    pub fn x_step(&self) -> u32 {
+        self.x_step
+    }
+
+    /// The vertical step (`yStep`) between predicted samples in 1/16 sample units.
+    pub fn y_step(&self) -> u32 {
+        self.y_step
+    }
+
+    /// Indicates if the reference frame has a different size than the frame.
+    pub fn is_scaled(&self) -> bool {
+        self.x_scale != 1 << REF_SCALE_SHIFT || self.y_scale != 1 << REF_SCALE_SHIFT
+    }
+}
+
+/// The state of a reference frame slot.
+///
+/// The parser keeps eight slots which are refreshed by the frames as signaled
+/// by their `refresh_frame_flags`.
+#[derive(Clone, Copy, Debug)]
+pub struct RefSlot {
+    width: u16,
+    height: u16,
+    subsampling_x: bool,
+    subsampling_y: bool,
+    color_depth: ColorDepth,
+    frame_index: u64,
+    filled: bool,
+}
+
+impl Default for RefSlot {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            subsampling_x: true,
+            subsampling_y: true,
+            color_depth: ColorDepth::Depth8,
+            frame_index: 0,
+            filled: false,
+        }
+    }
+}
+
+impl RefSlot {
+    /// The width of the frame stored in the slot.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// The height of the frame stored in the slot.
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// The subsampling of the frame stored in the slot.
+    pub fn subsampling(&self) -> Subsampling {
+        Subsampling::from_flags(self.subsampling_x, self.subsampling_y)
+    }
+
+    /// Indicates if sub sampling is used along the x axis.
+    pub fn subsampling_x(&self) -> bool {
+        self.subsampling_x
+    }
+
+    /// Indicates if sub sampling is used along the y axis.
+    pub fn subsampling_y(&self) -> bool {
+        self.subsampling_y
+    }
+
+    /// The color depth of the frame stored in the slot.
+    pub fn color_depth(&self) -> ColorDepth {
+        self.color_depth
+    }
+
+    /// The index of the frame that last refreshed the slot.
+    ///
+    /// Frames are counted in decoding order, starting with zero after the creation
+    /// or the reset of the parser. Every frame returned by the parser is counted.
+    pub fn frame_index(&self) -> u64 {
+        self.frame_index
+    }
+
+    /// Indicates if the slot was ever refreshed by a frame.
+    pub fn filled(&self) -> bool {
+        self.filled
+    }
+}
+
 /// Parses VP9 bitstreams.
 #[derive(Clone, Debug)]
 pub struct Vp9Parser {
-    ref_frame_sizes: [(u16, u16); 8],
+    ref_slots: [RefSlot; 8],
+    frame_index: u64,
+    frame_contexts: [FrameContext; FRAME_CONTEXTS],
     profile: Profile,
     show_existing_frame: bool,
     frame_to_show_map_idx: Option<u8>,
@@ -860,6 +1132,7 @@ pub struct Vp9Parser {
     reset_frame_context: ResetFrameContext,
     ref_frame_indices: [u8; 3],
     ref_frame_sign_bias: [bool; 4],
+    ref_scales: Option<[RefScale; 3]>,
     allow_high_precision_mv: bool,
     refresh_frame_context: bool,
     refresh_frame_flags: u8,
@@ -900,12 +1173,20 @@ pub struct Vp9Parser {
     segmentation_abs_or_delta_update: bool,
     segment_feature_enabled: [[bool; 4]; 8],
     segment_feature_data: [[i16; 4]; 8],
+    prev_mode_info: Option<ModeInfoGrid>,
+    segment_map: Option<SegmentMap>,
+    threads: usize,
+    decode_tiles: bool,
+    export_mvs: bool,
+    error_concealment: bool,
 }
 
 impl Default for Vp9Parser {
     fn default() -> Self {
         Self {
-            ref_frame_sizes: [(0u16, 0u16); 8],
+            ref_slots: [RefSlot::default(); 8],
+            frame_index: 0,
+            frame_contexts: Default::default(),
             show_existing_frame: false,
             frame_to_show_map_idx: None,
             profile: Profile::Profile0,
@@ -918,6 +1199,7 @@ impl Default for Vp9Parser {
             refresh_frame_flags: 0,
             ref_frame_indices: [0u8; 3],
             ref_frame_sign_bias: [false; 4],
+            ref_scales: None,
             allow_high_precision_mv: false,
             refresh_frame_context: false,
             frame_parallel_decoding_mode: true,
@@ -957,6 +1239,12 @@ impl Default for Vp9Parser {
             segmentation_abs_or_delta_update: false,
             segment_feature_enabled: [[false; 4]; 8],
             segment_feature_data: [[0i16; 4]; 8],
+            prev_mode_info: None,
+            segment_map: None,
+            threads: 1,
+            decode_tiles: false,
+            export_mvs: false,
+            error_concealment: false,
         }
     }
 }
@@ -968,8 +1256,96 @@ impl Vp9Parser {
     }
 
     /// Resets the state of the parser. Used when switching the bitstream or seeking.
+    ///
+    /// The number of threads, the tile decoding, the motion vector export and the error
+    /// concealment are kept.
     pub fn reset(&mut self) {
-        *self = Vp9Parser::default();
+        *self = Vp9Parser {
+            threads: self.threads,
+            decode_tiles: self.decode_tiles,
+            export_mvs: self.export_mvs,
+            error_concealment: self.error_concealment,
+            ..Default::default()
+        };
+    }
+
+    /// The number of threads used to decode the tile data.
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Sets the number of threads used to decode the tile data. Defaults to 1.
+    ///
+    /// Tile columns can be decoded independently, so frames with more than one tile column
+    /// decode them concurrently. The results don't depend on the number of threads.
+    /// A value of 0 is treated as 1.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Indicates if the tile data of the frames is decoded.
+    pub fn decode_tiles(&self) -> bool {
+        self.decode_tiles
+    }
+
+    /// Enables the decoding of the tile data into the mode info, the segment map and the
+    /// transform blocks of the frames. Disabled by default, in which case only the headers
+    /// are parsed.
+    ///
+    /// The probabilities of later frames are adapted to the decoded symbols, so the decoding
+    /// needs to be enabled before the first frame of the stream or after `reset()`.
+    pub fn set_decode_tiles(&mut self, decode_tiles: bool) {
+        self.decode_tiles = decode_tiles;
+    }
+
+    /// Indicates if the frames carry the motion vectors of their inter blocks.
+    pub fn export_mvs(&self) -> bool {
+        self.export_mvs
+    }
+
+    /// Enables the export of the motion vectors of the inter blocks with
+    /// `Frame::motion_vectors()`. Disabled by default.
+    ///
+    /// The motion vectors are taken from the decoded mode info, so no reconstruction is needed.
+    /// Unless the tile decoding is enabled with `set_decode_tiles()` as well, only the mode
+    /// info is kept: the coefficient tokens are read, but neither dequantized nor stored. Like
+    /// the tile decoding, the export needs to be enabled before the first frame of the stream.
+    pub fn set_export_mvs(&mut self, export_mvs: bool) {
+        self.export_mvs = export_mvs;
+    }
+
+    /// Indicates if superblocks that fail to decode are concealed.
+    pub fn error_concealment(&self) -> bool {
+        self.error_concealment
+    }
+
+    /// Enables the concealment of corrupted or truncated tile data. Disabled by default.
+    ///
+    /// Without it, tile data that fails to decode leaves the frame without mode info. With
+    /// it, the superblocks from the first one that fails to decode to the end of its tile
+    /// are replaced by the co-located area of `LAST_FRAME`, or by mid-grey in intra frames,
+    /// and are reported by `Frame::concealed_region()`. Like in libvpx, frames with concealed
+    /// superblocks don't adapt the probabilities.
+    pub fn set_error_concealment(&mut self, error_concealment: bool) {
+        self.error_concealment = error_concealment;
+    }
+
+    /// The segment map that the segment ids of the next frame are predicted from. `None` if
+    /// all ids are 0, which is the case after key frames, intra only frames, error resilient
+    /// frames and size changes until a frame updates the map.
+    pub fn segment_map(&self) -> Option<&SegmentMap> {
+        self.segment_map.as_ref()
+    }
+
+    /// The state of the eight reference frame slots.
+    pub fn ref_slots(&self) -> &[RefSlot; 8] {
+        &self.ref_slots
+    }
+
+    /// The four saved frame contexts. They hold the probabilities that the following frames
+    /// load before applying the updates of their compressed header.
+    pub fn frame_contexts(&self) -> &[FrameContext; FRAME_CONTEXTS] {
+        &self.frame_contexts
     }
 
     /// Parses a VP9 bitstream packet and returns the encoded frames.
@@ -1078,6 +1454,12 @@ impl Vp9Parser {
     }
 
     fn parse_frame(&mut self, data: Vec<u8>) -> Result<Frame> {
+        let frame = self.parse_frame_data(data)?;
+        self.frame_index += 1;
+        Ok(frame)
+    }
+
+    fn parse_frame_data(&mut self, data: Vec<u8>) -> Result<Frame> {
         let mut br = BitReader::new(&data);
 
         let frame_marker = br.read_u8(2)?;
@@ -1095,9 +1477,16 @@ impl Vp9Parser {
         self.show_existing_frame = br.read_bool()?;
 
         if self.show_existing_frame {
-            self.frame_to_show_map_idx = Some(br.read_u8(3)?);
+            let frame_to_show_map_idx = br.read_u8(3)?;
+            if !self.ref_slots[usize::from(frame_to_show_map_idx)].filled {
+                return Err(Vp9ParserError::EmptyRefSlot(frame_to_show_map_idx));
+            }
+            self.frame_to_show_map_idx = Some(frame_to_show_map_idx);
             self.refresh_frame_flags = 0;
             self.loop_filter_level = 0;
+            // Like libvpx, the shown frame counts as the last shown frame for the motion vector
+            // prediction of the next frame. The previous mode info stays unchanged.
+            self.show_frame = true;
 
             let frame = Frame::new(self, 0, 0, 0, vec![]);
             return Ok(frame);
@@ -1105,13 +1494,20 @@ impl Vp9Parser {
             self.frame_to_show_map_idx = None;
         }
 
+        // The previous frame provides motion vector candidates if it was shown and has the same size.
+        let last_show_frame = self.show_frame;
+        let last_intra_only = self.intra_only;
+        let (last_width, last_height) = (self.width, self.height);
+
         self.last_frame_type = self.frame_type;
         self.frame_type = br.read_bool()?.into();
 
         self.show_frame = br.read_bool()?;
         self.error_resilient_mode = br.read_bool()?;
 
+        self.ref_scales = None;
         if self.frame_type == FrameType::KeyFrame {
+            self.intra_only = false;
             self.frame_sync_code(&mut br)?;
             self.color_config(&mut br)?;
             self.frame_size(&mut br)?;
@@ -1149,7 +1545,9 @@ impl Vp9Parser {
                     self.ref_frame_indices[i] = br.read_u8(3)?;
                     self.ref_frame_sign_bias[LAST_FRAME + i] = br.read_bool()?;
                 }
+                self.validate_ref_slots()?;
                 self.frame_size_with_refs(&mut br)?;
+                self.ref_scales = Some(self.compute_ref_scales()?);
                 self.allow_high_precision_mv = br.read_bool()?;
                 self.read_interpolation_filter(&mut br)?;
             }
@@ -1165,18 +1563,8 @@ impl Vp9Parser {
 
         self.frame_context_idx = br.read_u8(2)?;
 
-        if self.intra_only || self.error_resilient_mode {
-            self.frame_context_idx = 0
-        }
-
         if self.frame_type == FrameType::KeyFrame || self.error_resilient_mode || self.intra_only {
-            // Reset the loop filter deltas.
-            self.loop_filter_ref_deltas[INTRA_FRAME] = 1;
-            self.loop_filter_ref_deltas[LAST_FRAME] = 0;
-            self.loop_filter_ref_deltas[GOLDEN_FRAME] = -1;
-            self.loop_filter_ref_deltas[ALTREF_FRAME] = -1;
-            self.loop_filter_mode_deltas[0] = 0;
-            self.loop_filter_mode_deltas[1] = 0;
+            self.setup_past_independence();
         }
         self.loop_filter_params(&mut br)?;
 
@@ -1189,35 +1577,168 @@ impl Vp9Parser {
         let uncompressed_header_size: usize = (br.position() / 8).try_into()?;
 
         let size = data.len();
+        if compressed_header_size == 0 || uncompressed_header_size + compressed_header_size > size {
+            return Err(Vp9ParserError::InvalidCompressedHeaderSize(
+                compressed_header_size,
+            ));
+        }
         let tile_size = size - (uncompressed_header_size + compressed_header_size);
 
-        let frame = Frame::new(
+        let mut frame = Frame::new(
             self,
             uncompressed_header_size,
             compressed_header_size,
             tile_size,
             data,
         );
+        let compressed_header = CompressedHeader::new(&frame)?;
+
+        let frame_context_idx = usize::from(self.frame_context_idx);
+        let mut frame_context = self.frame_contexts[frame_context_idx].clone();
+        frame_context.apply_updates(&compressed_header);
+
+        let use_prev_frame_mvs = !self.error_resilient_mode
+            && self.width == last_width
+            && self.height == last_height
+            && !last_intra_only
+            && last_show_frame;
+        let prev_mode_info = self.prev_mode_info.as_ref().filter(|_| use_prev_frame_mvs);
+        // The segment map is sized in mode info units and starts over with a new frame size.
+        if self.width != last_width || self.height != last_height {
+            self.segment_map = None;
+        }
+        // Tile data that fails to decode leaves the frame without mode info and stores the error
+        // on the frame. The headers stay usable. Without the tile decoding and the motion vector
+        // export, the tile data isn't decoded at all.
+        let decoded_tiles = (self.decode_tiles || self.export_mvs).then(|| {
+            tile_decoder::decode_tiles(
+                &frame,
+                &compressed_header,
+                &frame_context,
+                prev_mode_info,
+                self.segment_map.as_ref(),
+                self.threads,
+                self.error_concealment,
+                self.decode_tiles,
+            )
+        });
+        let counts = match decoded_tiles {
+            None => None,
+            Some(Ok(decoded)) => {
+                let mode_info = decoded.mode_info;
+                if self.export_mvs {
+                    frame.motion_vectors = Some(mode_info.motion_vectors());
+                }
+                // Like libvpx, the map is only replaced if the frame updates it. Otherwise the
+                // blocks predict their ids from the kept map.
+                let segment_map = SegmentMap::from_mode_info(&mode_info);
+                if self.segmentation_enabled && self.segmentation_update_map {
+                    self.segment_map = Some(segment_map.clone());
+                }
+                frame.concealed_region =
+                    ConcealedRegion::new(decoded.concealed_superblocks, self.mi_rows, self.mi_cols);
+                frame.segment_map = Some(segment_map);
+                frame.mode_info = Some(mode_info);
+                frame.transform_blocks = self.decode_tiles.then_some(decoded.transform_blocks);
+                Some(decoded.counts).filter(|_| frame.concealed_region.is_none())
+            }
+            Some(Err(err)) => {
+                frame.tile_error = Some(Arc::new(err));
+                None
+            }
+        };
+        // A frame that failed to decode keeps the motion vectors of the last decoded frame.
+        if frame.mode_info.is_some() {
+            self.prev_mode_info = frame.mode_info.clone();
+        }
+
+        // Implements spec "8.4.2 Coefficient probability adaptation process" and "8.4.3 Non
+        // coefficient probability adaptation process". Frames without decoded tile data and
+        // frames with concealed superblocks save the probabilities they were decoded with.
+        if self.refresh_frame_context {
+            let mut saved_context = frame_context.clone();
+            if let Some(counts) = counts
+                .as_ref()
+                .filter(|_| !self.error_resilient_mode && !self.frame_parallel_decoding_mode)
+            {
+                saved_context.adapt(
+                    &self.frame_contexts[frame_context_idx],
+                    counts,
+                    &frame,
+                    &compressed_header,
+                );
+            }
+            self.frame_contexts[frame_context_idx] = saved_context;
+        }
+
+        frame.compressed_header = Some(compressed_header);
+        frame.frame_context = Some(frame_context);
 
         self.refresh_ref_frames();
 
         Ok(frame)
     }
 
+    // Implements spec "setup_past_independence()" and the reset of the saved frame contexts.
+    fn setup_past_independence(&mut self) {
+        self.segment_map = None;
+        self.segment_feature_enabled = [[false; 4]; MAX_SEGMENTS];
+        self.segment_feature_data = [[0; 4]; MAX_SEGMENTS];
+        self.segmentation_abs_or_delta_update = false;
+
+        self.loop_filter_ref_deltas[INTRA_FRAME] = 1;
+        self.loop_filter_ref_deltas[LAST_FRAME] = 0;
+        self.loop_filter_ref_deltas[GOLDEN_FRAME] = -1;
+        self.loop_filter_ref_deltas[ALTREF_FRAME] = -1;
+        self.loop_filter_mode_deltas[0] = 0;
+        self.loop_filter_mode_deltas[1] = 0;
+
+        if self.frame_type == FrameType::KeyFrame
+            || self.error_resilient_mode
+            || self.reset_frame_context == ResetFrameContext::FullReset
+        {
+            self.frame_contexts = Default::default();
+        } else if self.reset_frame_context == ResetFrameContext::SingleReset {
+            self.frame_contexts[usize::from(self.frame_context_idx)] = FrameContext::default();
+        }
+        self.frame_context_idx = 0;
+    }
+
     // Implements spec "8.10 Reference frame update process".
     fn refresh_ref_frames(&mut self) {
         let flags = self.refresh_frame_flags;
-        let new_width = self.width;
-        let new_height = self.height;
-        self.ref_frame_sizes
-            .iter_mut()
-            .enumerate()
-            .for_each(|(i, (width, height))| {
-                if (flags >> i) & 1 == 1 {
-                    *width = new_width;
-                    *height = new_height;
-                }
-            });
+        let new_slot = RefSlot {
+            width: self.width,
+            height: self.height,
+            subsampling_x: self.subsampling_x,
+            subsampling_y: self.subsampling_y,
+            color_depth: self.color_depth,
+            frame_index: self.frame_index,
+            filled: true,
+        };
+        self.ref_slots.iter_mut().enumerate().for_each(|(i, slot)| {
+            if (flags >> i) & 1 == 1 {
+                *slot = new_slot;
+            }
+        });
+    }
+
+    // Checks that the references of an inter frame are filled and use the same color format.
+    fn validate_ref_slots(&self) -> Result<()> {
+        for index in self.ref_frame_indices {
+            let slot = &self.ref_slots[usize::from(index)];
+            if !slot.filled {
+                return Err(Vp9ParserError::EmptyRefSlot(index));
+            }
+            if slot.color_depth != self.color_depth {
+                return Err(Vp9ParserError::RefSlotColorDepthMismatch(index));
+            }
+            if slot.subsampling_x != self.subsampling_x || slot.subsampling_y != self.subsampling_y
+            {
+                return Err(Vp9ParserError::RefSlotSubsamplingMismatch(index));
+            }
+        }
+        Ok(())
     }
 
     fn frame_sync_code(&self, br: &mut BitReader) -> Result<()> {
@@ -1299,13 +1820,13 @@ impl Vp9Parser {
         for i in 0..3 {
             found_ref = br.read_bool()?;
             if found_ref {
-                let sizes = *self
-                    .ref_frame_sizes
+                let slot = self
+                    .ref_slots
                     .get(usize::from(self.ref_frame_indices[i]))
                     .ok_or(Vp9ParserError::InvalidRefFrameIndex)?;
 
-                self.width = sizes.0;
-                self.height = sizes.1;
+                self.width = slot.width;
+                self.height = slot.height;
                 break;
             }
         }
@@ -1321,6 +1842,29 @@ impl Vp9Parser {
         Ok(())
     }
 
+    // Implements the scale factors of spec "8.5.2.3 Motion vector scaling process".
+    fn compute_ref_scales(&self) -> Result<[RefScale; 3]> {
+        let mut ref_scales = [RefScale::new(1, 1, 1, 1); 3];
+        for (ref_scale, index) in ref_scales.iter_mut().zip(self.ref_frame_indices) {
+            let slot = &self.ref_slots[usize::from(index)];
+            let width = u32::from(self.width);
+            let height = u32::from(self.height);
+            let ref_width = u32::from(slot.width);
+            let ref_height = u32::from(slot.height);
+            if self.width == 0
+                || self.height == 0
+                || 2 * width < ref_width
+                || 2 * height < ref_height
+                || width > 16 * ref_width
+                || height > 16 * ref_height
+            {
+                return Err(Vp9ParserError::InvalidRefFrameScale(index));
+            }
+            *ref_scale = RefScale::new(slot.width, slot.height, self.width, self.height);
+        }
+        Ok(ref_scales)
+    }
+
     fn compute_image_size(&mut self) {
         self.mi_cols = (self.width + 7) >> 3;
         self.mi_rows = (self.height + 7) >> 3;
@@ -1489,7 +2033,7 @@ impl Vp9Parser {
 
     // Aligns the reader to the next byte offset.
     fn trailing_bits(&self, br: &mut BitReader) -> Result<()> {
-        while br.is_aligned(1) {
+        while !br.is_aligned(1) {
             let zero_bit = br.read_bool()?;
             if zero_bit {
                 return Err(Vp9ParserError::InvalidPadding);
@@ -1547,4 +2091,182 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn validate_ref_slots() -> Result<()> {
+        let mut ivf = ivf::Ivf::new(std::fs::File::open("tests/data/320-24-crf.ivf")?).unwrap();
+        let key_frame = ivf.read_frame().unwrap().unwrap();
+        let inter_frame = ivf.read_frame().unwrap().unwrap();
+
+        let mut parser = Vp9Parser::default();
+        let _ = parser.parse_packet(key_frame.packet)?;
+
+        let mut mismatched = parser.clone();
+        mismatched.ref_slots[0].color_depth = ColorDepth::Depth10;
+        assert!(matches!(
+            mismatched.parse_packet(inter_frame.packet.clone()),
+            Err(Vp9ParserError::RefSlotColorDepthMismatch(0))
+        ));
+
+        let mut mismatched = parser.clone();
+        mismatched.ref_slots[0].subsampling_y = false;
+        assert!(matches!(
+            mismatched.parse_packet(inter_frame.packet.clone()),
+            Err(Vp9ParserError::RefSlotSubsamplingMismatch(0))
+        ));
+
+        let _ = parser.parse_packet(inter_frame.packet)?;
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn decode_tiles() -> Result<()> {
+        let mut ivf = ivf::Ivf::new(std::fs::File::open("tests/data/320-24-crf.ivf")?).unwrap();
+        let key_frame = ivf.read_frame().unwrap().unwrap();
+        let inter_frame = ivf.read_frame().unwrap().unwrap();
+
+        // By default only the headers are parsed.
+        let mut parser = Vp9Parser::default();
+        assert!(!parser.decode_tiles());
+        let _ = parser.parse_packet(key_frame.packet.clone())?;
+        let frames = parser.parse_packet(inter_frame.packet.clone())?;
+        assert!(frames[0].mode_info().is_none());
+        assert!(frames[0].motion_vectors().is_none());
+        assert!(frames[0].tile_error().is_none());
+        assert!(parser.prev_mode_info.is_none());
+
+        // The motion vector export decodes the mode info, but keeps no transform blocks.
+        let mut mv_parser = Vp9Parser::default();
+        mv_parser.set_export_mvs(true);
+        let _ = mv_parser.parse_packet(key_frame.packet.clone())?;
+        let mv_frames = mv_parser.parse_packet(inter_frame.packet.clone())?;
+        assert!(mv_frames[0].transform_blocks().is_none());
+
+        let mut parser = Vp9Parser::default();
+        parser.set_decode_tiles(true);
+        parser.set_export_mvs(true);
+        parser.reset();
+        assert!(parser.decode_tiles());
+        let _ = parser.parse_packet(key_frame.packet)?;
+        let frames = parser.parse_packet(inter_frame.packet)?;
+        assert!(!frames[0].transform_blocks().unwrap().is_empty());
+        assert_eq!(mv_frames[0].segment_map(), frames[0].segment_map());
+        assert_eq!(mv_frames[0].motion_vectors(), frames[0].motion_vectors());
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn tile_error() -> Result<()> {
+        let mut ivf = ivf::Ivf::new(std::fs::File::open("tests/data/320-24-crf.ivf")?).unwrap();
+        let key_frame = ivf.read_frame().unwrap().unwrap();
+        let inter_frame = ivf.read_frame().unwrap().unwrap();
+        let next_frame = ivf.read_frame().unwrap().unwrap();
+
+        let mut parser = Vp9Parser::default();
+        parser.set_decode_tiles(true);
+        let _ = parser.parse_packet(key_frame.packet)?;
+        let frames = parser.parse_packet(inter_frame.packet)?;
+        assert!(frames[0].tile_error().is_none());
+        let motion_vectors = frames[0].mode_info().unwrap().motion_vectors();
+
+        // A truncated tile fails to decode, but the headers are still returned.
+        let mut truncated = next_frame.packet;
+        truncated.truncate(truncated.len() - 4);
+        let frames = parser.parse_packet(truncated)?;
+        assert!(frames[0].mode_info().is_none());
+        assert!(frames[0].tile_error().is_some());
+        assert_eq!(frames[0].width(), 320);
+
+        // The mode info of the last decoded frame is kept for the motion vector prediction.
+        let prev_mode_info = parser.prev_mode_info.as_ref().unwrap();
+        assert_eq!(prev_mode_info.motion_vectors(), motion_vectors);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn show_existing_frame() -> Result<()> {
+        let mut ivf = ivf::Ivf::new(std::fs::File::open("tests/data/320-24-crf.ivf")?).unwrap();
+        let key_frame = ivf.read_frame().unwrap().unwrap();
+        let inter_frame = ivf.read_frame().unwrap().unwrap();
+
+        let mut parser = Vp9Parser::default();
+        parser.set_decode_tiles(true);
+        let _ = parser.parse_packet(key_frame.packet)?;
+        let _ = parser.parse_packet(inter_frame.packet)?;
+        let motion_vectors = parser.prev_mode_info.as_ref().unwrap().motion_vectors();
+        assert!(!motion_vectors.is_empty());
+        // Pretend that the inter frame was hidden.
+        parser.show_frame = false;
+
+        // Shows the frame of slot 0.
+        let frames = parser.parse_packet(vec![0x88])?;
+        assert!(frames[0].show_existing_frame());
+        assert_eq!(frames[0].frame_to_show_map_idx(), Some(0));
+        assert!(frames[0].show_frame());
+
+        // The next frame can use the motion vectors of the last decoded frame.
+        assert!(parser.show_frame);
+        let prev_mode_info = parser.prev_mode_info.as_ref().unwrap();
+        assert_eq!(prev_mode_info.motion_vectors(), motion_vectors);
+
+        Ok(())
+    }
+
+    #[test]
+    fn ref_scale() {
+        let ref_scale = RefScale::new(640, 360, 320, 180);
+        assert_eq!(ref_scale.x_scale(), 2 << REF_SCALE_SHIFT);
+        assert_eq!(ref_scale.y_step(), 32);
+        assert!(ref_scale.is_scaled());
+
+        let ref_scale = RefScale::new(100, 180, 320, 180);
+        assert_eq!(ref_scale.x_scale(), 5120);
+        assert_eq!(ref_scale.x_step(), 5);
+        assert_eq!(ref_scale.y_step(), 16);
+    }
+
+    #[test]
+    fn compute_ref_scales() -> Result<()> {
+        let mut parser = Vp9Parser {
+            width: 320,
+            height: 180,
+            ref_frame_indices: [0, 1, 2],
+            ..Default::default()
+        };
+        parser.ref_slots[0].width = 640;
+        parser.ref_slots[0].height = 360;
+        parser.ref_slots[1].width = 20;
+        parser.ref_slots[1].height = 12;
+        parser.ref_slots[2].width = 320;
+        parser.ref_slots[2].height = 180;
+
+        let ref_scales = parser
+            .compute_ref_scales()
+            .unwrap_or_else(|_| unreachable!());
+        assert_eq!(ref_scales[0].x_step(), 32);
+        assert_eq!(ref_scales[1].y_step(), 1);
+        assert!(!ref_scales[2].is_scaled());
+
+        parser.ref_slots[1].width = 19;
+        assert!(matches!(
+            parser.compute_ref_scales(),
+            Err(Vp9ParserError::InvalidRefFrameScale(1))
+        ));
+
+        parser.ref_slots[1].width = 20;
+        parser.ref_slots[0].height = 361;
+        assert!(matches!(
+            parser.compute_ref_scales(),
+            Err(Vp9ParserError::InvalidRefFrameScale(0))
+        ));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,625 @@
+//! Loop filter as defined in spec "8.8 Loop filter process".
+//!
+//! The frame is filtered super block by super block in raster order. Inside a super block
+//! each plane first filters all vertical edges (left to right inside every row), then all
+//! horizontal edges (top to bottom). The filter size follows the transform size on both
+//! sides of the edge, while skipped inter blocks only filter the edges of the block itself.
+
+use std::{convert::TryFrom, ops::Range};
+
+use crate::{
+    tile_decoder, Frame, ModeInfo, ModeInfoGrid, Plane, PredictionMode, Result, TxSize,
+    Vp9ParserError, MAX_SEGMENTS, SEG_LVL_ALT_L,
+};
+
+/// Highest filter level.
+const MAX_LOOP_FILTER: i32 = 63;
+
+/// Size of a super block in mode info units.
+const MI_BLOCK_SIZE: u16 = 8;
+
+/// The width of the filter applied to an edge.
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
+enum FilterSize {
+    /// Modifies up to 2 samples on each side.
+    Filter4,
+    /// Modifies up to 3 samples on each side.
+    Filter8,
+    /// Modifies up to 7 samples on each side.
+    Filter16,
+}
+
+/// The limits of a filter level (spec "8.8.3 Filter limit process") at 8 bit.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct FilterLimits {
+    limit: i32,
+    blimit: i32,
+    thresh: i32,
+}
+
+/// The edges of an 8x8 block of a plane that are filtered.
+#[derive(Clone, Copy, Debug, Default)]
+struct BlockEdges {
+    level: u8,
+    left: Option<FilterSize>,
+    above: Option<FilterSize>,
+    /// The inner edges of 4x4 transforms.
+    inner: bool,
+}
+
+/// Applies the loop filter to the reconstructed planes of a frame.
+///
+/// The filter levels are derived from the loop filter parameters of the frame, the
+/// segmentation feature ALT_L and the reference frames and modes of the blocks. Nothing is
+/// done if the loop filter level of the frame is zero.
+pub fn loop_filter_frame(
+    planes: &mut [Plane],
+    frame: &Frame,
+    mode_info: &ModeInfoGrid,
+) -> Result<()> {
+    loop_filter_rows(planes, frame, mode_info, 0..frame.mi_rows())
+}
+
+/// Applies the loop filter to the super block rows starting in the given mode info rows.
+///
+/// The start of the range needs to be the first row of a super block. Filtering the rows in
+/// ascending order gives the same result as filtering the whole frame.
+pub(crate) fn loop_filter_rows(
+    planes: &mut [Plane],
+    frame: &Frame,
+    mode_info: &ModeInfoGrid,
+    mi_rows: Range<u16>,
+) -> Result<()> {
+    if frame.loop_filter_level() == 0 {
+        return Ok(());
+    }
+
+    let levels = filter_levels(frame);
+    let limits = filter_limits(frame.loop_filter_sharpness());
+    let mi_rows = mi_rows.start..mi_rows.end.min(frame.mi_rows());
+    for mi_row in mi_rows.step_by(usize::from(MI_BLOCK_SIZE)) {
+        for mi_col in (0..frame.mi_cols()).step_by(usize::from(MI_BLOCK_SIZE)) {
+            for (plane_index, plane) in planes.iter_mut().enumerate() {
+                let subsampling = if plane_index > 0 {
+                    (frame.subsampling_x(), frame.subsampling_y())
+                } else {
+                    (false, false)
+                };
+                let edges =
+                    superblock_edges(frame, mode_info, &levels, (mi_row, mi_col), subsampling)?;
+                filter_superblock(plane, &edges, &limits, frame, (mi_row, mi_col), subsampling)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Implements spec "8.8.1 Filter level lookup" for every segment, reference frame and mode type.
+//
+// Returns the levels indexed by [segment][ref_frame][mode_type], where the mode type is 0
+// for ZEROMV and intra blocks and 1 for all other inter modes.
+fn filter_levels(frame: &Frame) -> [[[u8; 2]; 4]; MAX_SEGMENTS] {
+    let mut levels = [[[0; 2]; 4]; MAX_SEGMENTS];
+    for (segment, segment_levels) in levels.iter_mut().enumerate() {
+        let mut level = i32::from(frame.loop_filter_level());
+        if frame.segmentation_enabled() && frame.segment_feature_enabled()[segment][SEG_LVL_ALT_L] {
+            let data = i32::from(frame.segment_feature_data()[segment][SEG_LVL_ALT_L]);
+            level = if frame.segmentation_abs_or_delta_update() {
+                data
+            } else {
+                level + data
+            };
+            level = level.clamp(0, MAX_LOOP_FILTER);
+        }
+
+        let shift = level >> 5;
+        let ref_deltas = frame.loop_filter_ref_deltas();
+        let mode_deltas = frame.loop_filter_mode_deltas();
+        for (ref_frame, ref_levels) in segment_levels.iter_mut().enumerate() {
+            for (mode_type, mode_level) in ref_levels.iter_mut().enumerate() {
+                let mut delta = 0;
+                if frame.loop_filter_delta_enabled() {
+                    delta = i32::from(ref_deltas[ref_frame]) << shift;
+                    if ref_frame > 0 {
+                        delta += i32::from(mode_deltas[mode_type]) << shift;
+                    }
+                }
+                *mode_level =
+                    u8::try_from((level + delta).clamp(0, MAX_LOOP_FILTER)).unwrap_or_default();
+            }
+        }
+    }
+    levels
+}
+
+/// Returns the filter level of a block.
+fn block_level(levels: &[[[u8; 2]; 4]; MAX_SEGMENTS], mode_info: &ModeInfo) -> u8 {
+    let mode_type = usize::from(mode_info.is_inter && mode_info.y_mode != PredictionMode::ZeroMv);
+    let segment = usize::from(mode_info.segment_id).min(MAX_SEGMENTS - 1);
+    let ref_frame = mode_info.ref_frame[0].min(3);
+    levels[segment][ref_frame][mode_type]
+}
+
+// Implements spec "8.8.3 Filter limit process" for all filter levels.
+fn filter_limits(sharpness: u8) -> [FilterLimits; 64] {
+    let mut limits = [FilterLimits::default(); 64];
+    let shift = u8::from(sharpness > 0) + u8::from(sharpness > 4);
+    for (level, limit) in (0..).zip(limits.iter_mut()) {
+        let mut inside_limit = level >> shift;
+        if sharpness > 0 {
+            inside_limit = inside_limit.min(9 - i32::from(sharpness));
+        }
+        inside_limit = inside_limit.max(1);
+        *limit = FilterLimits {
+            limit: inside_limit,
+            blimit: 2 * (level + 2) + inside_limit,
+            thresh: level >> 4,
+        };
+    }
+    limits
+}
+
+// Determines the edges to filter for every 8x8 block of a plane inside a super block.
+//
+// Follows the edge selection of the spec "8.8.2 Edge loop filter process": block edges are
+// always filtered, transform edges only if the block is not a skipped inter block. Subsampled
+// planes use the mode info of every second block and never use the 16 wide filter or filter
+// inner 4x4 edges on the last row or column of the frame.
+fn superblock_edges(
+    frame: &Frame,
+    mode_info: &ModeInfoGrid,
+    levels: &[[[u8; 2]; 4]; MAX_SEGMENTS],
+    (mi_row, mi_col): (u16, u16),
+    (subsampling_x, subsampling_y): (bool, bool),
+) -> Result<[[BlockEdges; 8]; 8]> {
+    let mut edges = [[BlockEdges::default(); 8]; 8];
+    let ss_x = u8::from(subsampling_x);
+    let ss_y = u8::from(subsampling_y);
+
+    for r in (0..MI_BLOCK_SIZE).step_by(1 << ss_y) {
+        if mi_row + r >= frame.mi_rows() {
+            break;
+        }
+        for c in (0..MI_BLOCK_SIZE).step_by(1 << ss_x) {
+            if mi_col + c >= frame.mi_cols() {
+                break;
+            }
+            let block =
+                mode_info
+                    .get(mi_row + r, mi_col + c)
+                    .ok_or(Vp9ParserError::BlockOutsidePlane(
+                        usize::from(mi_col + c),
+                        usize::from(mi_row + r),
+                    ))?;
+            let row = usize::from(r >> ss_y);
+            let col = usize::from(c >> ss_x);
+            let edge = &mut edges[row][col];
+            edge.level = block_level(levels, block);
+            if edge.level == 0 {
+                continue;
+            }
+
+            let block_size = block.block_size;
+            let skip = block.skip && block.is_inter;
+            let block_edge_left =
+                block_size.width_log2() == 0 || c & (block_size.num_8x8_wide() - 1) == 0;
+            let block_edge_above =
+                block_size.height_log2() == 0 || r & (block_size.num_8x8_high() - 1) == 0;
+            let skip_left = skip && !block_edge_left;
+            let skip_above = skip && !block_edge_above;
+            let tx_size = tile_decoder::uv_tx_size(
+                block.tx_size,
+                block_size,
+                u16::from(ss_x),
+                u16::from(ss_y),
+            );
+            let border_column = subsampling_x && mi_col + c == frame.mi_cols() - 1;
+            let border_row = subsampling_y && mi_row + r == frame.mi_rows() - 1;
+
+            let wide = |position: usize, border: bool, mask: usize| {
+                (position & mask == 0).then_some(if border {
+                    FilterSize::Filter8
+                } else {
+                    FilterSize::Filter16
+                })
+            };
+            let narrow = |position: usize| {
+                if tx_size == TxSize::Tx8x8 || position & 3 == 0 {
+                    FilterSize::Filter8
+                } else {
+                    FilterSize::Filter4
+                }
+            };
+            match tx_size {
+                TxSize::Tx32x32 | TxSize::Tx16x16 => {
+                    let mask = if tx_size == TxSize::Tx32x32 { 3 } else { 1 };
+                    if !skip_left {
+                        edge.left = wide(col, border_column, mask);
+                    }
+                    if !skip_above {
+                        edge.above = wide(row, border_row, mask);
+                    }
+                }
+                TxSize::Tx8x8 | TxSize::Tx4x4 => {
+                    if !skip_left {
+                        edge.left = Some(narrow(col));
+                    }
+                    if !skip_above {
+                        edge.above = Some(narrow(row));
+                    }
+                    edge.inner = !skip && tx_size == TxSize::Tx4x4 && !border_column;
+                }
+            }
+        }
+    }
+
+    Ok(edges)
+}
+
+// Filters the edges of a plane inside a super block, first the vertical edges, then the
+// horizontal edges.
+fn filter_superblock(
+    plane: &mut Plane,
+    edges: &[[BlockEdges; 8]; 8],
+    limits: &[FilterLimits; 64],
+    frame: &Frame,
+    (mi_row, mi_col): (u16, u16),
+    (subsampling_x, subsampling_y): (bool, bool),
+) -> Result<()> {
+    let x0 = (usize::from(mi_col) * 8) >> u8::from(subsampling_x);
+    let y0 = (usize::from(mi_row) * 8) >> u8::from(subsampling_y);
+    let shift = u32::from(plane.bit_depth() - 8);
+
+    for (row, row_edges) in edges.iter().enumerate() {
+        let y = y0 + row * 8;
+        for (col, edge) in row_edges.iter().enumerate() {
+            let x = x0 + col * 8;
+            let limits = scaled_limits(&limits[usize::from(edge.level)], shift);
+            // The left edge of the frame is never filtered.
+            if let Some(size) = edge.left.filter(|_| mi_col > 0 || col > 0) {
+                filter_edge(plane, (x, y), true, size, &limits)?;
+            }
+            if edge.inner {
+                filter_edge(plane, (x + 4, y), true, FilterSize::Filter4, &limits)?;
+            }
+        }
+    }
+
+    for (row, row_edges) in edges.iter().enumerate() {
+        let y = y0 + row * 8;
+        let mi_row_of_edge = mi_row + (u16::try_from(row)? << u8::from(subsampling_y));
+        let border_row = subsampling_y && mi_row_of_edge == frame.mi_rows() - 1;
+        for (col, edge) in row_edges.iter().enumerate() {
+            let x = x0 + col * 8;
+            let limits = scaled_limits(&limits[usize::from(edge.level)], shift);
+            // The top edge of the frame is never filtered.
+            if let Some(size) = edge.above.filter(|_| mi_row_of_edge > 0) {
+                filter_edge(plane, (x, y), false, size, &limits)?;
+            }
+            if edge.inner && !border_row {
+                filter_edge(plane, (x, y + 4), false, FilterSize::Filter4, &limits)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Scales the limits to the bit depth.
+fn scaled_limits(limits: &FilterLimits, shift: u32) -> FilterLimits {
+    FilterLimits {
+        limit: limits.limit << shift,
+        blimit: limits.blimit << shift,
+        thresh: limits.thresh << shift,
+    }
+}
+
+// Implements spec "8.8.4 Sample filtering process" for the 8 samples along an edge.
+//
+// Vertical edges filter the samples of a row, horizontal edges the samples of a column. The
+// position is the first sample right of or below the edge.
+fn filter_edge(
+    plane: &mut Plane,
+    (x, y): (usize, usize),
+    vertical: bool,
+    size: FilterSize,
+    limits: &FilterLimits,
+) -> Result<()> {
+    let taps = if size == FilterSize::Filter16 { 8 } else { 4 };
+    let stride = plane.stride();
+    let (step, along) = if vertical { (1, stride) } else { (stride, 1) };
+    let fits = if vertical {
+        x >= taps && plane.contains(x - taps, y, 2 * taps, 8)
+    } else {
+        y >= taps && plane.contains(x, y - taps, 8, 2 * taps)
+    };
+    if !fits {
+        return Err(Vp9ParserError::BlockOutsidePlane(x, y));
+    }
+
+    let shift = u32::from(plane.bit_depth() - 8);
+    let max_value = i32::from(plane.max_value());
+    let data = plane.data_mut();
+    let mut samples = [0i32; 16];
+    for i in 0..8 {
+        let start = y * stride + x + i * along - taps * step;
+        for (t, sample) in samples[..2 * taps].iter_mut().enumerate() {
+            *sample = i32::from(data[start + t * step]);
+        }
+        filter_samples(&mut samples[..2 * taps], size, limits, shift);
+        for (t, sample) in samples[..2 * taps].iter().enumerate() {
+            data[start + t * step] = u16::try_from((*sample).clamp(0, max_value))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Filters the samples across an edge, the first half of the samples is on the left or above
+/// side of the edge.
+fn filter_samples(samples: &mut [i32], size: FilterSize, limits: &FilterLimits, shift: u32) {
+    let n = samples.len() / 2;
+    // p[i] is the i-th sample before the edge, q[i] the i-th sample after it.
+    let p = |i: usize| samples[n - 1 - i];
+    let q = |i: usize| samples[n + i];
+
+    let mask = (1..4).all(|i| (p(i) - p(i - 1)).abs() <= limits.limit)
+        && (1..4).all(|i| (q(i) - q(i - 1)).abs() <= limits.limit)
+        && (p(0) - q(0)).abs() * 2 + (p(1) - q(1)).abs() / 2 <= limits.blimit;
+    if !mask {
+        return;
+    }
+
+    let flat_threshold = 1 << shift;
+    let flat = |range: std::ops::Range<usize>| {
+        range.clone().all(|i| (p(i) - p(0)).abs() <= flat_threshold)
+            && range
+                .into_iter()
+                .all(|i| (q(i) - q(0)).abs() <= flat_threshold)
+    };
+    let flat8 = size >= FilterSize::Filter8 && flat(1..4);
+    let flat16 = size == FilterSize::Filter16 && flat8 && flat(4..8);
+
+    if flat16 {
+        wide_filter(samples, 8, 4);
+    } else if flat8 {
+        // The 7-tap filter only uses the 4 samples next to the edge.
+        wide_filter(&mut samples[n - 4..n + 4], 4, 3);
+    } else {
+        let [p1, p0, q0, q1] = [n - 2, n - 1, n, n + 1];
+        let hev = (p(1) - p(0)).abs() > limits.thresh || (q(1) - q(0)).abs() > limits.thresh;
+        narrow_filter(samples, [p1, p0, q0, q1], hev, shift);
+    }
+}
+
+// Implements spec "8.8.5 Wide filter process".
+//
+// Replaces the samples next to the edge with the average of a window of 2 * n - 1 samples
+// that counts the center sample twice. Samples outside of the slice repeat the outer samples.
+fn wide_filter(samples: &mut [i32], n: usize, log2: u32) {
+    let input: Vec<i32> = samples.to_vec();
+    let last = 2 * n - 1;
+    for (j, sample) in samples.iter_mut().enumerate().take(last).skip(1) {
+        let mut sum = input[j];
+        for m in j..=j + 2 * (n - 1) {
+            sum += input[m.saturating_sub(n - 1).min(last)];
+        }
+        *sample = (sum + (1 << (log2 - 1))) >> log2;
+    }
+}
+
+// Implements spec "8.8.6 Narrow filter process".
+fn narrow_filter(samples: &mut [i32], [p1, p0, q0, q1]: [usize; 4], hev: bool, shift: u32) {
+    let offset = 0x80 << shift;
+    let clamp = |value: i32| value.clamp(-(128 << shift), (128 << shift) - 1);
+    let ps1 = samples[p1] - offset;
+    let ps0 = samples[p0] - offset;
+    let qs0 = samples[q0] - offset;
+    let qs1 = samples[q1] - offset;
+
+    let outer = if hev { clamp(ps1 - qs1) } else { 0 };
+    let filter = clamp(outer + 3 * (qs0 - ps0));
+    let filter1 = clamp(filter + 4) >> 3;
+    let filter2 = clamp(filter + 3) >> 3;
+    samples[q0] = clamp(qs0 - filter1) + offset;
+    samples[p0] = clamp(ps0 + filter2) + offset;
+
+    if !hev {
+        let filter = (filter1 + 1) >> 1;
+        samples[q1] = clamp(qs1 - filter) + offset;
+        samples[p1] = clamp(ps1 + filter) + offset;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::{ivf::Ivf, Vp9Parser, INTRA_FRAME, LAST_FRAME};
+
+    /// Parses the key frame of a test stream.
+    fn key_frame(path: &str) -> Frame {
+        let mut ivf = Ivf::new(std::fs::File::open(path).unwrap()).unwrap();
+        let mut parser = Vp9Parser::default();
+        parser
+            .parse_packet(ivf.read_frame().unwrap().unwrap().packet)
+            .unwrap()
+            .remove(0)
+    }
+
+    /// Filters the samples of an edge with 8 bit limits of level 32 and no sharpness.
+    fn filtered(samples: &[i32], size: FilterSize, bit_depth: u32) -> Vec<i32> {
+        let shift = bit_depth - 8;
+        let limits = scaled_limits(&filter_limits(0)[32], shift);
+        let mut samples = samples.to_vec();
+        filter_samples(&mut samples, size, &limits, shift);
+        samples
+    }
+
+    fn step(taps: usize, low: i32, high: i32) -> Vec<i32> {
+        let mut samples = vec![low; taps];
+        samples.extend(vec![high; taps]);
+        samples
+    }
+
+    #[test]
+    fn limits() {
+        let limits = filter_limits(0);
+        assert_eq!(limits[0].limit, 1);
+        assert_eq!(
+            limits[32],
+            FilterLimits {
+                limit: 32,
+                blimit: 100,
+                thresh: 2
+            }
+        );
+
+        let limits = filter_limits(5);
+        assert_eq!(
+            limits[32],
+            FilterLimits {
+                limit: 4,
+                blimit: 72,
+                thresh: 2
+            }
+        );
+        assert_eq!(filter_limits(2)[63].limit, 7);
+        assert_eq!(scaled_limits(&limits[32], 4).blimit, 72 << 4);
+    }
+
+    #[test]
+    fn filters() {
+        let samples = filtered(&step(8, 100, 110), FilterSize::Filter16, 8);
+        assert_eq!(
+            samples,
+            [100, 101, 101, 102, 103, 103, 104, 104, 106, 106, 107, 108, 108, 109, 109, 110]
+        );
+
+        let samples = filtered(&step(4, 100, 110), FilterSize::Filter8, 8);
+        assert_eq!(samples, [100, 101, 103, 104, 106, 108, 109, 110]);
+
+        let samples = filtered(&step(4, 100, 110), FilterSize::Filter4, 8);
+        assert_eq!(samples, [100, 100, 102, 104, 106, 108, 110, 110]);
+
+        // Samples that are not flat fall back to the narrower filters.
+        let mut samples = step(8, 100, 110);
+        samples[0] = 90;
+        assert_eq!(
+            filtered(&samples, FilterSize::Filter16, 8)[4..12],
+            [100, 101, 103, 104, 106, 108, 109, 110]
+        );
+        samples[4] = 97;
+        assert_eq!(
+            filtered(&samples, FilterSize::Filter16, 8)[4..12],
+            [97, 100, 102, 104, 106, 108, 110, 110]
+        );
+
+        // Edges above the limits are real edges and are kept.
+        let samples = step(8, 100, 200);
+        assert_eq!(filtered(&samples, FilterSize::Filter16, 8), samples);
+    }
+
+    #[test]
+    fn high_bit_depth_filters() {
+        for bit_depth in [10, 12] {
+            let shift = bit_depth - 8;
+            let samples = step(8, 100 << shift, 110 << shift);
+            let expected: Vec<i32> = filtered(&step(8, 100, 110), FilterSize::Filter16, 8)
+                .iter()
+                .map(|sample| sample << shift)
+                .collect();
+            let samples = filtered(&samples, FilterSize::Filter16, bit_depth);
+            for (sample, expected) in samples.iter().zip(expected) {
+                assert!((sample - expected).abs() < 1 << shift);
+            }
+
+            let samples = step(4, 100 << shift, 200 << shift);
+            assert_eq!(filtered(&samples, FilterSize::Filter8, bit_depth), samples);
+
+            let samples = step(4, 0, (1 << bit_depth) - 1);
+            assert_eq!(filtered(&samples, FilterSize::Filter4, bit_depth), samples);
+        }
+    }
+
+    #[test]
+    fn levels() {
+        let frame = key_frame("tests/data/320-24-crf.ivf");
+        let level = i32::from(frame.loop_filter_level());
+        assert!(level > 0);
+        assert!(frame.loop_filter_delta_enabled());
+
+        let levels = filter_levels(&frame);
+        let shift = level >> 5;
+        let ref_deltas = frame.loop_filter_ref_deltas();
+        let mode_deltas = frame.loop_filter_mode_deltas();
+        let expected = |delta: i32| u8::try_from((level + delta).clamp(0, 63)).unwrap();
+        assert_eq!(
+            levels[0][INTRA_FRAME][0],
+            expected(i32::from(ref_deltas[INTRA_FRAME]) << shift)
+        );
+        assert_eq!(
+            levels[0][LAST_FRAME][1],
+            expected(
+                (i32::from(ref_deltas[LAST_FRAME]) << shift) + (i32::from(mode_deltas[1]) << shift)
+            )
+        );
+        assert_eq!(levels[0], levels[MAX_SEGMENTS - 1]);
+
+        let mut mode_info = ModeInfo {
+            is_inter: true,
+            ref_frame: [LAST_FRAME, INTRA_FRAME],
+            y_mode: PredictionMode::ZeroMv,
+            ..Default::default()
+        };
+        assert_eq!(block_level(&levels, &mode_info), levels[0][LAST_FRAME][0]);
+        mode_info.y_mode = PredictionMode::NewMv;
+        assert_eq!(block_level(&levels, &mode_info), levels[0][LAST_FRAME][1]);
+    }
+
+    #[test]
+    fn frame_edges() {
+        for path in [
+            "tests/data/320-24-crf.ivf",
+            "tests/data/320-444-10bit.ivf",
+            "tests/data/320-444-12bit.ivf",
+        ] {
+            let frame = key_frame(path);
+            let grid = ModeInfoGrid::new(frame.mi_cols(), frame.mi_rows());
+            let mut planes: Vec<Plane> = (0..3)
+                .map(|plane_index| {
+                    let mut plane = Plane::from_frame(&frame, plane_index);
+                    let shift = plane.bit_depth() - 8;
+                    let stride = plane.stride();
+                    // Blocks of 4x4 samples that differ slightly from their neighbours.
+                    for (i, sample) in plane.data_mut().iter_mut().enumerate() {
+                        let block = (i % stride) / 4 + (i / stride) / 4;
+                        *sample = (100 + u16::try_from(block % 2).unwrap() * 6) << shift;
+                    }
+                    plane
+                })
+                .collect();
+            let original = planes.clone();
+            loop_filter_frame(&mut planes, &frame, &grid).unwrap();
+
+            for (plane, original) in planes.iter().zip(&original) {
+                let stride = plane.stride();
+                let changed = |x: usize, y: usize| {
+                    plane.data()[y * stride + x] != original.data()[y * stride + x]
+                };
+                // The frame borders are not filtered, the block edges are.
+                assert!(!changed(0, 0));
+                assert!(!changed(1, 1));
+                assert!(changed(7, 1));
+                assert!(changed(8, 1));
+                assert!(changed(1, 7));
+                assert!(changed(1, 8));
+                // Inner edges of the 4x4 transforms are filtered as well.
+                assert!(changed(3, 1));
+                assert!(changed(4, 1));
+            }
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! MD5 checksums of decoded pictures as calculated by libvpx.
+//!
+//! `vpxdec --md5 --i420` and the test vectors of libvpx hash the visible samples of the Y, U
+//! and V plane row by row. Samples of 8 bit pictures are hashed as one byte, samples of high
+//! bit depth pictures as two bytes in little endian order. The `.md5` files of the test
+//! vectors hold one checksum per shown frame.
+//!
+//! The MD5 message digest itself is implemented as defined in RFC 1321.
+
+use std::{convert::TryFrom, fmt};
+
+use crate::Plane;
+
+/// The per-round shift amounts.
+const SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// `floor(abs(sin(i + 1)) * 2^32)` for i = 0..64.
+const SINES: [u32; 64] = [
+    0xd76a_a478,
+    0xe8c7_b756,
+    0x2420_70db,
+    0xc1bd_ceee,
+    0xf57c_0faf,
+    0x4787_c62a,
+    0xa830_4613,
+    0xfd46_9501,
+    0x6980_98d8,
+    0x8b44_f7af,
+    0xffff_5bb1,
+    0x895c_d7be,
+    0x6b90_1122,
+    0xfd98_7193,
+    0xa679_438e,
+    0x49b4_0821,
+    0xf61e_2562,
+    0xc040_b340,
+    0x265e_5a51,
+    0xe9b6_c7aa,
+    0xd62f_105d,
+    0x0244_1453,
+    0xd8a1_e681,
+    0xe7d3_fbc8,
+    0x21e1_cde6,
+    0xc337_07d6,
+    0xf4d5_0d87,
+    0x455a_14ed,
+    0xa9e3_e905,
+    0xfcef_a3f8,
+    0x676f_02d9,
+    0x8d2a_4c8a,
+    0xfffa_3942,
+    0x8771_f681,
+    0x6d9d_6122,
+    0xfde5_380c,
+    0xa4be_ea44,
+    0x4bde_cfa9,
+    0xf6bb_4b60,
+    0xbebf_bc70,
+    0x289b_7ec6,
+    0xeaa1_27fa,
+    0xd4ef_3085,
+    0x0488_1d05,
+    0xd9d4_d039,
+    0xe6db_99e5,
+    0x1fa2_7cf8,
+    0xc4ac_5665,
+    0xf429_2244,
+    0x432a_ff97,
+    0xab94_23a7,
+    0xfc93_a039,
+    0x655b_59c3,
+    0x8f0c_cc92,
+    0xffef_f47d,
+    0x8584_5dd1,
+    0x6fa8_7e4f,
+    0xfe2c_e6e0,
+    0xa301_4314,
+    0x4e08_11a1,
+    0xf753_7e82,
+    0xbd3a_f235,
+    0x2ad7_d2bb,
+    0xeb86_d391,
+];
+
+/// The size of a block in bytes.
+const BLOCK_SIZE: usize = 64;
+
+/// An MD5 checksum.
+#[derive(Clone, Copy, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Md5Digest([u8; 16]);
+
+impl Md5Digest {
+    /// The 16 bytes of the checksum.
+    pub fn bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+/// Formats the checksum as 32 lowercase hexadecimal digits, like the `.md5` files.
+impl fmt::Display for Md5Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Calculates the MD5 checksum of a stream of bytes.
+#[derive(Clone, Debug)]
+pub struct Md5 {
+    state: [u32; 4],
+    block: [u8; BLOCK_SIZE],
+    block_len: usize,
+    /// The number of hashed bytes.
+    len: u64,
+}
+
+impl Default for Md5 {
+    fn default() -> Self {
+        Self {
+            state: [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476],
+            block: [0; BLOCK_SIZE],
+            block_len: 0,
+            len: 0,
+        }
+    }
+}
+
+impl Md5 {
+    /// Creates a new checksum calculation.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Hashes the given bytes.
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len = self
+            .len
+            .wrapping_add(u64::try_from(data.len()).unwrap_or(u64::MAX));
+        while !data.is_empty() {
+            let count = (BLOCK_SIZE - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + count].copy_from_slice(&data[..count]);
+            self.block_len += count;
+            data = &data[count..];
+            if self.block_len == BLOCK_SIZE {
+                let block = self.block;
+                self.process_block(&block);
+                self.block_len = 0;
+            }
+        }
+    }
+
+    /// Hashes the visible samples of a plane row by row.
+    ///
+    /// Samples of planes with a bit depth of 8 are hashed as one byte, all others as two
+    /// bytes in little endian order.
+    pub fn update_plane(&mut self, plane: &Plane) {
+        let mut bytes = Vec::with_capacity(plane.width() * 2);
+        for y in 0..plane.height() {
+            let Some(row) = plane.row(y) else {
+                break;
+            };
+            bytes.clear();
+            if plane.bit_depth() == 8 {
+                bytes.extend(row.iter().map(|sample| sample.to_le_bytes()[0]));
+            } else {
+                bytes.extend(row.iter().flat_map(|sample| sample.to_le_bytes()));
+            }
+            self.update(&bytes);
+        }
+    }
+
+    /// Finishes the calculation and returns the checksum.
+    pub fn finish(mut self) -> Md5Digest {
+        let bit_len = self.len.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.block_len != BLOCK_SIZE - 8 {
+            self.update(&[0]);
+        }
+        self.update(&bit_len.to_le_bytes());
+
+        let mut digest = [0; 16];
+        for (bytes, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        Md5Digest(digest)
+    }
+
+    fn process_block(&mut self, block: &[u8; BLOCK_SIZE]) {
+        let mut words = [0u32; 16];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for i in 0..BLOCK_SIZE {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f
+                .wrapping_add(a)
+                .wrapping_add(SINES[i])
+                .wrapping_add(words[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[i]));
+        }
+
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+/// Returns the checksum of the Y, U and V planes of a picture, as calculated by
+/// `vpxdec --md5 --i420` for a single frame.
+pub fn planes_md5(planes: &[Plane]) -> Md5Digest {
+    let mut md5 = Md5::new();
+    for plane in planes {
+        md5.update_plane(plane);
+    }
+    md5.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ColorDepth;
+
+    fn md5(data: &[u8]) -> String {
+        let mut md5 = Md5::new();
+        md5.update(data);
+        md5.finish().to_string()
+    }
+
+    #[test]
+    fn rfc_1321_test_suite() {
+        assert_eq!(md5(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5(b"a"), "0cc175b9c0f1b6a831c399e269772661");
+        assert_eq!(md5(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(md5(b"message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+        assert_eq!(
+            md5(b"abcdefghijklmnopqrstuvwxyz"),
+            "c3fcd3d76192e4007dfb496cca67e13b"
+        );
+        assert_eq!(
+            md5(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"),
+            "d174ab98d277d9f5a5611c2c9f419d9f"
+        );
+        assert_eq!(
+            md5(
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            ),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+
+        // Splitting the data doesn't change the checksum.
+        let mut split = Md5::new();
+        for chunk in b"abcdefghijklmnopqrstuvwxyz".chunks(7) {
+            split.update(chunk);
+        }
+        assert_eq!(
+            split.finish().to_string(),
+            "c3fcd3d76192e4007dfb496cca67e13b"
+        );
+    }
+
+    #[test]
+    fn plane_layout() {
+        let fill = |plane: &mut Plane, value: u16| {
+            for sample in plane.data_mut().iter_mut() {
+                *sample = value;
+            }
+        };
+
+        // Only the visible samples are hashed, 8 bit samples as one byte.
+        let mut planes = [
+            Plane::new(3, 2, false, false, ColorDepth::Depth8),
+            Plane::new(3, 2, true, true, ColorDepth::Depth8),
+            Plane::new(3, 2, true, true, ColorDepth::Depth8),
+        ];
+        fill(&mut planes[0], 0x61);
+        fill(&mut planes[1], 0x62);
+        fill(&mut planes[2], 0x63);
+        assert_eq!(planes_md5(&planes).to_string(), md5(b"aaaaaabbcc"));
+
+        // High bit depth samples are hashed as two bytes in little endian order.
+        let mut planes = [
+            Plane::new(2, 1, false, false, ColorDepth::Depth10),
+            Plane::new(2, 1, false, false, ColorDepth::Depth10),
+            Plane::new(2, 1, false, false, ColorDepth::Depth10),
+        ];
+        fill(&mut planes[0], 0x0302);
+        assert_eq!(
+            planes_md5(&planes).to_string(),
+            md5(&[2, 3, 2, 3, 0, 0, 0, 0, 0, 0, 0, 0])
+        );
+    }
+}
@@ -0,0 +1,794 @@
+//! Mode info of the decoded blocks.
+
+use crate::{InterpolationFilter, INTRA_FRAME};
+
+/// Size of a block.
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
+pub enum BlockSize {
+    /// 4x4 pixels.
+    Block4x4,
+    /// 4x8 pixels.
+    Block4x8,
+    /// 8x4 pixels.
+    Block8x4,
+    /// 8x8 pixels.
+    Block8x8,
+    /// 8x16 pixels.
+    Block8x16,
+    /// 16x8 pixels.
+    Block16x8,
+    /// 16x16 pixels.
+    Block16x16,
+    /// 16x32 pixels.
+    Block16x32,
+    /// 32x16 pixels.
+    Block32x16,
+    /// 32x32 pixels.
+    Block32x32,
+    /// 32x64 pixels.
+    Block32x64,
+    /// 64x32 pixels.
+    Block64x32,
+    /// 64x64 pixels.
+    Block64x64,
+}
+
+impl From<u8> for BlockSize {
+    fn from(i: u8) -> Self {
+        match i {
+            0 => BlockSize::Block4x4,
+            1 => BlockSize::Block4x8,
+            2 => BlockSize::Block8x4,
+            3 => BlockSize::Block8x8,
+            4 => BlockSize::Block8x16,
+            5 => BlockSize::Block16x8,
+            6 => BlockSize::Block16x16,
+            7 => BlockSize::Block16x32,
+            8 => BlockSize::Block32x16,
+            9 => BlockSize::Block32x32,
+            10 => BlockSize::Block32x64,
+            11 => BlockSize::Block64x32,
+            _ => BlockSize::Block64x64,
+        }
+    }
+}
+
+impl From<BlockSize> for u8 {
+    fn from(b: BlockSize) -> Self {
+        match b {
+            BlockSize::Block4x4 => 0,
+            BlockSize::Block4x8 => 1,
+            BlockSize::Block8x4 => 2,
+            BlockSize::Block8x8 => 3,
+            BlockSize::Block8x16 => 4,
+            BlockSize::Block16x8 => 5,
+            BlockSize::Block16x16 => 6,
+            BlockSize::Block16x32 => 7,
+            BlockSize::Block32x16 => 8,
+            BlockSize::Block32x32 => 9,
+            BlockSize::Block32x64 => 10,
+            BlockSize::Block64x32 => 11,
+            BlockSize::Block64x64 => 12,
+        }
+    }
+}
+
+impl BlockSize {
+    /// The width of the block in pixels.
+    pub fn width(&self) -> u8 {
+        4 << B_WIDTH_LOG2[usize::from(u8::from(*self))]
+    }
+
+    /// The height of the block in pixels.
+    pub fn height(&self) -> u8 {
+        4 << B_HEIGHT_LOG2[usize::from(u8::from(*self))]
+    }
+
+    /// Log2 of the width in units of 4x4 blocks.
+    pub(crate) fn width_log2(&self) -> u8 {
+        B_WIDTH_LOG2[usize::from(u8::from(*self))]
+    }
+
+    /// Log2 of the height in units of 4x4 blocks.
+    pub(crate) fn height_log2(&self) -> u8 {
+        B_HEIGHT_LOG2[usize::from(u8::from(*self))]
+    }
+
+    /// Number of 8x8 blocks that the block covers horizontally (at least 1).
+    pub(crate) fn num_8x8_wide(&self) -> u16 {
+        1 << self.width_log2().saturating_sub(1)
+    }
+
+    /// Number of 8x8 blocks that the block covers vertically (at least 1).
+    pub(crate) fn num_8x8_high(&self) -> u16 {
+        1 << self.height_log2().saturating_sub(1)
+    }
+}
+
+/// Log2 of the block widths in units of 4x4 blocks.
+const B_WIDTH_LOG2: [u8; 13] = [0, 0, 1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4];
+
+/// Log2 of the block heights in units of 4x4 blocks.
+const B_HEIGHT_LOG2: [u8; 13] = [0, 1, 0, 1, 2, 1, 2, 3, 2, 3, 4, 3, 4];
+
+/// The partition type of a block.
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
+pub enum PartitionType {
+    /// The block is not partitioned.
+    PartitionNone,
+    /// The block is split horizontally into two blocks.
+    PartitionHorz,
+    /// The block is split vertically into two blocks.
+    PartitionVert,
+    /// The block is split into four blocks.
+    PartitionSplit,
+}
+
+impl From<u8> for PartitionType {
+    fn from(i: u8) -> Self {
+        match i {
+            0 => PartitionType::PartitionNone,
+            1 => PartitionType::PartitionHorz,
+            2 => PartitionType::PartitionVert,
+            _ => PartitionType::PartitionSplit,
+        }
+    }
+}
+
+impl From<PartitionType> for u8 {
+    fn from(p: PartitionType) -> Self {
+        match p {
+            PartitionType::PartitionNone => 0,
+            PartitionType::PartitionHorz => 1,
+            PartitionType::PartitionVert => 2,
+            PartitionType::PartitionSplit => 3,
+        }
+    }
+}
+
+/// Size of a transform.
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
+pub enum TxSize {
+    /// 4x4 transform.
+    Tx4x4,
+    /// 8x8 transform.
+    Tx8x8,
+    /// 16x16 transform.
+    Tx16x16,
+    /// 32x32 transform.
+    Tx32x32,
+}
+
+impl From<u8> for TxSize {
+    fn from(i: u8) -> Self {
+        match i {
+            0 => TxSize::Tx4x4,
+            1 => TxSize::Tx8x8,
+            2 => TxSize::Tx16x16,
+            _ => TxSize::Tx32x32,
+        }
+    }
+}
+
+impl From<TxSize> for u8 {
+    fn from(t: TxSize) -> Self {
+        match t {
+            TxSize::Tx4x4 => 0,
+            TxSize::Tx8x8 => 1,
+            TxSize::Tx16x16 => 2,
+            TxSize::Tx32x32 => 3,
+        }
+    }
+}
+
+/// Type of a transform, consisting of the vertical and the horizontal 1D transform.
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
+pub enum TxType {
+    /// DCT in both directions.
+    DctDct,
+    /// ADST vertically and DCT horizontally.
+    AdstDct,
+    /// DCT vertically and ADST horizontally.
+    DctAdst,
+    /// ADST in both directions.
+    AdstAdst,
+}
+
+impl TxType {
+    // Implements spec "mode2txfm_map".
+    pub(crate) fn from_intra_mode(mode: PredictionMode) -> Self {
+        match mode {
+            PredictionMode::VPred | PredictionMode::D117Pred | PredictionMode::D63Pred => {
+                TxType::AdstDct
+            }
+            PredictionMode::HPred | PredictionMode::D153Pred | PredictionMode::D207Pred => {
+                TxType::DctAdst
+            }
+            PredictionMode::D135Pred | PredictionMode::TmPred => TxType::AdstAdst,
+            _ => TxType::DctDct,
+        }
+    }
+}
+
+impl From<u8> for TxType {
+    fn from(i: u8) -> Self {
+        match i {
+            0 => TxType::DctDct,
+            1 => TxType::AdstDct,
+            2 => TxType::DctAdst,
+            _ => TxType::AdstAdst,
+        }
+    }
+}
+
+impl From<TxType> for u8 {
+    fn from(t: TxType) -> Self {
+        match t {
+            TxType::DctDct => 0,
+            TxType::AdstDct => 1,
+            TxType::DctAdst => 2,
+            TxType::AdstAdst => 3,
+        }
+    }
+}
+
+/// The prediction mode of a block.
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
+pub enum PredictionMode {
+    /// Intra prediction using the average of the above and left edge.
+    DcPred,
+    /// Intra prediction using the above edge.
+    VPred,
+    /// Intra prediction using the left edge.
+    HPred,
+    /// Directional intra prediction at 45 degrees.
+    D45Pred,
+    /// Directional intra prediction at 135 degrees.
+    D135Pred,
+    /// Directional intra prediction at 117 degrees.
+    D117Pred,
+    /// Directional intra prediction at 153 degrees.
+    D153Pred,
+    /// Directional intra prediction at 207 degrees.
+    D207Pred,
+    /// Directional intra prediction at 63 degrees.
+    D63Pred,
+    /// True motion intra prediction.
+    TmPred,
+    /// Inter prediction using the nearest motion vector candidate.
+    NearestMv,
+    /// Inter prediction using the near motion vector candidate.
+    NearMv,
+    /// Inter prediction using a zero motion vector.
+    ZeroMv,
+    /// Inter prediction using a newly coded motion vector.
+    NewMv,
+}
+
+impl From<u8> for PredictionMode {
+    fn from(i: u8) -> Self {
+        match i {
+            0 => PredictionMode::DcPred,
+            1 => PredictionMode::VPred,
+            2 => PredictionMode::HPred,
+            3 => PredictionMode::D45Pred,
+            4 => PredictionMode::D135Pred,
+            5 => PredictionMode::D117Pred,
+            6 => PredictionMode::D153Pred,
+            7 => PredictionMode::D207Pred,
+            8 => PredictionMode::D63Pred,
+            9 => PredictionMode::TmPred,
+            10 => PredictionMode::NearestMv,
+            11 => PredictionMode::NearMv,
+            12 => PredictionMode::ZeroMv,
+            _ => PredictionMode::NewMv,
+        }
+    }
+}
+
+impl From<PredictionMode> for u8 {
+    fn from(m: PredictionMode) -> Self {
+        match m {
+            PredictionMode::DcPred => 0,
+            PredictionMode::VPred => 1,
+            PredictionMode::HPred => 2,
+            PredictionMode::D45Pred => 3,
+            PredictionMode::D135Pred => 4,
+            PredictionMode::D117Pred => 5,
+            PredictionMode::D153Pred => 6,
+            PredictionMode::D207Pred => 7,
+            PredictionMode::D63Pred => 8,
+            PredictionMode::TmPred => 9,
+            PredictionMode::NearestMv => 10,
+            PredictionMode::NearMv => 11,
+            PredictionMode::ZeroMv => 12,
+            PredictionMode::NewMv => 13,
+        }
+    }
+}
+
+/// A motion vector in units of 1/8 pixel.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MotionVector {
+    pub(crate) row: i16,
+    pub(crate) col: i16,
+}
+
+impl MotionVector {
+    /// The vertical component. Positive values point down.
+    pub fn row(&self) -> i16 {
+        self.row
+    }
+
+    /// The horizontal component. Positive values point right.
+    pub fn col(&self) -> i16 {
+        self.col
+    }
+}
+
+/// The motion vector of an inter block for one of its reference frames.
+///
+/// Blocks below 8x8 have one motion vector per sub-block, which is 4x4, 4x8 or 8x4 pixels.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BlockMotionVector {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) width: u8,
+    pub(crate) height: u8,
+    pub(crate) ref_frame: usize,
+    pub(crate) mv: MotionVector,
+}
+
+impl BlockMotionVector {
+    /// The horizontal position of the top left corner in luma pixels.
+    pub fn x(&self) -> u32 {
+        self.x
+    }
+
+    /// The vertical position of the top left corner in luma pixels.
+    pub fn y(&self) -> u32 {
+        self.y
+    }
+
+    /// The width in luma pixels.
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    /// The height in luma pixels.
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+
+    /// The reference frame: `LAST_FRAME`, `GOLDEN_FRAME` or `ALTREF_FRAME`.
+    pub fn ref_frame(&self) -> usize {
+        self.ref_frame
+    }
+
+    /// The motion vector in units of 1/8 pixel.
+    pub fn mv(&self) -> MotionVector {
+        self.mv
+    }
+}
+
+/// The mode info of a block.
+///
+/// Blocks with a size below 8x8 store the modes of their 4x4 sub-blocks in `sub_modes()`
+/// and their motion vectors in `sub_mvs()`.
+#[derive(Clone, Copy, Debug)]
+pub struct ModeInfo {
+    pub(crate) mi_row: u16,
+    pub(crate) mi_col: u16,
+    pub(crate) block_size: BlockSize,
+    pub(crate) partition: PartitionType,
+    pub(crate) segment_id: u8,
+    pub(crate) skip: bool,
+    pub(crate) tx_size: TxSize,
+    pub(crate) y_mode: PredictionMode,
+    pub(crate) sub_modes: [PredictionMode; 4],
+    pub(crate) uv_mode: PredictionMode,
+    pub(crate) is_inter: bool,
+    pub(crate) ref_frame: [usize; 2],
+    pub(crate) interp_filter: InterpolationFilter,
+    pub(crate) sub_mvs: [[MotionVector; 2]; 4],
+}
+
+impl Default for ModeInfo {
+    fn default() -> Self {
+        Self {
+            mi_row: 0,
+            mi_col: 0,
+            block_size: BlockSize::Block64x64,
+            partition: PartitionType::PartitionNone,
+            segment_id: 0,
+            skip: false,
+            tx_size: TxSize::Tx4x4,
+            y_mode: PredictionMode::DcPred,
+            sub_modes: [PredictionMode::DcPred; 4],
+            uv_mode: PredictionMode::DcPred,
+            is_inter: false,
+            ref_frame: [0; 2],
+            interp_filter: InterpolationFilter::Eighttap,
+            sub_mvs: [[MotionVector::default(); 2]; 4],
+        }
+    }
+}
+
+impl ModeInfo {
+    /// The mode info row of the top left corner of the block.
+    pub fn mi_row(&self) -> u16 {
+        self.mi_row
+    }
+
+    /// The mode info column of the top left corner of the block.
+    pub fn mi_col(&self) -> u16 {
+        self.mi_col
+    }
+
+    /// The size of the block.
+    pub fn block_size(&self) -> BlockSize {
+        self.block_size
+    }
+
+    /// The partition type that produced the block.
+    pub fn partition(&self) -> PartitionType {
+        self.partition
+    }
+
+    /// The segment the block belongs to.
+    pub fn segment_id(&self) -> u8 {
+        self.segment_id
+    }
+
+    /// Indicates that the block has no residual coefficients.
+    pub fn skip(&self) -> bool {
+        self.skip
+    }
+
+    /// The size of the luma transforms.
+    pub fn tx_size(&self) -> TxSize {
+        self.tx_size
+    }
+
+    /// The luma prediction mode. For blocks below 8x8 the mode of the last sub-block.
+    pub fn y_mode(&self) -> PredictionMode {
+        self.y_mode
+    }
+
+    /// The luma prediction modes of the four 4x4 sub-blocks in raster order.
+    /// All four equal `y_mode()` for blocks of 8x8 and above.
+    pub fn sub_modes(&self) -> &[PredictionMode; 4] {
+        &self.sub_modes
+    }
+
+    /// The chroma prediction mode.
+    pub fn uv_mode(&self) -> PredictionMode {
+        self.uv_mode
+    }
+
+    /// Indicates that the block uses inter prediction.
+    pub fn is_inter(&self) -> bool {
+        self.is_inter
+    }
+
+    /// The reference frames of the block: 0 (intra), 1 (last), 2 (golden) or 3 (altref).
+    /// The second reference frame is 0 if the block doesn't use compound prediction.
+    pub fn ref_frames(&self) -> &[usize; 2] {
+        &self.ref_frame
+    }
+
+    /// The interpolation filter of an inter block.
+    pub fn interp_filter(&self) -> InterpolationFilter {
+        self.interp_filter
+    }
+
+    /// The motion vectors of both reference frames. For blocks below 8x8 the motion
+    /// vectors of the last sub-block.
+    pub fn mv(&self) -> &[MotionVector; 2] {
+        &self.sub_mvs[3]
+    }
+
+    /// The motion vectors of the four 4x4 sub-blocks in raster order.
+    /// All four equal `mv()` for blocks of 8x8 and above.
+    pub fn sub_mvs(&self) -> &[[MotionVector; 2]; 4] {
+        &self.sub_mvs
+    }
+}
+
+/// The mode info of a frame in units of 8x8 pixels (mode info units).
+///
+/// Every position inside the grid holds the mode info of the block covering it.
+#[derive(Clone, Debug)]
+pub struct ModeInfoGrid {
+    mi_cols: u16,
+    mi_rows: u16,
+    mode_infos: Vec<ModeInfo>,
+}
+
+impl ModeInfoGrid {
+    pub(crate) fn new(mi_cols: u16, mi_rows: u16) -> Self {
+        Self {
+            mi_cols,
+            mi_rows,
+            mode_infos: vec![ModeInfo::default(); usize::from(mi_cols) * usize::from(mi_rows)],
+        }
+    }
+
+    /// Number of mode info columns.
+    pub fn mi_cols(&self) -> u16 {
+        self.mi_cols
+    }
+
+    /// Number of mode info rows.
+    pub fn mi_rows(&self) -> u16 {
+        self.mi_rows
+    }
+
+    /// Returns the mode info of the block covering the given position.
+    pub fn get(&self, mi_row: u16, mi_col: u16) -> Option<&ModeInfo> {
+        if mi_row >= self.mi_rows || mi_col >= self.mi_cols {
+            return None;
+        }
+        self.mode_infos
+            .get(usize::from(mi_row) * usize::from(self.mi_cols) + usize::from(mi_col))
+    }
+
+    /// The mode info of all positions in raster order.
+    pub fn mode_infos(&self) -> &[ModeInfo] {
+        &self.mode_infos
+    }
+
+    /// Iterates over the blocks of the frame. Every block is returned once.
+    pub fn blocks(&self) -> impl Iterator<Item = &ModeInfo> {
+        let mi_cols = usize::from(self.mi_cols);
+        self.mode_infos
+            .iter()
+            .enumerate()
+            .filter(move |(i, mode_info)| {
+                usize::from(mode_info.mi_row) * mi_cols + usize::from(mode_info.mi_col) == *i
+            })
+            .map(|(_, mode_info)| mode_info)
+    }
+
+    /// The motion vectors of all inter blocks in raster order of the blocks. Compound blocks
+    /// have one entry per reference frame.
+    pub(crate) fn motion_vectors(&self) -> Vec<BlockMotionVector> {
+        let mut motion_vectors = Vec::new();
+        for block in self.blocks().filter(|block| block.is_inter) {
+            // Blocks of 8x8 and above store the same motion vector for every sub-block.
+            let width = block.block_size.width().min(8);
+            let height = block.block_size.height().min(8);
+            for offset_y in (0..8u8).step_by(usize::from(height)) {
+                for offset_x in (0..8u8).step_by(usize::from(width)) {
+                    let sub_block = usize::from(offset_y / 4 * 2 + offset_x / 4);
+                    for (ref_list, ref_frame) in block.ref_frame.iter().enumerate() {
+                        if *ref_frame == INTRA_FRAME {
+                            continue;
+                        }
+                        motion_vectors.push(BlockMotionVector {
+                            x: u32::from(block.mi_col) * 8 + u32::from(offset_x),
+                            y: u32::from(block.mi_row) * 8 + u32::from(offset_y),
+                            width: block.block_size.width().max(width),
+                            height: block.block_size.height().max(height),
+                            ref_frame: *ref_frame,
+                            mv: block.sub_mvs[sub_block][ref_list],
+                        });
+                    }
+                }
+            }
+        }
+        motion_vectors
+    }
+
+    /// Copies the mode info of a tile column into the grid.
+    pub(crate) fn copy_columns(
+        &mut self,
+        mi_col_start: u16,
+        mi_col_end: u16,
+        mode_infos: &[ModeInfo],
+    ) {
+        let mi_cols = usize::from(self.mi_cols);
+        let width = usize::from(mi_col_end - mi_col_start);
+        let start = usize::from(mi_col_start);
+        for (row, column_row) in mode_infos.chunks_exact(width).enumerate() {
+            self.mode_infos[row * mi_cols + start..row * mi_cols + start + width]
+                .copy_from_slice(column_row);
+        }
+    }
+}
+
+/// The segment ids of a frame in units of 8x8 blocks (mode info units).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SegmentMap {
+    mi_cols: u16,
+    mi_rows: u16,
+    segment_ids: Vec<u8>,
+}
+
+impl SegmentMap {
+    /// Creates the map of the segment ids of the decoded blocks.
+    pub(crate) fn from_mode_info(mode_info: &ModeInfoGrid) -> Self {
+        Self {
+            mi_cols: mode_info.mi_cols,
+            mi_rows: mode_info.mi_rows,
+            segment_ids: mode_info
+                .mode_infos
+                .iter()
+                .map(|mode_info| mode_info.segment_id)
+                .collect(),
+        }
+    }
+
+    /// Number of mode info columns.
+    pub fn mi_cols(&self) -> u16 {
+        self.mi_cols
+    }
+
+    /// Number of mode info rows.
+    pub fn mi_rows(&self) -> u16 {
+        self.mi_rows
+    }
+
+    /// The segment id at the given mode info position.
+    pub fn get(&self, mi_row: u16, mi_col: u16) -> Option<u8> {
+        if mi_row >= self.mi_rows || mi_col >= self.mi_cols {
+            return None;
+        }
+        self.segment_ids
+            .get(usize::from(mi_row) * usize::from(self.mi_cols) + usize::from(mi_col))
+            .copied()
+    }
+
+    /// The segment ids of all positions in raster order.
+    pub fn segment_ids(&self) -> &[u8] {
+        &self.segment_ids
+    }
+
+    // Implements spec "get_segment_id()": the smallest segment id of the positions the block
+    // covers inside the frame.
+    pub(crate) fn predicted_segment_id(&self, mode_info: &ModeInfo) -> u8 {
+        let mi_row_end = (mode_info.mi_row + mode_info.block_size.num_8x8_high()).min(self.mi_rows);
+        let mi_col_end = (mode_info.mi_col + mode_info.block_size.num_8x8_wide()).min(self.mi_cols);
+        (mode_info.mi_row..mi_row_end)
+            .flat_map(|mi_row| {
+                (mode_info.mi_col..mi_col_end).filter_map(move |mi_col| self.get(mi_row, mi_col))
+            })
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+/// The superblocks of a frame whose tile data failed to decode and that were concealed.
+///
+/// Positions are given in mode info units. Concealed superblocks of inter frames are copied
+/// from the co-located area of the `LAST` reference frame, those of intra frames are filled
+/// with mid-grey.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConcealedRegion {
+    superblocks: Vec<(u16, u16)>,
+    mi_row_start: u16,
+    mi_row_end: u16,
+    mi_col_start: u16,
+    mi_col_end: u16,
+}
+
+impl ConcealedRegion {
+    /// Creates the region of the given superblock positions, clamped to the frame size.
+    /// Returns `None` if there are no concealed superblocks.
+    pub(crate) fn new(
+        mut superblocks: Vec<(u16, u16)>,
+        mi_rows: u16,
+        mi_cols: u16,
+    ) -> Option<Self> {
+        superblocks.sort_unstable();
+        let mi_row_start = superblocks.iter().map(|(mi_row, _)| *mi_row).min()?;
+        let mi_col_start = superblocks.iter().map(|(_, mi_col)| *mi_col).min()?;
+        let mi_row_end = superblocks.iter().map(|(mi_row, _)| *mi_row + 8).max()?;
+        let mi_col_end = superblocks.iter().map(|(_, mi_col)| *mi_col + 8).max()?;
+        Some(Self {
+            superblocks,
+            mi_row_start,
+            mi_row_end: mi_row_end.min(mi_rows),
+            mi_col_start,
+            mi_col_end: mi_col_end.min(mi_cols),
+        })
+    }
+
+    /// The mode info positions (row, column) of the top left corner of the concealed
+    /// superblocks in raster order.
+    pub fn superblocks(&self) -> &[(u16, u16)] {
+        &self.superblocks
+    }
+
+    /// The first mode info row of the bounding box of the concealed superblocks.
+    pub fn mi_row_start(&self) -> u16 {
+        self.mi_row_start
+    }
+
+    /// The mode info row after the last row of the bounding box.
+    pub fn mi_row_end(&self) -> u16 {
+        self.mi_row_end
+    }
+
+    /// The first mode info column of the bounding box of the concealed superblocks.
+    pub fn mi_col_start(&self) -> u16 {
+        self.mi_col_start
+    }
+
+    /// The mode info column after the last column of the bounding box.
+    pub fn mi_col_end(&self) -> u16 {
+        self.mi_col_end
+    }
+
+    /// Indicates if the given mode info position lies inside a concealed superblock.
+    pub fn contains(&self, mi_row: u16, mi_col: u16) -> bool {
+        mi_row < self.mi_row_end
+            && mi_col < self.mi_col_end
+            && self
+                .superblocks
+                .binary_search(&(mi_row & !7, mi_col & !7))
+                .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    #[test]
+    fn predicted_segment_id() {
+        let mut grid = ModeInfoGrid::new(3, 2);
+        for (i, mode_info) in grid.mode_infos.iter_mut().enumerate() {
+            mode_info.segment_id = [5, 3, 6, 4, 2, 7][i];
+        }
+        let map = SegmentMap::from_mode_info(&grid);
+        assert_eq!(map.get(1, 2), Some(7));
+        assert_eq!(map.get(2, 0), None);
+
+        let block = |mi_row, mi_col, block_size| ModeInfo {
+            mi_row,
+            mi_col,
+            block_size,
+            ..Default::default()
+        };
+        assert_eq!(
+            map.predicted_segment_id(&block(0, 0, BlockSize::Block8x8)),
+            5
+        );
+        assert_eq!(
+            map.predicted_segment_id(&block(0, 0, BlockSize::Block16x8)),
+            3
+        );
+        assert_eq!(
+            map.predicted_segment_id(&block(0, 2, BlockSize::Block8x16)),
+            6
+        );
+        // Positions outside of the frame are ignored.
+        assert_eq!(
+            map.predicted_segment_id(&block(0, 2, BlockSize::Block64x64)),
+            6
+        );
+        assert_eq!(
+            map.predicted_segment_id(&block(0, 0, BlockSize::Block64x64)),
+            2
+        );
+    }
+
+    #[test]
+    fn concealed_region() {
+        assert!(ConcealedRegion::new(Vec::new(), 23, 40).is_none());
+
+        let region = ConcealedRegion::new(vec![(16, 32), (8, 16), (16, 0)], 23, 40).unwrap();
+        assert_eq!(region.superblocks(), &[(8, 16), (16, 0), (16, 32)]);
+        assert_eq!(region.mi_row_start(), 8);
+        assert_eq!(region.mi_row_end(), 23);
+        assert_eq!(region.mi_col_start(), 0);
+        assert_eq!(region.mi_col_end(), 40);
+        assert!(region.contains(15, 23));
+        assert!(region.contains(22, 39));
+        assert!(!region.contains(23, 39));
+        assert!(!region.contains(8, 8));
+    }
+}
@@ -0,0 +1,165 @@
+//! Sample buffers of the planes of a frame.
+
+use crate::{ColorDepth, Frame};
+
+/// Alignment of the allocated buffer in luma samples (the size of a super block).
+const ALLOCATION_ALIGNMENT: usize = 64;
+
+/// Alignment of the decoded area in luma samples (the size of a mode info block).
+const DECODED_ALIGNMENT: usize = 8;
+
+/// A plane of a frame holding samples of up to 16 bit.
+///
+/// The visible size of the plane is derived from the frame size and the subsampling. Decoding
+/// writes whole blocks, so the buffer is allocated in units of super blocks. The decoded area
+/// covers all mode info blocks of the frame and is used when extending the edges of the
+/// prediction.
+#[derive(Clone, Debug)]
+pub struct Plane {
+    width: usize,
+    height: usize,
+    decoded_width: usize,
+    decoded_height: usize,
+    stride: usize,
+    rows: usize,
+    bit_depth: u8,
+    data: Vec<u16>,
+}
+
+impl Plane {
+    /// Creates a plane for a frame of the given luma size. The samples are initialized with zero.
+    pub fn new(
+        width: u16,
+        height: u16,
+        subsampling_x: bool,
+        subsampling_y: bool,
+        color_depth: ColorDepth,
+    ) -> Self {
+        let width = usize::from(width);
+        let height = usize::from(height);
+        let ss_x = usize::from(subsampling_x);
+        let ss_y = usize::from(subsampling_y);
+        let stride = align(width, ALLOCATION_ALIGNMENT) >> ss_x;
+        let rows = align(height, ALLOCATION_ALIGNMENT) >> ss_y;
+
+        Self {
+            width: (width + ss_x) >> ss_x,
+            height: (height + ss_y) >> ss_y,
+            decoded_width: align(width, DECODED_ALIGNMENT) >> ss_x,
+            decoded_height: align(height, DECODED_ALIGNMENT) >> ss_y,
+            stride,
+            rows,
+            bit_depth: bit_depth(color_depth),
+            data: vec![0; stride * rows],
+        }
+    }
+
+    /// Creates a plane of the given size in samples without any padding. The samples are
+    /// initialized with zero.
+    pub(crate) fn unpadded(width: usize, height: usize, bit_depth: u8) -> Self {
+        Self {
+            width,
+            height,
+            decoded_width: width,
+            decoded_height: height,
+            stride: width,
+            rows: height,
+            bit_depth,
+            data: vec![0; width * height],
+        }
+    }
+
+    /// Creates the given plane (0 = Y, 1 = U, 2 = V) for the size, subsampling and color
+    /// depth of the frame.
+    pub fn from_frame(frame: &Frame, plane: usize) -> Self {
+        let (subsampling_x, subsampling_y) = if plane > 0 {
+            (frame.subsampling_x(), frame.subsampling_y())
+        } else {
+            (false, false)
+        };
+        Self::new(
+            frame.width(),
+            frame.height(),
+            subsampling_x,
+            subsampling_y,
+            frame.color_depth(),
+        )
+    }
+
+    /// The visible width in samples.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The visible height in samples.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The width of the area covered by mode info blocks in samples.
+    pub fn decoded_width(&self) -> usize {
+        self.decoded_width
+    }
+
+    /// The height of the area covered by mode info blocks in samples.
+    pub fn decoded_height(&self) -> usize {
+        self.decoded_height
+    }
+
+    /// The distance between two rows in samples, which is the allocated width.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// The number of allocated rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// The number of bits per sample.
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    /// The largest value a sample can hold.
+    pub fn max_value(&self) -> u16 {
+        (1 << self.bit_depth) - 1
+    }
+
+    /// The allocated samples in raster order.
+    pub fn data(&self) -> &[u16] {
+        &self.data
+    }
+
+    /// The allocated samples in raster order.
+    pub fn data_mut(&mut self) -> &mut [u16] {
+        &mut self.data
+    }
+
+    /// The visible samples of a row. Returns `None` if the row is outside of the visible area.
+    pub fn row(&self, y: usize) -> Option<&[u16]> {
+        if y >= self.height {
+            return None;
+        }
+        let start = y * self.stride;
+        Some(&self.data[start..start + self.width])
+    }
+
+    /// Returns `true` if a block of the given size at the given position fits into the
+    /// allocated buffer.
+    pub(crate) fn contains(&self, x: usize, y: usize, width: usize, height: usize) -> bool {
+        x + width <= self.stride && y + height <= self.rows
+    }
+}
+
+fn align(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) & !(alignment - 1)
+}
+
+fn bit_depth(color_depth: ColorDepth) -> u8 {
+    match color_depth {
+        ColorDepth::Depth10 => 10,
+        ColorDepth::Depth12 => 12,
+        ColorDepth::Unknown | ColorDepth::Depth8 => 8,
+    }
+}
@@ -0,0 +1,68 @@
+//! Dequantized coefficients of the transform blocks.
+
+use crate::mode_info::{TxSize, TxType};
+
+/// A transform block of a plane with its dequantized coefficients.
+///
+/// Transform blocks are stored in decoding order. Blocks without residual (skipped blocks
+/// or blocks whose tokens end immediately) are included, so that every transform block
+/// inside the frame can be predicted and reconstructed in order.
+#[derive(Clone, Debug)]
+pub struct TransformBlock {
+    pub(crate) mi_row: u16,
+    pub(crate) mi_col: u16,
+    pub(crate) plane: usize,
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) tx_size: TxSize,
+    pub(crate) tx_type: TxType,
+    pub(crate) eob: usize,
+    pub(crate) coefficients: Vec<i32>,
+}
+
+impl TransformBlock {
+    /// Mode info row of the block the transform block belongs to.
+    pub fn mi_row(&self) -> u16 {
+        self.mi_row
+    }
+
+    /// Mode info column of the block the transform block belongs to.
+    pub fn mi_col(&self) -> u16 {
+        self.mi_col
+    }
+
+    /// The plane of the transform block (0 = Y, 1 = U, 2 = V).
+    pub fn plane(&self) -> usize {
+        self.plane
+    }
+
+    /// Horizontal position of the top left pixel inside the plane.
+    pub fn x(&self) -> u32 {
+        self.x
+    }
+
+    /// Vertical position of the top left pixel inside the plane.
+    pub fn y(&self) -> u32 {
+        self.y
+    }
+
+    /// The size of the transform.
+    pub fn tx_size(&self) -> TxSize {
+        self.tx_size
+    }
+
+    /// The type of the transform. Also determines the scan order of the coefficients.
+    pub fn tx_type(&self) -> TxType {
+        self.tx_type
+    }
+
+    /// The end of block, which is the number of coefficients read in scan order.
+    pub fn eob(&self) -> usize {
+        self.eob
+    }
+
+    /// The dequantized coefficients in raster order. Empty if the end of block is zero.
+    pub fn coefficients(&self) -> &[i32] {
+        &self.coefficients
+    }
+}
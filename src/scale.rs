@@ -0,0 +1,296 @@
+//! Cropping and scaling of decoded pictures to their output size.
+//!
+//! The planes of a decoded frame are allocated in whole super blocks. Cropping copies the
+//! visible samples into planes without padding. Frames can signal a render size that differs
+//! from the coded size, for example if the encoder lowered the internal resolution. Scaling
+//! resamples the cropped planes to the render size with a separable filter, where the centers
+//! of the first and last samples of the source and the target are aligned.
+
+use std::{convert::TryFrom, sync::Arc};
+
+use crate::{Frame, Plane, Result};
+
+/// Precision of the sub-sample position of the filter taps.
+const POSITION_BITS: u32 = 6;
+
+/// The filter used to scale pictures to the render size.
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
+pub enum ScalingFilter {
+    /// Linear interpolation between the two nearest samples.
+    Bilinear,
+    /// Cubic interpolation of the four nearest samples (Catmull-Rom).
+    Bicubic,
+}
+
+impl ScalingFilter {
+    /// The weights of the four taps at the given sub-sample position and the sum of the
+    /// weights.
+    fn weights(self, position: i64) -> ([i64; 4], i64) {
+        let n = 1 << POSITION_BITS;
+        let f = position;
+        match self {
+            ScalingFilter::Bilinear => ([0, n - f, f, 0], n),
+            // The Catmull-Rom weights, scaled by 2 * n^3 to keep them integral.
+            ScalingFilter::Bicubic => (
+                [
+                    -f * f * f + 2 * n * f * f - n * n * f,
+                    3 * f * f * f - 5 * n * f * f + 2 * n * n * n,
+                    -3 * f * f * f + 4 * n * f * f + n * n * f,
+                    f * f * f - n * f * f,
+                ],
+                2 * n * n * n,
+            ),
+        }
+    }
+}
+
+/// The size of the pictures returned by the decoders.
+#[derive(Clone, Copy, Debug, Default, Ord, PartialOrd, Eq, PartialEq)]
+pub enum OutputSize {
+    /// The planes as decoded. The visible size is the coded size of the frame, but the
+    /// buffers are padded to whole super blocks.
+    #[default]
+    Decoded,
+    /// The planes cropped to the coded size of the frame, without any padding.
+    Cropped,
+    /// The planes cropped and scaled to the render size of the frame with the given filter.
+    /// Frames whose render size equals the coded size are only cropped.
+    Render(ScalingFilter),
+}
+
+/// Returns the planes of a decoded frame in the requested output size and the output size
+/// that was applied.
+pub(crate) fn output_planes(
+    frame: &Frame,
+    planes: Arc<[Plane; 3]>,
+    output_size: OutputSize,
+) -> Result<(Arc<[Plane; 3]>, OutputSize)> {
+    let render_size = (
+        usize::from(frame.render_width()),
+        usize::from(frame.render_height()),
+    );
+    match output_size {
+        OutputSize::Decoded => Ok((planes, OutputSize::Decoded)),
+        OutputSize::Render(filter)
+            if render_size.0 > 0
+                && render_size.1 > 0
+                && render_size != (planes[0].width(), planes[0].height()) =>
+        {
+            let (width, height) = render_size;
+            let ss_x = usize::from(frame.subsampling_x());
+            let ss_y = usize::from(frame.subsampling_y());
+            let scaled = [
+                scale(&planes[0], width, height, filter)?,
+                scale(
+                    &planes[1],
+                    (width + ss_x) >> ss_x,
+                    (height + ss_y) >> ss_y,
+                    filter,
+                )?,
+                scale(
+                    &planes[2],
+                    (width + ss_x) >> ss_x,
+                    (height + ss_y) >> ss_y,
+                    filter,
+                )?,
+            ];
+            Ok((Arc::new(scaled), output_size))
+        }
+        OutputSize::Cropped | OutputSize::Render(_) => {
+            Ok((Arc::new(planes.each_ref().map(crop)), OutputSize::Cropped))
+        }
+    }
+}
+
+/// Copies the visible samples of a plane into a plane without padding.
+fn crop(plane: &Plane) -> Plane {
+    let mut cropped = Plane::unpadded(plane.width(), plane.height(), plane.bit_depth());
+    let width = plane.width();
+    for (y, row) in cropped
+        .data_mut()
+        .chunks_exact_mut(width.max(1))
+        .enumerate()
+    {
+        if let Some(source) = plane.row(y) {
+            row.copy_from_slice(source);
+        }
+    }
+    cropped
+}
+
+/// The source samples and weights of a target sample.
+struct Taps {
+    indices: [usize; 4],
+    weights: [i64; 4],
+}
+
+/// Returns the taps of every target sample when scaling `source` samples to `target` samples.
+fn taps(source: usize, target: usize, filter: ScalingFilter) -> Result<(Vec<Taps>, i64)> {
+    let (_, sum) = filter.weights(0);
+    let source = i64::try_from(source)?;
+    let target = i64::try_from(target)?;
+    if source == 0 {
+        return Ok((Vec::new(), sum));
+    }
+
+    let taps = (0..target)
+        .map(|x| {
+            // The center of the target sample in source samples: (x + 0.5) * source / target - 0.5.
+            let position = (2 * x + 1) * source - target;
+            let integer = position.div_euclid(2 * target);
+            let fraction = (position.rem_euclid(2 * target) << POSITION_BITS) / (2 * target);
+            let indices = [-1, 0, 1, 2].map(|offset| {
+                usize::try_from((integer + offset).clamp(0, source - 1)).unwrap_or(0)
+            });
+            let (weights, _) = filter.weights(fraction);
+            Taps { indices, weights }
+        })
+        .collect();
+    Ok((taps, sum))
+}
+
+/// Scales the visible samples of a plane to the given size.
+fn scale(plane: &Plane, width: usize, height: usize, filter: ScalingFilter) -> Result<Plane> {
+    let (horizontal, sum) = taps(plane.width(), width, filter)?;
+    let (vertical, _) = taps(plane.height(), height, filter)?;
+
+    // Horizontal pass with the full precision of the weights.
+    let mut rows = vec![0i64; width * plane.height()];
+    for (y, row) in rows.chunks_exact_mut(width.max(1)).enumerate() {
+        let Some(source) = plane.row(y) else {
+            break;
+        };
+        for (sample, taps) in row.iter_mut().zip(horizontal.iter()) {
+            *sample = taps
+                .indices
+                .iter()
+                .zip(taps.weights)
+                .map(|(index, weight)| weight * i64::from(source[*index]))
+                .sum();
+        }
+    }
+
+    let mut scaled = Plane::unpadded(width, height, plane.bit_depth());
+    let max_value = i64::from(plane.max_value());
+    let norm = sum * sum;
+    for (row, taps) in scaled
+        .data_mut()
+        .chunks_exact_mut(width.max(1))
+        .zip(vertical.iter())
+    {
+        for (x, sample) in row.iter_mut().enumerate() {
+            let value: i64 = taps
+                .indices
+                .iter()
+                .zip(taps.weights)
+                .map(|(index, weight)| weight * rows[index * width + x])
+                .sum();
+            let value = (value + norm / 2).div_euclid(norm).clamp(0, max_value);
+            *sample = u16::try_from(value).unwrap_or_default();
+        }
+    }
+    Ok(scaled)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use std::fs::File;
+
+    use super::*;
+    use crate::{ivf::Ivf, ColorDepth, Vp9Decoder};
+
+    fn plane(width: usize, height: usize, samples: &[u16]) -> Plane {
+        let mut plane = Plane::unpadded(width, height, 8);
+        plane.data_mut().copy_from_slice(samples);
+        plane
+    }
+
+    fn samples(plane: &Plane) -> Vec<u16> {
+        (0..plane.height())
+            .flat_map(|y| plane.row(y).unwrap().iter().copied())
+            .collect()
+    }
+
+    #[test]
+    fn filter_weights() {
+        for filter in [ScalingFilter::Bilinear, ScalingFilter::Bicubic] {
+            let (_, sum) = filter.weights(0);
+            for position in 0..1 << POSITION_BITS {
+                let (weights, _) = filter.weights(position);
+                assert_eq!(weights.iter().sum::<i64>(), sum);
+            }
+        }
+        let (weights, sum) = ScalingFilter::Bicubic.weights(0);
+        assert_eq!(weights, [0, sum, 0, 0]);
+    }
+
+    #[test]
+    fn crop_plane() {
+        let mut decoded = Plane::new(3, 2, false, false, ColorDepth::Depth10);
+        for (i, sample) in decoded.data_mut().iter_mut().enumerate() {
+            *sample = u16::try_from(i % 64).unwrap();
+        }
+        let cropped = crop(&decoded);
+        assert_eq!(cropped.stride(), 3);
+        assert_eq!(cropped.rows(), 2);
+        assert_eq!(cropped.bit_depth(), 10);
+        assert_eq!(cropped.data(), &[0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn scale_plane() {
+        let source = plane(2, 2, &[0, 64, 64, 128]);
+        for filter in [ScalingFilter::Bilinear, ScalingFilter::Bicubic] {
+            // The same size keeps the samples.
+            assert_eq!(
+                samples(&scale(&source, 2, 2, filter).unwrap()),
+                [0, 64, 64, 128]
+            );
+            // A flat plane stays flat.
+            let flat = plane(3, 2, &[77; 6]);
+            let scaled = scale(&flat, 7, 5, filter).unwrap();
+            assert_eq!(samples(&scaled), [77; 35]);
+        }
+
+        // Bilinear upscaling interpolates between the sample centers.
+        let scaled = scale(&plane(2, 1, &[0, 64]), 4, 1, ScalingFilter::Bilinear).unwrap();
+        assert_eq!(samples(&scaled), [0, 16, 48, 64]);
+
+        // Bicubic downscaling by 2 averages the centered samples and is clamped.
+        let scaled = scale(
+            &plane(4, 1, &[0, 0, 255, 255]),
+            2,
+            1,
+            ScalingFilter::Bicubic,
+        )
+        .unwrap();
+        assert_eq!(samples(&scaled), [0, 255]);
+    }
+
+    #[test]
+    fn render_size() {
+        let file = File::open("tests/data/320-24-crf.ivf").unwrap();
+        let mut ivf = Ivf::new(file).unwrap();
+        let packet = ivf.read_frame().unwrap().unwrap().packet;
+        let picture = Vp9Decoder::new().decode_packet(packet).unwrap().remove(0);
+        let mut frame = picture.frame().clone();
+        let planes = Arc::new(picture.planes().clone());
+
+        // The render size of the stream equals the coded size.
+        let output_size = OutputSize::Render(ScalingFilter::Bicubic);
+        let (cropped, applied) = output_planes(&frame, planes.clone(), output_size).unwrap();
+        assert_eq!(applied, OutputSize::Cropped);
+        assert_eq!((cropped[0].stride(), cropped[0].rows()), (320, 180));
+        assert_eq!((cropped[1].stride(), cropped[1].rows()), (160, 90));
+
+        frame.render_width = 160;
+        frame.render_height = 90;
+        let (scaled, applied) = output_planes(&frame, planes, output_size).unwrap();
+        assert_eq!(applied, output_size);
+        assert_eq!((scaled[0].width(), scaled[0].height()), (160, 90));
+        assert_eq!((scaled[2].width(), scaled[2].height()), (80, 45));
+        assert_eq!(scaled[0].stride(), 160);
+    }
+}
@@ -0,0 +1,279 @@
+//! Splitting of the tile data into the individual tiles.
+
+use std::convert::TryFrom;
+
+use crate::{Frame, Result, Vp9ParserError};
+
+/// Number of bytes of the tile size marker.
+const TILE_SIZE_BYTES: usize = 4;
+
+/// A tile of a frame.
+///
+/// Tiles are independently arithmetic coded parts of the frame. The position of the tile
+/// is given in units of 8x8 pixel blocks (mode info units).
+#[derive(Clone, Copy, Debug)]
+pub struct Tile<'a> {
+    row: u8,
+    col: u8,
+    data: &'a [u8],
+    mi_row_start: u16,
+    mi_row_end: u16,
+    mi_col_start: u16,
+    mi_col_end: u16,
+}
+
+impl<'a> Tile<'a> {
+    /// The tile row of the tile.
+    pub fn row(&self) -> u8 {
+        self.row
+    }
+
+    /// The tile column of the tile.
+    pub fn col(&self) -> u8 {
+        self.col
+    }
+
+    /// The arithmetic coded data of the tile (without the tile size marker).
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The first mode info row of the tile.
+    pub fn mi_row_start(&self) -> u16 {
+        self.mi_row_start
+    }
+
+    /// The mode info row after the last row of the tile.
+    pub fn mi_row_end(&self) -> u16 {
+        self.mi_row_end
+    }
+
+    /// The first mode info column of the tile.
+    pub fn mi_col_start(&self) -> u16 {
+        self.mi_col_start
+    }
+
+    /// The mode info column after the last column of the tile.
+    pub fn mi_col_end(&self) -> u16 {
+        self.mi_col_end
+    }
+}
+
+/// Iterator over the tiles of a frame. Created by `Frame::tiles()`.
+///
+/// Tiles are returned in raster order. Every tile but the last one is prefixed by
+/// a 4 byte big endian tile size marker. Stops after the first error.
+#[derive(Clone, Debug)]
+pub struct Tiles<'a> {
+    data: &'a [u8],
+    tile_rows: u8,
+    tile_cols: u8,
+    tile_rows_log2: u8,
+    tile_cols_log2: u8,
+    mi_rows: u16,
+    mi_cols: u16,
+    row: u8,
+    col: u8,
+    failed: bool,
+}
+
+impl<'a> Tiles<'a> {
+    pub(crate) fn new(frame: &'a Frame) -> Self {
+        Self::from_parts(
+            frame.tile_data(),
+            frame.tile_rows_log2(),
+            frame.tile_cols_log2(),
+            frame.mi_rows(),
+            frame.mi_cols(),
+        )
+    }
+
+    fn from_parts(
+        data: &'a [u8],
+        tile_rows_log2: u8,
+        tile_cols_log2: u8,
+        mi_rows: u16,
+        mi_cols: u16,
+    ) -> Self {
+        Self {
+            data,
+            tile_rows: 1 << tile_rows_log2,
+            tile_cols: 1 << tile_cols_log2,
+            tile_rows_log2,
+            tile_cols_log2,
+            mi_rows,
+            mi_cols,
+            row: 0,
+            col: 0,
+            failed: false,
+        }
+    }
+
+    /// Returns all tiles, also if the tile data is truncated or corrupted. A tile whose size
+    /// marker is missing or too large gets the remaining data and the following tiles get no
+    /// data.
+    pub(crate) fn collect_damaged(mut self) -> Vec<Tile<'a>> {
+        let mut tiles = Vec::new();
+        while self.row < self.tile_rows {
+            let tile_size = if self.is_last_tile() {
+                self.data.len()
+            } else {
+                self.read_tile_size().unwrap_or(self.data.len())
+            };
+            tiles.push(self.take_tile(tile_size));
+        }
+        tiles
+    }
+
+    fn next_tile(&mut self) -> Result<Tile<'a>> {
+        let tile_size = if self.is_last_tile() {
+            self.data.len()
+        } else {
+            self.read_tile_size()?
+        };
+        Ok(self.take_tile(tile_size))
+    }
+
+    fn is_last_tile(&self) -> bool {
+        self.row == self.tile_rows - 1 && self.col == self.tile_cols - 1
+    }
+
+    /// Reads the tile size marker of a tile that is not the last one.
+    fn read_tile_size(&mut self) -> Result<usize> {
+        if self.data.len() < TILE_SIZE_BYTES {
+            return Err(Vp9ParserError::TileDataTooShort);
+        }
+        let (marker, data) = self.data.split_at(TILE_SIZE_BYTES);
+        self.data = data;
+        let tile_size = u32::from_be_bytes([marker[0], marker[1], marker[2], marker[3]]);
+        let tile_size = usize::try_from(tile_size)?;
+        if tile_size > self.data.len() {
+            return Err(Vp9ParserError::InvalidTileSize(tile_size));
+        }
+        Ok(tile_size)
+    }
+
+    /// Splits the data of the current tile off and advances to the next tile.
+    fn take_tile(&mut self, tile_size: usize) -> Tile<'a> {
+        let (data, remaining) = self.data.split_at(tile_size);
+        self.data = remaining;
+
+        let tile = Tile {
+            row: self.row,
+            col: self.col,
+            data,
+            mi_row_start: get_tile_offset(self.row, self.mi_rows, self.tile_rows_log2),
+            mi_row_end: get_tile_offset(self.row + 1, self.mi_rows, self.tile_rows_log2),
+            mi_col_start: get_tile_offset(self.col, self.mi_cols, self.tile_cols_log2),
+            mi_col_end: get_tile_offset(self.col + 1, self.mi_cols, self.tile_cols_log2),
+        };
+
+        self.col += 1;
+        if self.col == self.tile_cols {
+            self.col = 0;
+            self.row += 1;
+        }
+
+        tile
+    }
+}
+
+impl<'a> Iterator for Tiles<'a> {
+    type Item = Result<Tile<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.row == self.tile_rows {
+            return None;
+        }
+
+        let tile = self.next_tile();
+        if tile.is_err() {
+            self.failed = true;
+        }
+        Some(tile)
+    }
+}
+
+/// Returns the first mode info column of the tile column that contains the given column.
+pub(crate) fn tile_mi_col_start(mi_col: u16, mi_cols: u16, tile_cols_log2: u8) -> u16 {
+    (0..1u16 << tile_cols_log2)
+        .filter_map(|tile_num| u8::try_from(tile_num).ok())
+        .map(|tile_num| get_tile_offset(tile_num, mi_cols, tile_cols_log2))
+        .filter(|offset| *offset <= mi_col)
+        .max()
+        .unwrap_or(0)
+}
+
+// Implements spec "get_tile_offset()".
+fn get_tile_offset(tile_num: u8, mis: u16, tile_size_log2: u8) -> u16 {
+    let sbs = (u32::from(mis) + 7) >> 3;
+    let offset = ((u32::from(tile_num) * sbs) >> tile_size_log2) << 3;
+    u16::try_from(offset).unwrap_or(mis).min(mis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_offsets() {
+        // 1920 pixel wide frame with 4 tile columns.
+        let offsets: Vec<u16> = (0..=4).map(|i| get_tile_offset(i, 240, 2)).collect();
+        assert_eq!(offsets, [0, 56, 120, 176, 240]);
+        assert_eq!(tile_mi_col_start(0, 240, 2), 0);
+        assert_eq!(tile_mi_col_start(119, 240, 2), 56);
+        assert_eq!(tile_mi_col_start(120, 240, 2), 120);
+        assert_eq!(tile_mi_col_start(239, 240, 2), 176);
+
+        // 180 pixel high frame with 2 tile rows.
+        let offsets: Vec<u16> = (0..=2).map(|i| get_tile_offset(i, 23, 1)).collect();
+        assert_eq!(offsets, [0, 8, 23]);
+    }
+
+    #[test]
+    fn split_tiles() -> Result<()> {
+        let data = [0, 0, 0, 2, 1, 2, 0, 0, 0, 1, 3, 0, 0, 0, 0, 4, 5];
+        let tiles: Vec<Tile> = Tiles::from_parts(&data, 0, 2, 23, 40).collect::<Result<_>>()?;
+
+        assert_eq!(tiles.len(), 4);
+        assert_eq!(tiles[0].data(), &[1, 2]);
+        assert_eq!(tiles[1].data(), &[3]);
+        assert!(tiles[2].data().is_empty());
+        assert_eq!(tiles[3].data(), &[4, 5]);
+        assert_eq!(tiles[3].col(), 3);
+        assert_eq!(tiles[3].mi_col_start(), 24);
+        assert_eq!(tiles[3].mi_col_end(), 40);
+        assert_eq!(tiles[3].mi_row_end(), 23);
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_tile_sizes() {
+        let mut tiles = Tiles::from_parts(&[0, 0, 0], 1, 0, 23, 40);
+        assert!(matches!(
+            tiles.next(),
+            Some(Err(Vp9ParserError::TileDataTooShort))
+        ));
+        assert!(tiles.next().is_none());
+
+        let mut tiles = Tiles::from_parts(&[0, 0, 0, 3, 1, 2], 1, 0, 23, 40);
+        assert!(matches!(
+            tiles.next(),
+            Some(Err(Vp9ParserError::InvalidTileSize(3)))
+        ));
+        assert!(tiles.next().is_none());
+    }
+
+    #[test]
+    fn damaged_tiles() {
+        let tiles =
+            Tiles::from_parts(&[0, 0, 0, 1, 1, 0, 0, 0, 9, 2, 3], 0, 2, 23, 40).collect_damaged();
+        assert_eq!(tiles.len(), 4);
+        assert_eq!(tiles[0].data(), &[1]);
+        assert_eq!(tiles[1].data(), &[2, 3]);
+        assert!(tiles[2].data().is_empty());
+        assert!(tiles[3].data().is_empty());
+        assert_eq!(tiles[3].mi_col_start(), 24);
+    }
+}
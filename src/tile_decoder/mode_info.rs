@@ -0,0 +1,1272 @@
+//! Decoding of the mode info of the blocks of intra and inter frames.
+
+use super::{largest_tx_size, TileDecoder};
+use crate::{
+    default_probs::{KF_UV_MODE_PROBS, KF_Y_MODE_PROBS},
+    mode_info::{BlockSize, ModeInfo, MotionVector, PredictionMode, TxSize},
+    BoolDecoder, CompressedHeader, InterpolationFilter, ReferenceMode, Result, TxMode,
+    Vp9ParserError, ALTREF_FRAME, GOLDEN_FRAME, INTRA_FRAME, LAST_FRAME, SEG_LVL_REF_FRAME,
+    SEG_LVL_SKIP,
+};
+
+/// Tree of the intra prediction modes.
+pub(crate) const INTRA_MODE_TREE: [i8; 18] = [
+    0, 2, -9, 4, -1, 6, 8, 12, -2, 10, -4, -5, -3, 14, -8, 16, -6, -7,
+];
+
+/// Tree of the segment ids.
+const SEGMENT_TREE: [i8; 14] = [2, 4, 6, 8, 10, 12, 0, -1, -2, -3, -4, -5, -6, -7];
+
+/// Tree of the inter prediction modes, relative to NEARESTMV.
+pub(crate) const INTER_MODE_TREE: [i8; 6] = [-2, 2, 0, 4, -1, -3];
+
+/// Tree of the switchable interpolation filters (EIGHTTAP, EIGHTTAP_SMOOTH, EIGHTTAP_SHARP).
+pub(crate) const INTERP_FILTER_TREE: [i8; 4] = [0, 2, -1, -2];
+
+/// Tree of the motion vector joints.
+pub(crate) const MV_JOINT_TREE: [i8; 6] = [0, 2, -1, 4, -2, -3];
+
+/// Tree of the motion vector classes.
+pub(crate) const MV_CLASS_TREE: [i8; 20] = [
+    0, 2, -1, 4, 6, 8, -2, -3, 10, 12, -4, -5, -6, 14, 16, 18, -7, -8, -9, -10,
+];
+
+/// Tree of the fractional parts of the motion vectors.
+pub(crate) const MV_FR_TREE: [i8; 6] = [0, 2, -1, 4, -2, -3];
+
+/// Size group of every block size, which selects the intra mode probabilities of inter frames.
+const SIZE_GROUP_LOOKUP: [u8; 13] = [0, 0, 0, 1, 1, 1, 2, 2, 2, 3, 3, 3, 3];
+
+/// Positions (row, column) of the neighbours that are searched for motion vector candidates.
+const MV_REF_BLOCKS: [[(i8, i8); 8]; 13] = [
+    [
+        (-1, 0),
+        (0, -1),
+        (-1, -1),
+        (-2, 0),
+        (0, -2),
+        (-2, -1),
+        (-1, -2),
+        (-2, -2),
+    ],
+    [
+        (-1, 0),
+        (0, -1),
+        (-1, -1),
+        (-2, 0),
+        (0, -2),
+        (-2, -1),
+        (-1, -2),
+        (-2, -2),
+    ],
+    [
+        (-1, 0),
+        (0, -1),
+        (-1, -1),
+        (-2, 0),
+        (0, -2),
+        (-2, -1),
+        (-1, -2),
+        (-2, -2),
+    ],
+    [
+        (-1, 0),
+        (0, -1),
+        (-1, -1),
+        (-2, 0),
+        (0, -2),
+        (-2, -1),
+        (-1, -2),
+        (-2, -2),
+    ],
+    [
+        (0, -1),
+        (-1, 0),
+        (1, -1),
+        (-1, -1),
+        (0, -2),
+        (-2, 0),
+        (-2, -1),
+        (-1, -2),
+    ],
+    [
+        (-1, 0),
+        (0, -1),
+        (-1, 1),
+        (-1, -1),
+        (-2, 0),
+        (0, -2),
+        (-1, -2),
+        (-2, -1),
+    ],
+    [
+        (-1, 0),
+        (0, -1),
+        (-1, 1),
+        (1, -1),
+        (-1, -1),
+        (-3, 0),
+        (0, -3),
+        (-3, -3),
+    ],
+    [
+        (0, -1),
+        (-1, 0),
+        (2, -1),
+        (-1, -1),
+        (-1, 1),
+        (0, -3),
+        (-3, 0),
+        (-3, -3),
+    ],
+    [
+        (-1, 0),
+        (0, -1),
+        (-1, 2),
+        (-1, -1),
+        (1, -1),
+        (-3, 0),
+        (0, -3),
+        (-3, -3),
+    ],
+    [
+        (-1, 1),
+        (1, -1),
+        (-1, 2),
+        (2, -1),
+        (-1, -1),
+        (-3, 0),
+        (0, -3),
+        (-3, -3),
+    ],
+    [
+        (0, -1),
+        (-1, 0),
+        (4, -1),
+        (-1, 2),
+        (-1, -1),
+        (0, -3),
+        (-3, 0),
+        (2, -1),
+    ],
+    [
+        (-1, 0),
+        (0, -1),
+        (-1, 4),
+        (2, -1),
+        (-1, -1),
+        (-3, 0),
+        (0, -3),
+        (-1, 2),
+    ],
+    [
+        (-1, 3),
+        (3, -1),
+        (-1, 4),
+        (4, -1),
+        (-1, -1),
+        (-1, 0),
+        (0, -1),
+        (-1, 6),
+    ],
+];
+
+/// Contribution of the prediction mode of a neighbour to the mode context.
+const MODE_2_COUNTER: [u8; 14] = [9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 0, 0, 3, 1];
+
+/// Mode context for the sum of the contributions of the two nearest neighbours.
+const COUNTER_TO_CONTEXT: [u8; 19] = [2, 3, 4, 1, 3, 9, 0, 9, 9, 5, 5, 9, 5, 9, 9, 9, 9, 9, 6];
+
+/// The sub-block of a neighbour below 8x8 whose motion vector is a candidate of the given
+/// sub-block. Indexed by the sub-block and whether the neighbour is to the left or above.
+const IDX_N_COLUMN_TO_SUBBLOCK: [[usize; 2]; 4] = [[1, 2], [1, 3], [3, 2], [3, 3]];
+
+/// Margin in 1/8 pixel by which the motion vector candidates may point outside of the frame.
+const MV_BORDER: i32 = 16 << 3;
+
+/// Margin in 1/8 pixel by which the predicted motion vectors may point outside of the frame.
+const MV_PRED_BORDER: i32 = (160 - 4) << 3;
+
+/// Motion vectors need to be inside of the exclusive range `-MV_LIMIT..MV_LIMIT`.
+const MV_LIMIT: i32 = 1 << 14;
+
+/// Motion vector magnitude from which the high precision bit is not used.
+const COMPANDED_MVREF_THRESH: i32 = 8;
+
+/// Largest transform size of every block size.
+const MAX_TX_SIZE_LOOKUP: [TxSize; 13] = [
+    TxSize::Tx4x4,
+    TxSize::Tx4x4,
+    TxSize::Tx4x4,
+    TxSize::Tx8x8,
+    TxSize::Tx8x8,
+    TxSize::Tx8x8,
+    TxSize::Tx16x16,
+    TxSize::Tx16x16,
+    TxSize::Tx16x16,
+    TxSize::Tx32x32,
+    TxSize::Tx32x32,
+    TxSize::Tx32x32,
+    TxSize::Tx32x32,
+];
+
+impl TileDecoder<'_> {
+    // Implements spec "intra_frame_mode_info()".
+    pub(super) fn intra_frame_mode_info(&mut self, bd: &mut BoolDecoder, mode_info: &mut ModeInfo) {
+        let above = self.above_mode_info(mode_info);
+        let left = self.left_mode_info(mode_info);
+
+        mode_info.segment_id = self.intra_segment_id(bd);
+        mode_info.skip = self.read_skip(bd, mode_info.segment_id, above.as_ref(), left.as_ref());
+        mode_info.tx_size = self.read_tx_size(bd, mode_info, true, above.as_ref(), left.as_ref());
+
+        let mut sub_modes = [PredictionMode::DcPred; 4];
+        if mode_info.block_size >= BlockSize::Block8x8 {
+            let above_mode = above_block_mode(above.as_ref(), &sub_modes, 0);
+            let left_mode = left_block_mode(left.as_ref(), &sub_modes, 0);
+            let mode = read_intra_mode(bd, &kf_y_mode_probs(above_mode, left_mode));
+            sub_modes = [mode; 4];
+        } else {
+            for block in sub_blocks(mode_info.block_size) {
+                let above_mode = above_block_mode(above.as_ref(), &sub_modes, block);
+                let left_mode = left_block_mode(left.as_ref(), &sub_modes, block);
+                let mode = read_intra_mode(bd, &kf_y_mode_probs(above_mode, left_mode));
+                fill_sub_blocks(&mut sub_modes, mode_info.block_size, block, mode);
+            }
+        }
+        mode_info.sub_modes = sub_modes;
+        mode_info.y_mode = sub_modes[3];
+
+        let uv_probs = &KF_UV_MODE_PROBS[usize::from(u8::from(mode_info.y_mode))];
+        mode_info.uv_mode = read_intra_mode(bd, uv_probs);
+    }
+
+    // Implements spec "intra_segment_id()".
+    fn intra_segment_id(&self, bd: &mut BoolDecoder) -> u8 {
+        if self.frame.segmentation_enabled() && self.frame.segmentation_update_map() {
+            bd.read_tree(&SEGMENT_TREE, self.frame.segment_tree_probs())
+        } else {
+            0
+        }
+    }
+
+    // Implements spec "inter_frame_mode_info()".
+    pub(super) fn inter_frame_mode_info(
+        &mut self,
+        bd: &mut BoolDecoder,
+        mode_info: &mut ModeInfo,
+    ) -> Result<()> {
+        let above = self.above_mode_info(mode_info);
+        let left = self.left_mode_info(mode_info);
+
+        mode_info.segment_id = self.inter_segment_id(bd, mode_info);
+        mode_info.skip = self.read_skip(bd, mode_info.segment_id, above.as_ref(), left.as_ref());
+        mode_info.is_inter =
+            self.read_is_inter(bd, mode_info.segment_id, above.as_ref(), left.as_ref());
+        let allow_select = !mode_info.is_inter || !mode_info.skip;
+        mode_info.tx_size =
+            self.read_tx_size(bd, mode_info, allow_select, above.as_ref(), left.as_ref());
+
+        if mode_info.is_inter {
+            self.inter_block_mode_info(bd, mode_info, above.as_ref(), left.as_ref())
+        } else {
+            self.intra_block_mode_info(bd, mode_info);
+            Ok(())
+        }
+    }
+
+    // Implements spec "inter_segment_id()".
+    fn inter_segment_id(&mut self, bd: &mut BoolDecoder, mode_info: &ModeInfo) -> u8 {
+        if !self.frame.segmentation_enabled() {
+            return 0;
+        }
+
+        let predicted_segment_id = self
+            .prev_segment_map
+            .map_or(0, |segment_map| segment_map.predicted_segment_id(mode_info));
+        if !self.frame.segmentation_update_map() {
+            return predicted_segment_id;
+        }
+        if !self.frame.segmentation_temporal_update() {
+            return bd.read_tree(&SEGMENT_TREE, self.frame.segment_tree_probs());
+        }
+
+        let col = usize::from(mode_info.mi_col - self.mi_col_start);
+        let row = usize::from(mode_info.mi_row & 7);
+        let ctx = usize::from(self.above_seg_pred_context[col])
+            + usize::from(self.left_seg_pred_context[row]);
+        let seg_id_predicted = bd.read_bool(self.frame.segment_pred_probs()[ctx]);
+
+        let num_8x8_w = usize::from(mode_info.block_size.num_8x8_wide());
+        let num_8x8_h = usize::from(mode_info.block_size.num_8x8_high());
+        self.above_seg_pred_context[col..col + num_8x8_w].fill(seg_id_predicted);
+        self.left_seg_pred_context[row..row + num_8x8_h].fill(seg_id_predicted);
+
+        if seg_id_predicted {
+            predicted_segment_id
+        } else {
+            bd.read_tree(&SEGMENT_TREE, self.frame.segment_tree_probs())
+        }
+    }
+
+    // Implements spec "read_is_inter()".
+    fn read_is_inter(
+        &mut self,
+        bd: &mut BoolDecoder,
+        segment_id: u8,
+        above: Option<&ModeInfo>,
+        left: Option<&ModeInfo>,
+    ) -> bool {
+        if let Some(ref_frame) = self.segment_ref_frame(segment_id) {
+            return ref_frame != INTRA_FRAME;
+        }
+
+        let ctx = match (above, left) {
+            (Some(above), Some(left)) if !above.is_inter && !left.is_inter => 3,
+            (Some(above), Some(left)) => usize::from(!above.is_inter || !left.is_inter),
+            (Some(edge), None) | (None, Some(edge)) => 2 * usize::from(!edge.is_inter),
+            (None, None) => 0,
+        };
+        let is_inter = bd.read_bool(self.frame_context.is_inter_prob()[ctx]);
+        self.counts.is_inter[ctx][usize::from(is_inter)] += 1;
+        is_inter
+    }
+
+    /// Returns the reference frame of the segment if the segment feature is active.
+    fn segment_ref_frame(&self, segment_id: u8) -> Option<usize> {
+        let segment = usize::from(segment_id);
+        if self.frame.segmentation_enabled()
+            && self.frame.segment_feature_enabled()[segment][SEG_LVL_REF_FRAME]
+        {
+            let ref_frame = self.frame.segment_feature_data()[segment][SEG_LVL_REF_FRAME];
+            Some(usize::try_from(ref_frame).unwrap_or(INTRA_FRAME))
+        } else {
+            None
+        }
+    }
+
+    // Implements spec "intra_block_mode_info()".
+    fn intra_block_mode_info(&mut self, bd: &mut BoolDecoder, mode_info: &mut ModeInfo) {
+        let y_mode_probs = self.frame_context.y_mode_probs();
+        if mode_info.block_size >= BlockSize::Block8x8 {
+            let size_group =
+                usize::from(SIZE_GROUP_LOOKUP[usize::from(u8::from(mode_info.block_size))]);
+            let mode = read_intra_mode(bd, &y_mode_probs[size_group]);
+            self.counts.y_mode[size_group][usize::from(u8::from(mode))] += 1;
+            mode_info.sub_modes = [mode; 4];
+        } else {
+            for block in sub_blocks(mode_info.block_size) {
+                let mode = read_intra_mode(bd, &y_mode_probs[0]);
+                self.counts.y_mode[0][usize::from(u8::from(mode))] += 1;
+                fill_sub_blocks(&mut mode_info.sub_modes, mode_info.block_size, block, mode);
+            }
+        }
+        mode_info.y_mode = mode_info.sub_modes[3];
+
+        let y_mode = usize::from(u8::from(mode_info.y_mode));
+        mode_info.uv_mode = read_intra_mode(bd, &self.frame_context.uv_mode_probs()[y_mode]);
+        self.counts.uv_mode[y_mode][usize::from(u8::from(mode_info.uv_mode))] += 1;
+    }
+
+    // Implements spec "inter_block_mode_info()".
+    fn inter_block_mode_info(
+        &mut self,
+        bd: &mut BoolDecoder,
+        mode_info: &mut ModeInfo,
+        above: Option<&ModeInfo>,
+        left: Option<&ModeInfo>,
+    ) -> Result<()> {
+        mode_info.ref_frame = self.read_ref_frames(bd, mode_info.segment_id, above, left);
+        let is_compound = mode_info.ref_frame[1] > INTRA_FRAME;
+
+        let mut nearest_mv = [MotionVector::default(); 2];
+        let mut near_mv = [MotionVector::default(); 2];
+        let mut mode_context = 0;
+        for ref_list in 0..1 + usize::from(is_compound) {
+            let (mv_list, context) =
+                self.find_mv_refs(mode_info, mode_info.ref_frame[ref_list], None);
+            let [nearest, near] = self.find_best_ref_mvs(mode_info, mv_list);
+            nearest_mv[ref_list] = nearest;
+            near_mv[ref_list] = near;
+            mode_context = context;
+        }
+        // The best motion vectors are the base of newly coded motion vectors.
+        let best_mv = nearest_mv;
+        let inter_mode_probs = &self.frame_context.inter_mode_probs()[mode_context];
+
+        let segment = usize::from(mode_info.segment_id);
+        let mut y_mode = PredictionMode::ZeroMv;
+        if !(self.frame.segmentation_enabled()
+            && self.frame.segment_feature_enabled()[segment][SEG_LVL_SKIP])
+            && mode_info.block_size >= BlockSize::Block8x8
+        {
+            y_mode = read_inter_mode(bd, inter_mode_probs);
+            self.count_inter_mode(mode_context, y_mode);
+        }
+
+        mode_info.interp_filter = match self.frame.interpolation_filter() {
+            InterpolationFilter::Switchable => self.read_interp_filter(bd, above, left),
+            interpolation_filter => interpolation_filter,
+        };
+
+        if mode_info.block_size >= BlockSize::Block8x8 {
+            let mv = self.assign_mv(bd, y_mode, is_compound, &best_mv, &nearest_mv, &near_mv)?;
+            mode_info.sub_modes = [y_mode; 4];
+            mode_info.sub_mvs = [mv; 4];
+        } else {
+            for block in sub_blocks(mode_info.block_size) {
+                let b_mode = read_inter_mode(bd, inter_mode_probs);
+                self.count_inter_mode(mode_context, b_mode);
+                let mut nearest_sub8x8 = nearest_mv;
+                let mut near_sub8x8 = near_mv;
+                if b_mode == PredictionMode::NearestMv || b_mode == PredictionMode::NearMv {
+                    for ref_list in 0..1 + usize::from(is_compound) {
+                        let [nearest, near] = self.append_sub8x8_mvs(mode_info, block, ref_list);
+                        nearest_sub8x8[ref_list] = nearest;
+                        near_sub8x8[ref_list] = near;
+                    }
+                }
+                let mv = self.assign_mv(
+                    bd,
+                    b_mode,
+                    is_compound,
+                    &best_mv,
+                    &nearest_sub8x8,
+                    &near_sub8x8,
+                )?;
+                fill_sub_blocks(
+                    &mut mode_info.sub_modes,
+                    mode_info.block_size,
+                    block,
+                    b_mode,
+                );
+                fill_sub_blocks(&mut mode_info.sub_mvs, mode_info.block_size, block, mv);
+            }
+        }
+        mode_info.y_mode = mode_info.sub_modes[3];
+
+        Ok(())
+    }
+
+    // Implements spec "read_ref_frames()".
+    fn read_ref_frames(
+        &mut self,
+        bd: &mut BoolDecoder,
+        segment_id: u8,
+        above: Option<&ModeInfo>,
+        left: Option<&ModeInfo>,
+    ) -> [usize; 2] {
+        if let Some(ref_frame) = self.segment_ref_frame(segment_id) {
+            return [ref_frame, INTRA_FRAME];
+        }
+
+        let header = self.compressed_header;
+        let reference_mode = match header.reference_mode() {
+            ReferenceMode::ReferenceModeSelect => {
+                let ctx = comp_mode_context(header, above, left);
+                let comp_mode = bd.read_bool(self.frame_context.comp_mode_prob()[ctx]);
+                self.counts.comp_mode[ctx][usize::from(comp_mode)] += 1;
+                if comp_mode {
+                    ReferenceMode::CompoundReference
+                } else {
+                    ReferenceMode::SingleReference
+                }
+            }
+            reference_mode => reference_mode,
+        };
+
+        if reference_mode == ReferenceMode::CompoundReference {
+            let fixed_ref_index =
+                usize::from(self.frame.ref_frame_sign_bias()[header.comp_fixed_ref()]);
+            let ctx = comp_ref_context(header, self.frame.ref_frame_sign_bias(), above, left);
+            let comp_ref = bd.read_bool(self.frame_context.comp_ref_prob()[ctx]);
+            self.counts.comp_ref[ctx][usize::from(comp_ref)] += 1;
+
+            let mut ref_frame = [INTRA_FRAME; 2];
+            ref_frame[fixed_ref_index] = header.comp_fixed_ref();
+            ref_frame[1 - fixed_ref_index] = header.comp_var_ref()[usize::from(comp_ref)];
+            ref_frame
+        } else {
+            let probs = self.frame_context.single_ref_prob();
+            let ctx = single_ref_p1_context(above, left);
+            let single_ref_p1 = bd.read_bool(probs[ctx][0]);
+            self.counts.single_ref[ctx][0][usize::from(single_ref_p1)] += 1;
+            if !single_ref_p1 {
+                return [LAST_FRAME, INTRA_FRAME];
+            }
+
+            let ctx = single_ref_p2_context(above, left);
+            let single_ref_p2 = bd.read_bool(probs[ctx][1]);
+            self.counts.single_ref[ctx][1][usize::from(single_ref_p2)] += 1;
+            if single_ref_p2 {
+                [ALTREF_FRAME, INTRA_FRAME]
+            } else {
+                [GOLDEN_FRAME, INTRA_FRAME]
+            }
+        }
+    }
+
+    /// Reads the interpolation filter of a block in a frame with switchable filters.
+    fn read_interp_filter(
+        &mut self,
+        bd: &mut BoolDecoder,
+        above: Option<&ModeInfo>,
+        left: Option<&ModeInfo>,
+    ) -> InterpolationFilter {
+        // Neighbours that are not inter predicted count as the invalid filter type 3.
+        let filter_type = |mode_info: Option<&ModeInfo>| match mode_info {
+            Some(mode_info) if mode_info.is_inter => {
+                switchable_filter_type(mode_info.interp_filter)
+            }
+            _ => 3,
+        };
+        let left_type = filter_type(left);
+        let above_type = filter_type(above);
+        let ctx = if left_type == above_type || above_type == 3 {
+            left_type
+        } else if left_type == 3 {
+            above_type
+        } else {
+            3
+        };
+
+        let filter_type = bd.read_tree(
+            &INTERP_FILTER_TREE,
+            &self.frame_context.interp_filter_probs()[ctx],
+        );
+        self.counts.interp_filter[ctx][usize::from(filter_type)] += 1;
+        match filter_type {
+            0 => InterpolationFilter::Eighttap,
+            1 => InterpolationFilter::EighttapSmooth,
+            _ => InterpolationFilter::EighttapSharp,
+        }
+    }
+
+    fn count_inter_mode(&mut self, mode_context: usize, mode: PredictionMode) {
+        let offset = u8::from(mode) - u8::from(PredictionMode::NearestMv);
+        self.counts.inter_mode[mode_context][usize::from(offset)] += 1;
+    }
+
+    // Implements spec "assign_mv()".
+    fn assign_mv(
+        &mut self,
+        bd: &mut BoolDecoder,
+        mode: PredictionMode,
+        is_compound: bool,
+        best_mv: &[MotionVector; 2],
+        nearest_mv: &[MotionVector; 2],
+        near_mv: &[MotionVector; 2],
+    ) -> Result<[MotionVector; 2]> {
+        let mut mv = [MotionVector::default(); 2];
+        for ref_list in 0..1 + usize::from(is_compound) {
+            mv[ref_list] = match mode {
+                PredictionMode::NewMv => self.read_mv(bd, best_mv[ref_list])?,
+                PredictionMode::NearestMv => nearest_mv[ref_list],
+                PredictionMode::NearMv => near_mv[ref_list],
+                _ => MotionVector::default(),
+            };
+        }
+        Ok(mv)
+    }
+
+    // Implements spec "read_mv()".
+    fn read_mv(&mut self, bd: &mut BoolDecoder, best_mv: MotionVector) -> Result<MotionVector> {
+        let use_hp = self.frame.allow_high_precision_mv() && use_mv_hp(best_mv);
+        let joint = bd.read_tree(&MV_JOINT_TREE, self.frame_context.mv_joint_probs());
+        self.counts.mv_joint[usize::from(joint)] += 1;
+
+        let mut diff = [0; 2];
+        if joint == 2 || joint == 3 {
+            diff[0] = self.read_mv_component(bd, 0, use_hp);
+        }
+        if joint == 1 || joint == 3 {
+            diff[1] = self.read_mv_component(bd, 1, use_hp);
+        }
+
+        let row = i32::from(best_mv.row) + diff[0];
+        let col = i32::from(best_mv.col) + diff[1];
+        let valid = -MV_LIMIT + 1..MV_LIMIT;
+        if !valid.contains(&row) || !valid.contains(&col) {
+            return Err(Vp9ParserError::InvalidMotionVector);
+        }
+        Ok(MotionVector {
+            row: clamp_to_i16(row),
+            col: clamp_to_i16(col),
+        })
+    }
+
+    /// Reads the difference of a motion vector component (0 = row, 1 = column) to the best
+    /// motion vector.
+    ///
+    /// Like libvpx, the high precision bit is counted even if it is not read.
+    fn read_mv_component(&mut self, bd: &mut BoolDecoder, comp: usize, use_hp: bool) -> i32 {
+        let fc = self.frame_context;
+        let counts = &mut self.counts;
+        let sign = bd.read_bool(fc.mv_sign_prob()[comp]);
+        counts.mv_sign[comp][usize::from(sign)] += 1;
+        let mv_class = bd.read_tree(&MV_CLASS_TREE, &fc.mv_class_probs()[comp]);
+        counts.mv_class[comp][usize::from(mv_class)] += 1;
+
+        let (mag, d, fr, hp) = if mv_class == 0 {
+            let d = bd.read_bool(fc.mv_class0_bit_prob()[comp]);
+            let fr = bd.read_tree(&MV_FR_TREE, &fc.mv_class0_fr_probs()[comp][usize::from(d)]);
+            let hp = !use_hp || bd.read_bool(fc.mv_class0_hp_prob()[comp]);
+            counts.mv_class0_bit[comp][usize::from(d)] += 1;
+            counts.mv_class0_fr[comp][usize::from(d)][usize::from(fr)] += 1;
+            counts.mv_class0_hp[comp][usize::from(hp)] += 1;
+            (0, i32::from(d), fr, hp)
+        } else {
+            let mut d = 0;
+            for (i, prob) in fc.mv_bits_prob()[comp]
+                .iter()
+                .enumerate()
+                .take(usize::from(mv_class))
+            {
+                let bit = bd.read_bool(*prob);
+                counts.mv_bits[comp][i][usize::from(bit)] += 1;
+                d |= i32::from(bit) << i;
+            }
+            let fr = bd.read_tree(&MV_FR_TREE, &fc.mv_fr_probs()[comp]);
+            let hp = !use_hp || bd.read_bool(fc.mv_hp_prob()[comp]);
+            counts.mv_fr[comp][usize::from(fr)] += 1;
+            counts.mv_hp[comp][usize::from(hp)] += 1;
+            (2 << (mv_class + 2), d, fr, hp)
+        };
+
+        let mag = mag + ((d << 3) | (i32::from(fr) << 1) | i32::from(hp)) + 1;
+        if sign {
+            -mag
+        } else {
+            mag
+        }
+    }
+
+    // Implements spec "find_mv_refs()". Returns the candidate list and the mode context.
+    //
+    // For blocks below 8x8 the sub-block can be given, which then uses the motion vectors
+    // of the closest sub-blocks of the two nearest neighbours.
+    fn find_mv_refs(
+        &self,
+        mode_info: &ModeInfo,
+        ref_frame: usize,
+        block: Option<usize>,
+    ) -> ([MotionVector; 2], usize) {
+        let search = &MV_REF_BLOCKS[usize::from(u8::from(mode_info.block_size))];
+        let candidates = search.map(|position| self.mv_ref_candidate(mode_info, position));
+        let context_counter: u8 = candidates[..2]
+            .iter()
+            .flatten()
+            .map(|candidate| MODE_2_COUNTER[usize::from(u8::from(candidate.y_mode))])
+            .sum();
+
+        let mut list = MvList::default();
+        let _ = self.add_mv_ref_candidates(&mut list, mode_info, &candidates, ref_frame, block);
+
+        let edges = self.frame_edges(mode_info);
+        let mvs = list.mvs.map(|mv| clamp_mv(mv, &edges, MV_BORDER));
+        let mode_context = COUNTER_TO_CONTEXT[usize::from(context_counter)];
+        (mvs, usize::from(mode_context))
+    }
+
+    /// Adds the motion vectors of the neighbours and of the co-located block of the previous
+    /// frame to the candidate list. Returns `true` once the list is full.
+    fn add_mv_ref_candidates(
+        &self,
+        list: &mut MvList,
+        mode_info: &ModeInfo,
+        candidates: &[Option<ModeInfo>; 8],
+        ref_frame: usize,
+        block: Option<usize>,
+    ) -> bool {
+        let search = &MV_REF_BLOCKS[usize::from(u8::from(mode_info.block_size))];
+        let prev_frame = self
+            .prev_mode_info
+            .and_then(|grid| grid.get(mode_info.mi_row, mode_info.mi_col));
+
+        // The nearest two neighbours use the motion vector of their closest sub-block.
+        for (i, candidate) in candidates.iter().enumerate() {
+            if let Some(candidate) = candidate {
+                let sub_block = match block {
+                    Some(block) if i < 2 && candidate.block_size < BlockSize::Block8x8 => {
+                        IDX_N_COLUMN_TO_SUBBLOCK[block][usize::from(search[i].1 == 0)]
+                    }
+                    _ => 3,
+                };
+                if add_same_ref_mv(list, candidate, sub_block, ref_frame) {
+                    return true;
+                }
+            }
+        }
+        if let Some(prev_frame) = prev_frame {
+            if add_same_ref_mv(list, prev_frame, 3, ref_frame) {
+                return true;
+            }
+        }
+
+        // Motion vectors of other reference frames are used if nothing better was found.
+        for candidate in candidates.iter().flatten() {
+            if self.add_different_ref_mvs(list, candidate, ref_frame) {
+                return true;
+            }
+        }
+        if let Some(prev_frame) = prev_frame {
+            if self.add_different_ref_mvs(list, prev_frame, ref_frame) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Adds the motion vectors of a block that use a different reference frame. The motion
+    /// vectors are inverted if the sign bias of the reference frames differs. Returns `true`
+    /// once the list is full.
+    fn add_different_ref_mvs(
+        &self,
+        list: &mut MvList,
+        candidate: &ModeInfo,
+        ref_frame: usize,
+    ) -> bool {
+        if candidate.ref_frame[0] == INTRA_FRAME {
+            return false;
+        }
+
+        let sign_bias = self.frame.ref_frame_sign_bias();
+        let scale = |which: usize| {
+            let mv = candidate.sub_mvs[3][which];
+            if sign_bias[candidate.ref_frame[which]] != sign_bias[ref_frame] {
+                MotionVector {
+                    row: mv.row.saturating_neg(),
+                    col: mv.col.saturating_neg(),
+                }
+            } else {
+                mv
+            }
+        };
+
+        let mvs = &candidate.sub_mvs[3];
+        if candidate.ref_frame[0] != ref_frame && list.add(scale(0)) {
+            return true;
+        }
+        candidate.ref_frame[1] > INTRA_FRAME
+            && candidate.ref_frame[1] != ref_frame
+            && mvs[1] != mvs[0]
+            && list.add(scale(1))
+    }
+
+    /// Returns the mode info at the given offset to the block if it is inside the tile
+    /// column and decoded.
+    fn mv_ref_candidate(&self, mode_info: &ModeInfo, (row, col): (i8, i8)) -> Option<ModeInfo> {
+        let mi_row = u16::try_from(i32::from(mode_info.mi_row) + i32::from(row)).ok()?;
+        let mi_col = u16::try_from(i32::from(mode_info.mi_col) + i32::from(col)).ok()?;
+        if mi_row >= self.frame.mi_rows() || mi_col < self.mi_col_start || mi_col >= self.mi_col_end
+        {
+            return None;
+        }
+        Some(*self.mode_info_at(mi_row, mi_col))
+    }
+
+    // Implements spec "find_best_ref_mvs()". Returns the nearest and the near motion vector.
+    fn find_best_ref_mvs(&self, mode_info: &ModeInfo, mvs: [MotionVector; 2]) -> [MotionVector; 2] {
+        let edges = self.frame_edges(mode_info);
+        mvs.map(|mut mv| {
+            if !self.frame.allow_high_precision_mv() || !use_mv_hp(mv) {
+                mv.row = lower_precision(mv.row);
+                mv.col = lower_precision(mv.col);
+            }
+            clamp_mv(mv, &edges, MV_PRED_BORDER)
+        })
+    }
+
+    // Implements spec "append_sub8x8_mvs()". Returns the nearest and the near motion vector
+    // of a sub-block.
+    fn append_sub8x8_mvs(
+        &self,
+        mode_info: &ModeInfo,
+        block: usize,
+        ref_list: usize,
+    ) -> [MotionVector; 2] {
+        let (mv_list, _) = self.find_mv_refs(mode_info, mode_info.ref_frame[ref_list], Some(block));
+        let sub_mvs = &mode_info.sub_mvs;
+        let (nearest, candidates) = match block {
+            0 => return mv_list,
+            1 | 2 => (sub_mvs[0][ref_list], vec![mv_list[0], mv_list[1]]),
+            _ => (
+                sub_mvs[2][ref_list],
+                vec![
+                    sub_mvs[1][ref_list],
+                    sub_mvs[0][ref_list],
+                    mv_list[0],
+                    mv_list[1],
+                ],
+            ),
+        };
+        let near = candidates
+            .into_iter()
+            .find(|mv| *mv != nearest)
+            .unwrap_or_default();
+        [nearest, near]
+    }
+
+    /// Returns the distances of the block to the frame edges in 1/8 pixel
+    /// (left, right, top, bottom). Distances to the left and top are negative.
+    fn frame_edges(&self, mode_info: &ModeInfo) -> [i32; 4] {
+        let mi_col = i32::from(mode_info.mi_col);
+        let mi_row = i32::from(mode_info.mi_row);
+        let num_8x8_w = i32::from(mode_info.block_size.num_8x8_wide());
+        let num_8x8_h = i32::from(mode_info.block_size.num_8x8_high());
+        [
+            -(mi_col * 64),
+            (i32::from(self.frame.mi_cols()) - num_8x8_w - mi_col) * 64,
+            -(mi_row * 64),
+            (i32::from(self.frame.mi_rows()) - num_8x8_h - mi_row) * 64,
+        ]
+    }
+
+    // Implements spec "read_skip()".
+    fn read_skip(
+        &mut self,
+        bd: &mut BoolDecoder,
+        segment_id: u8,
+        above: Option<&ModeInfo>,
+        left: Option<&ModeInfo>,
+    ) -> bool {
+        if self.frame.segmentation_enabled()
+            && self.frame.segment_feature_enabled()[usize::from(segment_id)][SEG_LVL_SKIP]
+        {
+            return true;
+        }
+
+        let ctx = usize::from(above.is_some_and(|above| above.skip))
+            + usize::from(left.is_some_and(|left| left.skip));
+        let skip = bd.read_bool(self.frame_context.skip_prob()[ctx]);
+        self.counts.skip[ctx][usize::from(skip)] += 1;
+        skip
+    }
+
+    // Implements spec "read_tx_size()".
+    fn read_tx_size(
+        &mut self,
+        bd: &mut BoolDecoder,
+        mode_info: &ModeInfo,
+        allow_select: bool,
+        above: Option<&ModeInfo>,
+        left: Option<&ModeInfo>,
+    ) -> TxSize {
+        let max_tx_size = MAX_TX_SIZE_LOOKUP[usize::from(u8::from(mode_info.block_size))];
+        let tx_mode = self.compressed_header.tx_mode();
+        if !(allow_select
+            && tx_mode == TxMode::TxModeSelect
+            && mode_info.block_size >= BlockSize::Block8x8)
+        {
+            return max_tx_size.min(largest_tx_size(tx_mode));
+        }
+
+        let mut above_ctx = match above {
+            Some(above) if !above.skip => above.tx_size,
+            _ => max_tx_size,
+        };
+        let mut left_ctx = match left {
+            Some(left) if !left.skip => left.tx_size,
+            _ => max_tx_size,
+        };
+        if left.is_none() {
+            left_ctx = above_ctx;
+        }
+        if above.is_none() {
+            above_ctx = left_ctx;
+        }
+        let ctx = usize::from(u8::from(above_ctx) + u8::from(left_ctx) > u8::from(max_tx_size));
+
+        let probs: &[u8] = match max_tx_size {
+            TxSize::Tx32x32 => &self.frame_context.tx_probs_32x32()[ctx],
+            TxSize::Tx16x16 => &self.frame_context.tx_probs_16x16()[ctx],
+            _ => &self.frame_context.tx_probs_8x8()[ctx],
+        };
+        let mut tx_size = u8::from(bd.read_bool(probs[0]));
+        if tx_size != 0 && max_tx_size >= TxSize::Tx16x16 {
+            tx_size += u8::from(bd.read_bool(probs[1]));
+            if tx_size != 1 && max_tx_size >= TxSize::Tx32x32 {
+                tx_size += u8::from(bd.read_bool(probs[2]));
+            }
+        }
+        let counts: &mut [u32] = match max_tx_size {
+            TxSize::Tx32x32 => &mut self.counts.tx_32x32[ctx],
+            TxSize::Tx16x16 => &mut self.counts.tx_16x16[ctx],
+            _ => &mut self.counts.tx_8x8[ctx],
+        };
+        counts[usize::from(tx_size)] += 1;
+        TxSize::from(tx_size)
+    }
+}
+
+fn read_intra_mode(bd: &mut BoolDecoder, probs: &[u8]) -> PredictionMode {
+    PredictionMode::from(bd.read_tree(&INTRA_MODE_TREE, probs))
+}
+
+fn kf_y_mode_probs(above: PredictionMode, left: PredictionMode) -> [u8; 9] {
+    KF_Y_MODE_PROBS[usize::from(u8::from(above))][usize::from(u8::from(left))]
+}
+
+/// Returns the mode of the 4x4 block above the given sub-block.
+fn above_block_mode(
+    above: Option<&ModeInfo>,
+    sub_modes: &[PredictionMode; 4],
+    block: usize,
+) -> PredictionMode {
+    if block >= 2 {
+        return sub_modes[block - 2];
+    }
+    above.map_or(PredictionMode::DcPred, |above| above.sub_modes[block + 2])
+}
+
+/// Returns the mode of the 4x4 block left of the given sub-block.
+fn left_block_mode(
+    left: Option<&ModeInfo>,
+    sub_modes: &[PredictionMode; 4],
+    block: usize,
+) -> PredictionMode {
+    if block & 1 == 1 {
+        return sub_modes[block - 1];
+    }
+    left.map_or(PredictionMode::DcPred, |left| left.sub_modes[block + 1])
+}
+
+/// Returns the first 4x4 sub-block of every prediction block inside a block below 8x8.
+fn sub_blocks(block_size: BlockSize) -> impl Iterator<Item = usize> {
+    let num_4x4_w = 1 << block_size.width_log2();
+    let num_4x4_h = 1 << block_size.height_log2();
+    (0..2)
+        .step_by(num_4x4_h)
+        .flat_map(move |idy| (0..2).step_by(num_4x4_w).map(move |idx| idy * 2 + idx))
+}
+
+/// Assigns the value of a prediction block below 8x8 to all 4x4 sub-blocks it covers.
+fn fill_sub_blocks<T: Copy>(values: &mut [T; 4], block_size: BlockSize, block: usize, value: T) {
+    for y in 0..1 << block_size.height_log2() {
+        for x in 0..1 << block_size.width_log2() {
+            values[block + y * 2 + x] = value;
+        }
+    }
+}
+
+fn read_inter_mode(bd: &mut BoolDecoder, probs: &[u8]) -> PredictionMode {
+    let mode = bd.read_tree(&INTER_MODE_TREE, probs);
+    PredictionMode::from(u8::from(PredictionMode::NearestMv) + mode)
+}
+
+/// Returns the type of a switchable interpolation filter as used by the filter context.
+fn switchable_filter_type(filter: InterpolationFilter) -> usize {
+    match filter {
+        InterpolationFilter::Eighttap => 0,
+        InterpolationFilter::EighttapSmooth => 1,
+        InterpolationFilter::EighttapSharp => 2,
+        _ => 3,
+    }
+}
+
+fn has_second_ref(mode_info: &ModeInfo) -> bool {
+    mode_info.ref_frame[1] > INTRA_FRAME
+}
+
+/// Returns the context of the compound mode flag.
+fn comp_mode_context(
+    header: &CompressedHeader,
+    above: Option<&ModeInfo>,
+    left: Option<&ModeInfo>,
+) -> usize {
+    let fixed_ref = header.comp_fixed_ref();
+    match (above, left) {
+        (Some(above), Some(left)) => {
+            if !has_second_ref(above) && !has_second_ref(left) {
+                usize::from((above.ref_frame[0] == fixed_ref) ^ (left.ref_frame[0] == fixed_ref))
+            } else if !has_second_ref(above) {
+                2 + usize::from(above.ref_frame[0] == fixed_ref || !above.is_inter)
+            } else if !has_second_ref(left) {
+                2 + usize::from(left.ref_frame[0] == fixed_ref || !left.is_inter)
+            } else {
+                4
+            }
+        }
+        (Some(edge), None) | (None, Some(edge)) => {
+            if !has_second_ref(edge) {
+                usize::from(edge.ref_frame[0] == fixed_ref)
+            } else {
+                3
+            }
+        }
+        (None, None) => 1,
+    }
+}
+
+/// Returns the context of the variable reference frame of compound prediction.
+fn comp_ref_context(
+    header: &CompressedHeader,
+    sign_bias: &[bool; 4],
+    above: Option<&ModeInfo>,
+    left: Option<&ModeInfo>,
+) -> usize {
+    let fixed_ref = header.comp_fixed_ref();
+    let var_ref = header.comp_var_ref();
+    let var_ref_index = 1 - usize::from(sign_bias[fixed_ref]);
+    // The reference frame of a block that can take the place of the variable reference frame.
+    let var_ref_frame = |mode_info: &ModeInfo| {
+        if has_second_ref(mode_info) {
+            mode_info.ref_frame[var_ref_index]
+        } else {
+            mode_info.ref_frame[0]
+        }
+    };
+
+    match (above, left) {
+        (Some(above), Some(left)) => match (above.is_inter, left.is_inter) {
+            (false, false) => 2,
+            (true, false) => 1 + 2 * usize::from(var_ref_frame(above) != var_ref[1]),
+            (false, true) => 1 + 2 * usize::from(var_ref_frame(left) != var_ref[1]),
+            (true, true) => {
+                let above_single = !has_second_ref(above);
+                let left_single = !has_second_ref(left);
+                let var_ref_above = var_ref_frame(above);
+                let var_ref_left = var_ref_frame(left);
+                if var_ref_above == var_ref_left && var_ref[1] == var_ref_above {
+                    0
+                } else if above_single && left_single {
+                    if (var_ref_above == fixed_ref && var_ref_left == var_ref[0])
+                        || (var_ref_left == fixed_ref && var_ref_above == var_ref[0])
+                    {
+                        4
+                    } else if var_ref_above == var_ref_left {
+                        3
+                    } else {
+                        1
+                    }
+                } else if above_single || left_single {
+                    let var_ref_compound = if left_single {
+                        var_ref_above
+                    } else {
+                        var_ref_left
+                    };
+                    let ref_single = if above_single {
+                        var_ref_above
+                    } else {
+                        var_ref_left
+                    };
+                    if var_ref_compound == var_ref[1] && ref_single != var_ref[1] {
+                        1
+                    } else if ref_single == var_ref[1] && var_ref_compound != var_ref[1] {
+                        2
+                    } else {
+                        4
+                    }
+                } else if var_ref_above == var_ref_left {
+                    4
+                } else {
+                    2
+                }
+            }
+        },
+        (Some(edge), None) | (None, Some(edge)) => {
+            if !edge.is_inter {
+                2
+            } else if has_second_ref(edge) {
+                4 * usize::from(edge.ref_frame[var_ref_index] != var_ref[1])
+            } else {
+                3 * usize::from(edge.ref_frame[0] != var_ref[1])
+            }
+        }
+        (None, None) => 2,
+    }
+}
+
+/// Returns the context of the flag that distinguishes LAST_FRAME from the other references.
+fn single_ref_p1_context(above: Option<&ModeInfo>, left: Option<&ModeInfo>) -> usize {
+    let uses_last = |mode_info: &ModeInfo| {
+        mode_info.ref_frame[0] == LAST_FRAME || mode_info.ref_frame[1] == LAST_FRAME
+    };
+    // Context of a single inter predicted neighbour.
+    let edge_context = |edge: &ModeInfo| {
+        if !has_second_ref(edge) {
+            4 * usize::from(edge.ref_frame[0] == LAST_FRAME)
+        } else {
+            1 + usize::from(uses_last(edge))
+        }
+    };
+
+    match (above, left) {
+        (Some(above), Some(left)) => match (above.is_inter, left.is_inter) {
+            (false, false) => 2,
+            (true, false) => edge_context(above),
+            (false, true) => edge_context(left),
+            (true, true) => match (has_second_ref(above), has_second_ref(left)) {
+                (true, true) => 1 + usize::from(uses_last(above) || uses_last(left)),
+                (false, false) => {
+                    2 * usize::from(above.ref_frame[0] == LAST_FRAME)
+                        + 2 * usize::from(left.ref_frame[0] == LAST_FRAME)
+                }
+                (above_compound, _) => {
+                    let (single, compound) = if above_compound {
+                        (left, above)
+                    } else {
+                        (above, left)
+                    };
+                    if single.ref_frame[0] == LAST_FRAME {
+                        3 + usize::from(uses_last(compound))
+                    } else {
+                        usize::from(uses_last(compound))
+                    }
+                }
+            },
+        },
+        (Some(edge), None) | (None, Some(edge)) => {
+            if edge.is_inter {
+                edge_context(edge)
+            } else {
+                2
+            }
+        }
+        (None, None) => 2,
+    }
+}
+
+/// Returns the context of the flag that distinguishes GOLDEN_FRAME from ALTREF_FRAME.
+fn single_ref_p2_context(above: Option<&ModeInfo>, left: Option<&ModeInfo>) -> usize {
+    let uses_golden = |mode_info: &ModeInfo| {
+        mode_info.ref_frame[0] == GOLDEN_FRAME || mode_info.ref_frame[1] == GOLDEN_FRAME
+    };
+
+    match (above, left) {
+        (Some(above), Some(left)) => match (above.is_inter, left.is_inter) {
+            (false, false) => 2,
+            (true, false) | (false, true) => {
+                let edge = if above.is_inter { above } else { left };
+                if has_second_ref(edge) {
+                    1 + 2 * usize::from(uses_golden(edge))
+                } else if edge.ref_frame[0] == LAST_FRAME {
+                    3
+                } else {
+                    4 * usize::from(edge.ref_frame[0] == GOLDEN_FRAME)
+                }
+            }
+            (true, true) => match (has_second_ref(above), has_second_ref(left)) {
+                (true, true) => {
+                    if above.ref_frame == left.ref_frame {
+                        3 * usize::from(uses_golden(above) || uses_golden(left))
+                    } else {
+                        2
+                    }
+                }
+                (false, false) => {
+                    let above_ref = above.ref_frame[0];
+                    let left_ref = left.ref_frame[0];
+                    if above_ref == LAST_FRAME && left_ref == LAST_FRAME {
+                        3
+                    } else if above_ref == LAST_FRAME || left_ref == LAST_FRAME {
+                        let edge_ref = if above_ref == LAST_FRAME {
+                            left_ref
+                        } else {
+                            above_ref
+                        };
+                        4 * usize::from(edge_ref == GOLDEN_FRAME)
+                    } else {
+                        2 * usize::from(above_ref == GOLDEN_FRAME)
+                            + 2 * usize::from(left_ref == GOLDEN_FRAME)
+                    }
+                }
+                (above_compound, _) => {
+                    let (single, compound) = if above_compound {
+                        (left, above)
+                    } else {
+                        (above, left)
+                    };
+                    match single.ref_frame[0] {
+                        GOLDEN_FRAME => 3 + usize::from(uses_golden(compound)),
+                        ALTREF_FRAME => usize::from(uses_golden(compound)),
+                        _ => 1 + 2 * usize::from(uses_golden(compound)),
+                    }
+                }
+            },
+        },
+        (Some(edge), None) | (None, Some(edge)) => {
+            if !edge.is_inter || (edge.ref_frame[0] == LAST_FRAME && !has_second_ref(edge)) {
+                2
+            } else if !has_second_ref(edge) {
+                4 * usize::from(edge.ref_frame[0] == GOLDEN_FRAME)
+            } else {
+                3 * usize::from(uses_golden(edge))
+            }
+        }
+        (None, None) => 2,
+    }
+}
+
+/// The candidate list of the motion vector prediction. Holds up to two different motion vectors.
+#[derive(Default)]
+struct MvList {
+    mvs: [MotionVector; 2],
+    count: usize,
+}
+
+impl MvList {
+    /// Adds a motion vector unless it equals the first one. Returns `true` once the list is full.
+    fn add(&mut self, mv: MotionVector) -> bool {
+        if self.count == 0 {
+            self.mvs[0] = mv;
+            self.count = 1;
+            false
+        } else if mv != self.mvs[0] {
+            self.mvs[1] = mv;
+            self.count = 2;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Adds the motion vector of a sub-block of the candidate that uses the same reference frame.
+/// Returns `true` once the list is full.
+fn add_same_ref_mv(
+    list: &mut MvList,
+    candidate: &ModeInfo,
+    sub_block: usize,
+    ref_frame: usize,
+) -> bool {
+    match candidate
+        .ref_frame
+        .iter()
+        .position(|candidate_ref| *candidate_ref == ref_frame)
+    {
+        Some(which) => list.add(candidate.sub_mvs[sub_block][which]),
+        None => false,
+    }
+}
+
+/// Clamps a motion vector to point at most `border` 1/8 pixels outside of the frame edges.
+fn clamp_mv(mv: MotionVector, edges: &[i32; 4], border: i32) -> MotionVector {
+    let [left, right, top, bottom] = *edges;
+    MotionVector {
+        row: clamp_to_i16(i32::from(mv.row).clamp(top - border, bottom + border)),
+        col: clamp_to_i16(i32::from(mv.col).clamp(left - border, right + border)),
+    }
+}
+
+fn clamp_to_i16(value: i32) -> i16 {
+    i16::try_from(value).unwrap_or(if value < 0 { i16::MIN } else { i16::MAX })
+}
+
+/// Indicates that the motion vector is small enough to use the high precision bit.
+fn use_mv_hp(mv: MotionVector) -> bool {
+    (i32::from(mv.row).abs() >> 3) < COMPANDED_MVREF_THRESH
+        && (i32::from(mv.col).abs() >> 3) < COMPANDED_MVREF_THRESH
+}
+
+/// Rounds an odd motion vector component towards zero.
+fn lower_precision(value: i16) -> i16 {
+    if value & 1 == 0 {
+        value
+    } else if value > 0 {
+        value - 1
+    } else {
+        value + 1
+    }
+}
@@ -0,0 +1,395 @@
+//! Decoding of the tokens of the transform blocks of the residual.
+
+use super::{uv_tx_size, TileDecoder};
+use crate::{
+    default_probs::PARETO_TABLE,
+    mode_info::{BlockSize, ModeInfo, TxSize, TxType},
+    residual::TransformBlock,
+    scan::*,
+    BoolDecoder, ColorDepth,
+};
+
+/// Coefficient bands of the 4x4 transform.
+const COEFBAND_4X4: [u8; 16] = [0, 1, 1, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 5, 5, 5];
+
+/// Coefficient bands of the larger transforms. All following positions use band 5.
+const COEFBAND_8X8_PLUS: [u8; 22] = [
+    0, 1, 1, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+];
+
+/// Energy class of the tokens that is used to derive the context of the next coefficient.
+const ENERGY_CLASS: [u8; 11] = [0, 1, 2, 3, 3, 4, 4, 5, 5, 5, 5];
+
+/// Probabilities of the extra bits of the coefficient categories 1 to 5.
+const CAT1_PROBS: [u8; 1] = [159];
+const CAT2_PROBS: [u8; 2] = [165, 145];
+const CAT3_PROBS: [u8; 3] = [173, 148, 140];
+const CAT4_PROBS: [u8; 4] = [176, 155, 140, 135];
+const CAT5_PROBS: [u8; 5] = [180, 157, 141, 134, 130];
+
+/// Probabilities of the extra bits of the coefficient category 6 at 8 bit depth.
+const CAT6_PROBS: [u8; 14] = [
+    254, 254, 254, 252, 249, 243, 230, 196, 177, 153, 140, 133, 130, 129,
+];
+
+impl TileDecoder<'_> {
+    // Implements spec "residual()". Returns the number of coefficients of all transform blocks.
+    //
+    // The transform blocks of skipped blocks are stored without reading any tokens.
+    pub(super) fn residual(&mut self, bd: &mut BoolDecoder, mode_info: &ModeInfo) -> usize {
+        let mut eob_total = 0;
+        let block_size = mode_info.block_size.max(BlockSize::Block8x8);
+        for plane in 0..3 {
+            let (ss_x, ss_y) = self.plane_subsampling(plane);
+            let tx_size = if plane == 0 {
+                mode_info.tx_size
+            } else {
+                uv_tx_size(mode_info.tx_size, block_size, ss_x, ss_y)
+            };
+            let step = 1 << u8::from(tx_size);
+            let num_4x4_w = (block_size.num_8x8_wide() * 2) >> ss_x;
+            let num_4x4_h = (block_size.num_8x8_high() * 2) >> ss_y;
+            let base_x = (mode_info.mi_col * 2) >> ss_x;
+            let base_y = (mode_info.mi_row * 2) >> ss_y;
+            let max_x = (self.frame.mi_cols() * 2) >> ss_x;
+            let max_y = (self.frame.mi_rows() * 2) >> ss_y;
+
+            for y in (0..num_4x4_h).step_by(step) {
+                for x in (0..num_4x4_w).step_by(step) {
+                    let start_x = base_x + x;
+                    let start_y = base_y + y;
+                    if start_x >= max_x || start_y >= max_y {
+                        continue;
+                    }
+
+                    let tx_type = if plane > 0
+                        || self.frame.lossless()
+                        || mode_info.is_inter
+                        || tx_size == TxSize::Tx32x32
+                    {
+                        TxType::DctDct
+                    } else if tx_size == TxSize::Tx4x4 {
+                        let block = usize::from(y.min(1) * 2 + x.min(1));
+                        TxType::from_intra_mode(mode_info.sub_modes[block])
+                    } else {
+                        TxType::from_intra_mode(mode_info.y_mode)
+                    };
+                    eob_total += if mode_info.skip {
+                        self.store_transform_block(
+                            mode_info,
+                            plane,
+                            tx_size,
+                            tx_type,
+                            (start_x, start_y),
+                            0,
+                        );
+                        0
+                    } else {
+                        self.decode_tx_block(
+                            bd,
+                            mode_info,
+                            plane,
+                            tx_size,
+                            tx_type,
+                            (start_x, start_y),
+                            (max_x, max_y),
+                        )
+                    };
+                }
+            }
+        }
+        eob_total
+    }
+
+    /// Reads the tokens of a transform block, stores its coefficients and updates the nonzero
+    /// contexts. Returns the number of coefficients.
+    #[allow(clippy::too_many_arguments)]
+    fn decode_tx_block(
+        &mut self,
+        bd: &mut BoolDecoder,
+        mode_info: &ModeInfo,
+        plane: usize,
+        tx_size: TxSize,
+        tx_type: TxType,
+        (start_x, start_y): (u16, u16),
+        (max_x, max_y): (u16, u16),
+    ) -> usize {
+        let (ss_x, ss_y) = self.plane_subsampling(plane);
+        let above_start = usize::from(start_x - ((self.mi_col_start * 2) >> ss_x));
+        let left_start = usize::from(start_y & (15 >> ss_y));
+        let step = 1 << u8::from(tx_size);
+
+        let above = &self.above_nonzero_context[plane][above_start..above_start + step];
+        let left = &self.left_nonzero_context[plane][left_start..left_start + step];
+        let ctx = usize::from(above.iter().any(|nonzero| *nonzero))
+            + usize::from(left.iter().any(|nonzero| *nonzero));
+
+        let eob = self.tokens(bd, mode_info, plane, tx_size, tx_type, ctx);
+        self.store_transform_block(mode_info, plane, tx_size, tx_type, (start_x, start_y), eob);
+        let nonzero = eob > 0;
+
+        // Contexts outside of the frame are always zero.
+        let above_count = usize::from(max_x - start_x).min(step);
+        let left_count = usize::from(max_y - start_y).min(step);
+        let above = &mut self.above_nonzero_context[plane][above_start..above_start + step];
+        above.fill(false);
+        above[..above_count].fill(nonzero);
+        let left = &mut self.left_nonzero_context[plane][left_start..left_start + step];
+        left.fill(false);
+        left[..left_count].fill(nonzero);
+
+        eob
+    }
+
+    /// Stores a transform block, taking the coefficients of the current transform block.
+    fn store_transform_block(
+        &mut self,
+        mode_info: &ModeInfo,
+        plane: usize,
+        tx_size: TxSize,
+        tx_type: TxType,
+        (start_x, start_y): (u16, u16),
+        eob: usize,
+    ) {
+        if !self.store_coefficients {
+            return;
+        }
+        let coefficients = if eob > 0 {
+            let coefficients = &mut self.coefficients[..16 << (u8::from(tx_size) << 1)];
+            let taken = coefficients.to_vec();
+            coefficients.fill(0);
+            taken
+        } else {
+            Vec::new()
+        };
+        self.transform_blocks.push(TransformBlock {
+            mi_row: mode_info.mi_row,
+            mi_col: mode_info.mi_col,
+            plane,
+            x: u32::from(start_x) * 4,
+            y: u32::from(start_y) * 4,
+            tx_size,
+            tx_type,
+            eob,
+            coefficients,
+        });
+    }
+
+    // Implements spec "tokens()". Returns the number of decoded coefficients (end of block).
+    //
+    // The coefficients are dequantized as in spec "8.6.2 Reconstruction process" and stored
+    // in raster order. The first coefficient uses the DC quantizer, all others the AC quantizer.
+    fn tokens(
+        &mut self,
+        bd: &mut BoolDecoder,
+        mode_info: &ModeInfo,
+        plane: usize,
+        tx_size: TxSize,
+        tx_type: TxType,
+        mut ctx: usize,
+    ) -> usize {
+        let tx = usize::from(u8::from(tx_size));
+        let plane_type = usize::from(plane > 0);
+        let ref_type = usize::from(mode_info.is_inter);
+        let probs = &self.frame_context.coef_probs()[tx][plane_type][ref_type];
+        let scan = scan_order(tx_size, tx_type);
+        let log2 = tx + 2;
+        let max_eob = 16 << (tx << 1);
+        let [dc_quantizer, ac_quantizer] =
+            self.quantizers[usize::from(mode_info.segment_id)][plane_type];
+        // The coefficients of 32x32 transforms are halved.
+        let shift = u32::from(tx_size == TxSize::Tx32x32);
+
+        let mut c = 0;
+        while c < max_eob {
+            let mut band = coef_band(tx_size, c);
+            let mut prob = &probs[band][ctx];
+            let more_coefs = bd.read_bool(prob[0]);
+            self.counts.more_coefs[tx][plane_type][ref_type][band][ctx][usize::from(more_coefs)] +=
+                1;
+            if !more_coefs {
+                break;
+            }
+
+            while !bd.read_bool(prob[1]) {
+                self.counts.tokens[tx][plane_type][ref_type][band][ctx][0] += 1;
+                self.token_cache[usize::from(scan[c])] = 0;
+                c += 1;
+                if c >= max_eob {
+                    return c;
+                }
+                ctx = self.coef_context(scan, tx_type, log2, c);
+                band = coef_band(tx_size, c);
+                prob = &probs[band][ctx];
+            }
+
+            let (token, value) = if bd.read_bool(prob[2]) {
+                self.read_large_token(bd, prob[2])
+            } else {
+                (1, 1)
+            };
+            self.counts.tokens[tx][plane_type][ref_type][band][ctx][usize::from(token.min(2))] += 1;
+            let sign = bd.read_bool(128);
+            let pos = usize::from(scan[c]);
+            if self.store_coefficients {
+                let quantizer = if c == 0 { dc_quantizer } else { ac_quantizer };
+                let magnitude = (i64::from(value) * i64::from(quantizer)) >> shift;
+                let coefficient = if sign { -magnitude } else { magnitude };
+                self.coefficients[pos] = clamp_to_i32(coefficient);
+            }
+            self.token_cache[pos] = ENERGY_CLASS[usize::from(token)];
+
+            c += 1;
+            if c < max_eob {
+                ctx = self.coef_context(scan, tx_type, log2, c);
+            }
+        }
+        c
+    }
+
+    /// Reads a token larger than ONE_TOKEN using the pareto probabilities
+    /// and the extra bits of the token categories. Returns the token and its absolute value.
+    fn read_large_token(&self, bd: &mut BoolDecoder, prob: u8) -> (u8, u32) {
+        let pareto = pareto_probs(prob);
+        if !bd.read_bool(pareto[0]) {
+            if !bd.read_bool(pareto[1]) {
+                (2, 2)
+            } else if !bd.read_bool(pareto[2]) {
+                (3, 3)
+            } else {
+                (4, 4)
+            }
+        } else if !bd.read_bool(pareto[3]) {
+            if !bd.read_bool(pareto[4]) {
+                (5, 5 + read_extra_bits(bd, &CAT1_PROBS))
+            } else {
+                (6, 7 + read_extra_bits(bd, &CAT2_PROBS))
+            }
+        } else if !bd.read_bool(pareto[5]) {
+            if !bd.read_bool(pareto[6]) {
+                (7, 11 + read_extra_bits(bd, &CAT3_PROBS))
+            } else {
+                (8, 19 + read_extra_bits(bd, &CAT4_PROBS))
+            }
+        } else if !bd.read_bool(pareto[7]) {
+            (9, 35 + read_extra_bits(bd, &CAT5_PROBS))
+        } else {
+            // High bit depths prefix the category 6 bits with additional bits.
+            let high_bits: &[u8] = match self.frame.color_depth() {
+                ColorDepth::Depth10 => &[255; 2],
+                ColorDepth::Depth12 => &[255; 4],
+                _ => &[],
+            };
+            let high = read_extra_bits(bd, high_bits);
+            let low = read_extra_bits(bd, &CAT6_PROBS);
+            (10, 67 + ((high << CAT6_PROBS.len()) | low))
+        }
+    }
+
+    /// Returns the context of the coefficient at the given scan position, derived from the
+    /// energy of its already decoded neighbours.
+    fn coef_context(&self, scan: &[u16], tx_type: TxType, log2: usize, c: usize) -> usize {
+        let n = 1 << log2;
+        let pos = usize::from(scan[c]);
+        let i = pos >> log2;
+        let j = pos & (n - 1);
+        let (a, b) = if i > 0 && j > 0 {
+            match scan_type(log2, tx_type) {
+                ScanType::Col => (pos - n, pos - n),
+                ScanType::Row => (pos - 1, pos - 1),
+                ScanType::Default => (pos - n, pos - 1),
+            }
+        } else if i > 0 {
+            (pos - n, pos - n)
+        } else {
+            (pos - 1, pos - 1)
+        };
+        usize::from((1 + self.token_cache[a] + self.token_cache[b]) >> 1)
+    }
+
+    /// Clears the nonzero contexts covered by a skipped block.
+    pub(super) fn reset_nonzero_context(&mut self, mode_info: &ModeInfo) {
+        let block_size = mode_info.block_size.max(BlockSize::Block8x8);
+        for plane in 0..3 {
+            let (ss_x, ss_y) = self.plane_subsampling(plane);
+            let num_4x4_w = usize::from((block_size.num_8x8_wide() * 2) >> ss_x);
+            let num_4x4_h = usize::from((block_size.num_8x8_high() * 2) >> ss_y);
+            let above_start =
+                usize::from(((mode_info.mi_col * 2) >> ss_x) - ((self.mi_col_start * 2) >> ss_x));
+            let left_start = usize::from(((mode_info.mi_row & 7) * 2) >> ss_y);
+            self.above_nonzero_context[plane][above_start..above_start + num_4x4_w].fill(false);
+            self.left_nonzero_context[plane][left_start..left_start + num_4x4_h].fill(false);
+        }
+    }
+}
+
+/// The scan order of a transform block.
+enum ScanType {
+    Default,
+    Col,
+    Row,
+}
+
+fn scan_type(log2: usize, tx_type: TxType) -> ScanType {
+    match tx_type {
+        TxType::AdstDct if log2 < 5 => ScanType::Row,
+        TxType::DctAdst if log2 < 5 => ScanType::Col,
+        _ => ScanType::Default,
+    }
+}
+
+// Implements spec "get_scan()".
+fn scan_order(tx_size: TxSize, tx_type: TxType) -> &'static [u16] {
+    match (
+        tx_size,
+        scan_type(usize::from(u8::from(tx_size)) + 2, tx_type),
+    ) {
+        (TxSize::Tx4x4, ScanType::Default) => &DEFAULT_SCAN_4X4,
+        (TxSize::Tx4x4, ScanType::Col) => &COL_SCAN_4X4,
+        (TxSize::Tx4x4, ScanType::Row) => &ROW_SCAN_4X4,
+        (TxSize::Tx8x8, ScanType::Default) => &DEFAULT_SCAN_8X8,
+        (TxSize::Tx8x8, ScanType::Col) => &COL_SCAN_8X8,
+        (TxSize::Tx8x8, ScanType::Row) => &ROW_SCAN_8X8,
+        (TxSize::Tx16x16, ScanType::Default) => &DEFAULT_SCAN_16X16,
+        (TxSize::Tx16x16, ScanType::Col) => &COL_SCAN_16X16,
+        (TxSize::Tx16x16, ScanType::Row) => &ROW_SCAN_16X16,
+        (TxSize::Tx32x32, _) => &DEFAULT_SCAN_32X32,
+    }
+}
+
+fn coef_band(tx_size: TxSize, c: usize) -> usize {
+    if tx_size == TxSize::Tx4x4 {
+        usize::from(COEFBAND_4X4[c])
+    } else {
+        COEFBAND_8X8_PLUS
+            .get(c)
+            .map_or(5, |band| usize::from(*band))
+    }
+}
+
+/// Returns the probabilities of the token tree nodes below ONE_TOKEN.
+///
+/// Odd probabilities index the pareto table directly, even probabilities are interpolated.
+fn pareto_probs(prob: u8) -> [u8; 8] {
+    let x = usize::from((prob - 1) / 2);
+    if prob & 1 == 1 {
+        PARETO_TABLE[x]
+    } else {
+        let mut probs = [0; 8];
+        for (i, p) in probs.iter_mut().enumerate() {
+            let sum = u16::from(PARETO_TABLE[x][i]) + u16::from(PARETO_TABLE[x + 1][i]);
+            *p = (sum >> 1).to_le_bytes()[0];
+        }
+        probs
+    }
+}
+
+fn read_extra_bits(bd: &mut BoolDecoder, probs: &[u8]) -> u32 {
+    probs.iter().fold(0, |value, prob| {
+        (value << 1) | u32::from(bd.read_bool(*prob))
+    })
+}
+
+fn clamp_to_i32(value: i64) -> i32 {
+    i32::try_from(value).unwrap_or(if value < 0 { i32::MIN } else { i32::MAX })
+}
@@ -0,0 +1,826 @@
+//! Inverse transforms as defined in spec "8.7.1 1D transforms" and
+//! "8.7.2 2D inverse transform process".
+//!
+//! The transforms follow the intermediate rounding of the spec exactly. All products are
+//! calculated with 64 bit, so the larger coefficient ranges of 10 and 12 bit depth are
+//! supported. The results only wrap (saturate) for coefficients that violate the bitstream
+//! conformance requirement of fitting into `8 + BitDepth` bits.
+
+use std::convert::TryFrom;
+
+use crate::{Plane, Result, TransformBlock, TxSize, TxType, Vp9ParserError};
+
+/// `round(16384 * cos(i * PI / 64))` for i = 0..=32 (spec `cos64_lookup`).
+const COS64: [i64; 33] = [
+    16384, 16364, 16305, 16207, 16069, 15893, 15679, 15426, 15137, 14811, 14449, 14053, 13623,
+    13160, 12665, 12140, 11585, 11003, 10394, 9760, 9102, 8423, 7723, 7005, 6270, 5520, 4756, 3981,
+    3196, 2404, 1606, 804, 0,
+];
+
+/// `round(16384 * sqrt(2) * 2 / 3 * sin(i * PI / 9))` for i = 1..=4 (spec `sinpi_1_9` to `sinpi_4_9`).
+const SINPI_9: [i64; 5] = [0, 5283, 9929, 13377, 15212];
+
+/// Applies the 2D inverse transform to a transform block in place.
+///
+/// The block holds the dequantized coefficients in raster order and receives the residual.
+/// The rows are transformed first, then the columns, followed by the final rounding. The
+/// type of 32x32 transforms is ignored, they always use the DCT. Lossless frames use the
+/// Walsh-Hadamard transform, which only exists for 4x4 blocks.
+pub fn inverse_transform_2d(
+    block: &mut [i32],
+    tx_size: TxSize,
+    tx_type: TxType,
+    lossless: bool,
+) -> Result<()> {
+    let n = 4 << u8::from(tx_size);
+    if block.len() != n * n {
+        return Err(Vp9ParserError::InvalidCoefficientCount(block.len()));
+    }
+
+    if lossless {
+        if tx_size != TxSize::Tx4x4 {
+            return Err(Vp9ParserError::InvalidTransformSize(tx_size));
+        }
+        for row in block.chunks_exact_mut(4) {
+            iwht4(<&mut [i32; 4]>::try_from(row)?, 2);
+        }
+        let mut column = [0; 4];
+        for i in 0..4 {
+            for (j, value) in column.iter_mut().enumerate() {
+                *value = block[j * 4 + i];
+            }
+            iwht4(&mut column, 0);
+            for (j, value) in column.iter().enumerate() {
+                block[j * 4 + i] = *value;
+            }
+        }
+        return Ok(());
+    }
+
+    let (row_adst, column_adst) = match tx_type {
+        _ if tx_size == TxSize::Tx32x32 => (false, false),
+        TxType::DctDct => (false, false),
+        TxType::AdstDct => (false, true),
+        TxType::DctAdst => (true, false),
+        TxType::AdstAdst => (true, true),
+    };
+
+    for row in block.chunks_exact_mut(n) {
+        inverse_transform_1d(row, row_adst)?;
+    }
+
+    // Implements the final rounding "Round2(T[i], Min(6, n + 2))".
+    let shift = (u8::from(tx_size) + 4).min(6);
+    let mut column = [0; 32];
+    for i in 0..n {
+        for (j, value) in column[..n].iter_mut().enumerate() {
+            *value = block[j * n + i];
+        }
+        inverse_transform_1d(&mut column[..n], column_adst)?;
+        for (j, value) in column[..n].iter().enumerate() {
+            block[j * n + i] = round2(i64::from(*value), shift);
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds the residual of a transform block to the prediction in the plane.
+///
+/// Implements spec "8.6.2 Reconstruction process". Blocks without coefficients keep the
+/// prediction. The reconstructed samples are clipped to the bit depth of the plane.
+pub fn reconstruct(
+    plane: &mut Plane,
+    transform_block: &TransformBlock,
+    lossless: bool,
+) -> Result<()> {
+    if transform_block.eob == 0 {
+        return Ok(());
+    }
+
+    let n = 4 << u8::from(transform_block.tx_size);
+    let x = usize::try_from(transform_block.x)?;
+    let y = usize::try_from(transform_block.y)?;
+    if !plane.contains(x, y, n, n) {
+        return Err(Vp9ParserError::BlockOutsidePlane(x, y));
+    }
+
+    let mut residual = transform_block.coefficients.clone();
+    inverse_transform_2d(
+        &mut residual,
+        transform_block.tx_size,
+        transform_block.tx_type,
+        lossless,
+    )?;
+
+    let stride = plane.stride();
+    let max_value = i32::from(plane.max_value());
+    let data = plane.data_mut();
+    for (row, values) in residual.chunks_exact(n).enumerate() {
+        let start = (y + row) * stride + x;
+        for (sample, value) in data[start..start + n].iter_mut().zip(values) {
+            *sample = u16::try_from((i32::from(*sample) + value).clamp(0, max_value))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies the inverse DCT or ADST to a row or column of 4, 8, 16 or 32 values.
+fn inverse_transform_1d(data: &mut [i32], adst: bool) -> Result<()> {
+    match (data.len(), adst) {
+        (4, false) => idct4(<&mut [i32; 4]>::try_from(data)?),
+        (4, true) => iadst4(<&mut [i32; 4]>::try_from(data)?),
+        (8, false) => idct8(<&mut [i32; 8]>::try_from(data)?),
+        (8, true) => iadst8(<&mut [i32; 8]>::try_from(data)?),
+        (16, false) => idct16(<&mut [i32; 16]>::try_from(data)?),
+        (16, true) => iadst16(<&mut [i32; 16]>::try_from(data)?),
+        (32, _) => idct32(<&mut [i32; 32]>::try_from(data)?),
+        (len, _) => return Err(Vp9ParserError::InvalidCoefficientCount(len)),
+    }
+    Ok(())
+}
+
+/// Inverse DCT of 4 values.
+pub fn idct4(x: &mut [i32; 4]) {
+    *x = idct4_i64(&x.map(i64::from)).map(saturate);
+}
+
+/// Inverse DCT of 8 values.
+pub fn idct8(x: &mut [i32; 8]) {
+    *x = idct8_i64(&x.map(i64::from)).map(saturate);
+}
+
+/// Inverse DCT of 16 values.
+pub fn idct16(x: &mut [i32; 16]) {
+    *x = idct16_i64(&x.map(i64::from)).map(saturate);
+}
+
+/// Inverse DCT of 32 values.
+pub fn idct32(x: &mut [i32; 32]) {
+    *x = idct32_i64(&x.map(i64::from)).map(saturate);
+}
+
+/// Inverse ADST of 4 values.
+pub fn iadst4(x: &mut [i32; 4]) {
+    *x = iadst4_i64(&x.map(i64::from)).map(saturate);
+}
+
+/// Inverse ADST of 8 values.
+pub fn iadst8(x: &mut [i32; 8]) {
+    *x = iadst8_i64(&x.map(i64::from)).map(saturate);
+}
+
+/// Inverse ADST of 16 values.
+pub fn iadst16(x: &mut [i32; 16]) {
+    *x = iadst16_i64(&x.map(i64::from)).map(saturate);
+}
+
+/// Inverse Walsh-Hadamard transform of 4 values. The input is shifted right by `shift` first.
+///
+/// The rows of a lossless block use a shift of 2, the columns a shift of 0.
+pub fn iwht4(x: &mut [i32; 4], shift: u32) {
+    let [mut a, mut c, mut d, mut b] = x.map(|value| i64::from(value) >> shift);
+    a += c;
+    d -= b;
+    let e = (a - d) >> 1;
+    b = e - b;
+    c = e - c;
+    a -= b;
+    d += c;
+    *x = [a, b, c, d].map(saturate);
+}
+
+// The even half of a DCT is the DCT of half the size applied to the even inputs. The odd half
+// is calculated in stages of butterfly rotations and additions, and both halves are combined
+// by a final butterfly.
+
+fn idct4_i64(x: &[i64; 4]) -> [i64; 4] {
+    let s0 = round_shift((x[0] + x[2]) * COS64[16]);
+    let s1 = round_shift((x[0] - x[2]) * COS64[16]);
+    let s2 = round_shift(x[1] * COS64[24] - x[3] * COS64[8]);
+    let s3 = round_shift(x[1] * COS64[8] + x[3] * COS64[24]);
+    [s0 + s3, s1 + s2, s1 - s2, s0 - s3]
+}
+
+fn idct8_i64(x: &[i64; 8]) -> [i64; 8] {
+    let even = idct4_i64(&std::array::from_fn(|i| x[2 * i]));
+
+    let s4 = round_shift(x[1] * COS64[28] - x[7] * COS64[4]);
+    let s7 = round_shift(x[1] * COS64[4] + x[7] * COS64[28]);
+    let s5 = round_shift(x[5] * COS64[12] - x[3] * COS64[20]);
+    let s6 = round_shift(x[5] * COS64[20] + x[3] * COS64[12]);
+
+    let t4 = s4 + s5;
+    let t5 = s4 - s5;
+    let t6 = -s6 + s7;
+    let t7 = s6 + s7;
+
+    let odd = [
+        t4,
+        round_shift((t6 - t5) * COS64[16]),
+        round_shift((t5 + t6) * COS64[16]),
+        t7,
+    ];
+    combine(&even, &odd)
+}
+
+fn idct16_i64(x: &[i64; 16]) -> [i64; 16] {
+    let even = idct8_i64(&std::array::from_fn(|i| x[2 * i]));
+
+    let s8 = round_shift(x[1] * COS64[30] - x[15] * COS64[2]);
+    let s15 = round_shift(x[1] * COS64[2] + x[15] * COS64[30]);
+    let s9 = round_shift(x[9] * COS64[14] - x[7] * COS64[18]);
+    let s14 = round_shift(x[9] * COS64[18] + x[7] * COS64[14]);
+    let s10 = round_shift(x[5] * COS64[22] - x[11] * COS64[10]);
+    let s13 = round_shift(x[5] * COS64[10] + x[11] * COS64[22]);
+    let s11 = round_shift(x[13] * COS64[6] - x[3] * COS64[26]);
+    let s12 = round_shift(x[13] * COS64[26] + x[3] * COS64[6]);
+
+    let t8 = s8 + s9;
+    let t9 = s8 - s9;
+    let t10 = -s10 + s11;
+    let t11 = s10 + s11;
+    let t12 = s12 + s13;
+    let t13 = s12 - s13;
+    let t14 = -s14 + s15;
+    let t15 = s14 + s15;
+
+    let u9 = round_shift(-t9 * COS64[8] + t14 * COS64[24]);
+    let u14 = round_shift(t9 * COS64[24] + t14 * COS64[8]);
+    let u10 = round_shift(-t10 * COS64[24] - t13 * COS64[8]);
+    let u13 = round_shift(-t10 * COS64[8] + t13 * COS64[24]);
+
+    let v8 = t8 + t11;
+    let v9 = u9 + u10;
+    let v10 = u9 - u10;
+    let v11 = t8 - t11;
+    let v12 = -t12 + t15;
+    let v13 = -u13 + u14;
+    let v14 = u13 + u14;
+    let v15 = t12 + t15;
+
+    let odd = [
+        v8,
+        v9,
+        round_shift((-v10 + v13) * COS64[16]),
+        round_shift((-v11 + v12) * COS64[16]),
+        round_shift((v11 + v12) * COS64[16]),
+        round_shift((v10 + v13) * COS64[16]),
+        v14,
+        v15,
+    ];
+    combine(&even, &odd)
+}
+
+fn idct32_i64(x: &[i64; 32]) -> [i64; 32] {
+    let even = idct16_i64(&std::array::from_fn(|i| x[2 * i]));
+
+    // The odd values are stored at index 16 and above to follow the spec numbering.
+    let mut s = [0; 32];
+    for (i, (a, b)) in [
+        (1, 31),
+        (17, 15),
+        (9, 23),
+        (25, 7),
+        (5, 27),
+        (21, 11),
+        (13, 19),
+        (29, 3),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        s[16 + i] = round_shift(x[a] * COS64[32 - a] - x[b] * COS64[32 - b]);
+        s[31 - i] = round_shift(x[a] * COS64[32 - b] + x[b] * COS64[32 - a]);
+    }
+
+    let mut t = [0; 32];
+    for k in (16..32).step_by(4) {
+        t[k] = s[k] + s[k + 1];
+        t[k + 1] = s[k] - s[k + 1];
+        t[k + 2] = -s[k + 2] + s[k + 3];
+        t[k + 3] = s[k + 2] + s[k + 3];
+    }
+
+    let mut u = t;
+    u[17] = round_shift(-t[17] * COS64[4] + t[30] * COS64[28]);
+    u[30] = round_shift(t[17] * COS64[28] + t[30] * COS64[4]);
+    u[18] = round_shift(-t[18] * COS64[28] - t[29] * COS64[4]);
+    u[29] = round_shift(-t[18] * COS64[4] + t[29] * COS64[28]);
+    u[21] = round_shift(-t[21] * COS64[20] + t[26] * COS64[12]);
+    u[26] = round_shift(t[21] * COS64[12] + t[26] * COS64[20]);
+    u[22] = round_shift(-t[22] * COS64[12] - t[25] * COS64[20]);
+    u[25] = round_shift(-t[22] * COS64[20] + t[25] * COS64[12]);
+
+    let mut v = [0; 32];
+    for k in [16, 24] {
+        v[k] = u[k] + u[k + 3];
+        v[k + 1] = u[k + 1] + u[k + 2];
+        v[k + 2] = u[k + 1] - u[k + 2];
+        v[k + 3] = u[k] - u[k + 3];
+        v[k + 4] = -u[k + 4] + u[k + 7];
+        v[k + 5] = -u[k + 5] + u[k + 6];
+        v[k + 6] = u[k + 5] + u[k + 6];
+        v[k + 7] = u[k + 4] + u[k + 7];
+    }
+
+    let mut w = v;
+    w[18] = round_shift(-v[18] * COS64[8] + v[29] * COS64[24]);
+    w[29] = round_shift(v[18] * COS64[24] + v[29] * COS64[8]);
+    w[19] = round_shift(-v[19] * COS64[8] + v[28] * COS64[24]);
+    w[28] = round_shift(v[19] * COS64[24] + v[28] * COS64[8]);
+    w[20] = round_shift(-v[20] * COS64[24] - v[27] * COS64[8]);
+    w[27] = round_shift(-v[20] * COS64[8] + v[27] * COS64[24]);
+    w[21] = round_shift(-v[21] * COS64[24] - v[26] * COS64[8]);
+    w[26] = round_shift(-v[21] * COS64[8] + v[26] * COS64[24]);
+
+    let mut y = [0; 32];
+    for i in 0..4 {
+        y[16 + i] = w[16 + i] + w[23 - i];
+        y[23 - i] = w[16 + i] - w[23 - i];
+        y[24 + i] = -w[24 + i] + w[31 - i];
+        y[31 - i] = w[24 + i] + w[31 - i];
+    }
+    let mut odd = [0; 16];
+    odd.copy_from_slice(&y[16..]);
+    for i in 0..4 {
+        let (a, b) = (20 + i, 27 - i);
+        odd[a - 16] = round_shift((-y[a] + y[b]) * COS64[16]);
+        odd[b - 16] = round_shift((y[a] + y[b]) * COS64[16]);
+    }
+    combine(&even, &odd)
+}
+
+/// Combines the even and the odd half of a DCT with the final butterfly.
+fn combine<const N: usize, const H: usize>(even: &[i64; H], odd: &[i64; H]) -> [i64; N] {
+    let mut out = [0; N];
+    for i in 0..H {
+        out[i] = even[i] + odd[H - 1 - i];
+        out[N - 1 - i] = even[i] - odd[H - 1 - i];
+    }
+    out
+}
+
+fn iadst4_i64(x: &[i64; 4]) -> [i64; 4] {
+    let s0 = SINPI_9[1] * x[0] + SINPI_9[4] * x[2] + SINPI_9[2] * x[3];
+    let s1 = SINPI_9[2] * x[0] - SINPI_9[1] * x[2] - SINPI_9[4] * x[3];
+    let s2 = SINPI_9[3] * (x[0] - x[2] + x[3]);
+    let s3 = SINPI_9[3] * x[1];
+    [
+        round_shift(s0 + s3),
+        round_shift(s1 + s3),
+        round_shift(s2),
+        round_shift(s0 + s1 - s3),
+    ]
+}
+
+fn iadst8_i64(input: &[i64; 8]) -> [i64; 8] {
+    let x = [
+        input[7], input[0], input[5], input[2], input[3], input[4], input[1], input[6],
+    ];
+
+    let mut s = [0; 8];
+    for (k, (a, b)) in [(2, 30), (10, 22), (18, 14), (26, 6)]
+        .into_iter()
+        .enumerate()
+    {
+        s[2 * k] = x[2 * k] * COS64[a] + x[2 * k + 1] * COS64[b];
+        s[2 * k + 1] = x[2 * k] * COS64[b] - x[2 * k + 1] * COS64[a];
+    }
+    let x: [i64; 8] = std::array::from_fn(|k| {
+        if k < 4 {
+            round_shift(s[k] + s[k + 4])
+        } else {
+            round_shift(s[k - 4] - s[k])
+        }
+    });
+
+    let s4 = x[4] * COS64[8] + x[5] * COS64[24];
+    let s5 = x[4] * COS64[24] - x[5] * COS64[8];
+    let s6 = -x[6] * COS64[24] + x[7] * COS64[8];
+    let s7 = x[6] * COS64[8] + x[7] * COS64[24];
+    let x = [
+        x[0] + x[2],
+        x[1] + x[3],
+        x[0] - x[2],
+        x[1] - x[3],
+        round_shift(s4 + s6),
+        round_shift(s5 + s7),
+        round_shift(s4 - s6),
+        round_shift(s5 - s7),
+    ];
+
+    let x2 = round_shift(COS64[16] * (x[2] + x[3]));
+    let x3 = round_shift(COS64[16] * (x[2] - x[3]));
+    let x6 = round_shift(COS64[16] * (x[6] + x[7]));
+    let x7 = round_shift(COS64[16] * (x[6] - x[7]));
+
+    [x[0], -x[4], x6, -x2, x3, -x7, x[5], -x[1]]
+}
+
+fn iadst16_i64(input: &[i64; 16]) -> [i64; 16] {
+    let x = [
+        input[15], input[0], input[13], input[2], input[11], input[4], input[9], input[6],
+        input[7], input[8], input[5], input[10], input[3], input[12], input[1], input[14],
+    ];
+
+    let mut s = [0; 16];
+    for (k, (a, b)) in [
+        (1, 31),
+        (5, 27),
+        (9, 23),
+        (13, 19),
+        (17, 15),
+        (21, 11),
+        (25, 7),
+        (29, 3),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        s[2 * k] = x[2 * k] * COS64[a] + x[2 * k + 1] * COS64[b];
+        s[2 * k + 1] = x[2 * k] * COS64[b] - x[2 * k + 1] * COS64[a];
+    }
+    let x: [i64; 16] = std::array::from_fn(|k| {
+        if k < 8 {
+            round_shift(s[k] + s[k + 8])
+        } else {
+            round_shift(s[k - 8] - s[k])
+        }
+    });
+
+    let mut s = x;
+    s[8] = x[8] * COS64[4] + x[9] * COS64[28];
+    s[9] = x[8] * COS64[28] - x[9] * COS64[4];
+    s[10] = x[10] * COS64[20] + x[11] * COS64[12];
+    s[11] = x[10] * COS64[12] - x[11] * COS64[20];
+    s[12] = -x[12] * COS64[28] + x[13] * COS64[4];
+    s[13] = x[12] * COS64[4] + x[13] * COS64[28];
+    s[14] = -x[14] * COS64[12] + x[15] * COS64[20];
+    s[15] = x[14] * COS64[20] + x[15] * COS64[12];
+    let x: [i64; 16] = std::array::from_fn(|k| match k {
+        0..=3 => s[k] + s[k + 4],
+        4..=7 => s[k - 4] - s[k],
+        8..=11 => round_shift(s[k] + s[k + 4]),
+        _ => round_shift(s[k - 4] - s[k]),
+    });
+
+    let mut s = x;
+    for b in [4, 12] {
+        s[b] = x[b] * COS64[8] + x[b + 1] * COS64[24];
+        s[b + 1] = x[b] * COS64[24] - x[b + 1] * COS64[8];
+        s[b + 2] = -x[b + 2] * COS64[24] + x[b + 3] * COS64[8];
+        s[b + 3] = x[b + 2] * COS64[8] + x[b + 3] * COS64[24];
+    }
+    let mut x = [0; 16];
+    for b in [0, 8] {
+        x[b] = s[b] + s[b + 2];
+        x[b + 1] = s[b + 1] + s[b + 3];
+        x[b + 2] = s[b] - s[b + 2];
+        x[b + 3] = s[b + 1] - s[b + 3];
+        x[b + 4] = round_shift(s[b + 4] + s[b + 6]);
+        x[b + 5] = round_shift(s[b + 5] + s[b + 7]);
+        x[b + 6] = round_shift(s[b + 4] - s[b + 6]);
+        x[b + 7] = round_shift(s[b + 5] - s[b + 7]);
+    }
+
+    let x2 = round_shift(-COS64[16] * (x[2] + x[3]));
+    let x3 = round_shift(COS64[16] * (x[2] - x[3]));
+    let x6 = round_shift(COS64[16] * (x[6] + x[7]));
+    let x7 = round_shift(COS64[16] * (-x[6] + x[7]));
+    let x10 = round_shift(COS64[16] * (x[10] + x[11]));
+    let x11 = round_shift(COS64[16] * (-x[10] + x[11]));
+    let x14 = round_shift(-COS64[16] * (x[14] + x[15]));
+    let x15 = round_shift(COS64[16] * (x[14] - x[15]));
+
+    [
+        x[0], -x[8], x[12], -x[4], x6, x14, x10, x2, x3, x11, x15, x7, x[5], -x[13], x[9], -x[1],
+    ]
+}
+
+/// Implements spec `Round2(x, 14)`, which removes the scaling of the cosine constants.
+fn round_shift(value: i64) -> i64 {
+    (value + (1 << 13)) >> 14
+}
+
+/// Implements spec `Round2(x, n)` and saturates the result to 32 bit.
+fn round2(value: i64, n: u8) -> i32 {
+    saturate((value + (1 << (n - 1))) >> n)
+}
+
+fn saturate(value: i64) -> i32 {
+    i32::try_from(value).unwrap_or(if value < 0 { i32::MIN } else { i32::MAX })
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    /// Deterministic pseudo random coefficients in the range of -2048 to 2047.
+    fn coefficients(seed: u32, count: usize) -> Vec<i32> {
+        let mut state = seed;
+        (0..count)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                i32::try_from((state >> 16) & 0xFFF).unwrap() - 2048
+            })
+            .collect()
+    }
+
+    /// Floating point inverse DCT with the scaling of the integer transform.
+    fn float_idct(input: &[i32]) -> Vec<f64> {
+        let n = f64::from(u32::try_from(input.len()).unwrap());
+        (0..input.len())
+            .map(|i| {
+                let i = f64::from(u32::try_from(i).unwrap());
+                input.iter().enumerate().fold(0.0, |sum, (k, value)| {
+                    let k = f64::from(u32::try_from(k).unwrap());
+                    let scale = if k == 0.0 {
+                        std::f64::consts::FRAC_1_SQRT_2
+                    } else {
+                        1.0
+                    };
+                    let angle = (2.0 * i + 1.0) * k * std::f64::consts::PI / (2.0 * n);
+                    sum + f64::from(*value) * scale * angle.cos()
+                })
+            })
+            .collect()
+    }
+
+    /// Floating point inverse ADST with the scaling of the integer transform.
+    fn float_iadst(input: &[i32]) -> Vec<f64> {
+        let n = f64::from(u32::try_from(input.len()).unwrap());
+        (0..input.len())
+            .map(|i| {
+                let i = f64::from(u32::try_from(i).unwrap());
+                input.iter().enumerate().fold(0.0, |sum, (k, value)| {
+                    let k = f64::from(u32::try_from(k).unwrap());
+                    let basis = if n == 4.0 {
+                        2.0 * std::f64::consts::SQRT_2 / 3.0
+                            * ((i + 1.0) * (2.0 * k + 1.0) * std::f64::consts::PI / 9.0).sin()
+                    } else {
+                        ((2.0 * i + 1.0) * (2.0 * k + 1.0) * std::f64::consts::PI / (4.0 * n)).sin()
+                    };
+                    sum + f64::from(*value) * basis
+                })
+            })
+            .collect()
+    }
+
+    fn assert_close(actual: &[i32], expected: &[f64], tolerance: f64) {
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(
+                (f64::from(*actual) - expected).abs() <= tolerance,
+                "{} differs from {}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn transforms_match_float_reference() {
+        for seed in 0..50 {
+            let input = coefficients(seed, 32);
+
+            let mut x4 = <[i32; 4]>::try_from(&input[..4]).unwrap();
+            idct4(&mut x4);
+            assert_close(&x4, &float_idct(&input[..4]), 2.0);
+            let mut x8 = <[i32; 8]>::try_from(&input[..8]).unwrap();
+            idct8(&mut x8);
+            assert_close(&x8, &float_idct(&input[..8]), 3.0);
+            let mut x16 = <[i32; 16]>::try_from(&input[..16]).unwrap();
+            idct16(&mut x16);
+            assert_close(&x16, &float_idct(&input[..16]), 5.0);
+            let mut x32 = <[i32; 32]>::try_from(&input[..32]).unwrap();
+            idct32(&mut x32);
+            assert_close(&x32, &float_idct(&input[..32]), 8.0);
+
+            let mut x4 = <[i32; 4]>::try_from(&input[..4]).unwrap();
+            iadst4(&mut x4);
+            assert_close(&x4, &float_iadst(&input[..4]), 2.0);
+            let mut x8 = <[i32; 8]>::try_from(&input[..8]).unwrap();
+            iadst8(&mut x8);
+            assert_close(&x8, &float_iadst(&input[..8]), 3.0);
+            let mut x16 = <[i32; 16]>::try_from(&input[..16]).unwrap();
+            iadst16(&mut x16);
+            assert_close(&x16, &float_iadst(&input[..16]), 5.0);
+        }
+    }
+
+    #[test]
+    fn inverse_transform_4x4() {
+        let input = [300, -120, 45, 0, 80, -30, 0, 12, -25, 0, 9, 0, 6, 0, 0, -3];
+        let expected = [
+            (
+                TxType::DctDct,
+                [7, 7, 14, 20, 7, 7, 13, 18, 6, 7, 9, 15, 3, 3, 4, 10],
+            ),
+            (
+                TxType::AdstDct,
+                [4, 3, 7, 11, 7, 7, 13, 17, 8, 8, 13, 19, 6, 5, 8, 16],
+            ),
+            (
+                TxType::DctAdst,
+                [2, 3, 12, 23, 2, 4, 12, 21, 2, 4, 9, 17, 1, 1, 4, 11],
+            ),
+            (
+                TxType::AdstAdst,
+                [1, 1, 7, 12, 2, 3, 12, 20, 2, 5, 12, 22, 2, 3, 7, 18],
+            ),
+        ];
+        for (tx_type, residual) in expected {
+            let mut block = input;
+            inverse_transform_2d(&mut block, TxSize::Tx4x4, tx_type, false).unwrap();
+            assert_eq!(block, residual, "{:?}", tx_type);
+        }
+    }
+
+    #[test]
+    fn inverse_transform_8x8() {
+        let mut block = [0; 64];
+        block[0] = -700;
+        block[1] = 210;
+        block[8] = -96;
+        block[9] = 40;
+        block[18] = -17;
+        block[63] = 5;
+        inverse_transform_2d(&mut block, TxSize::Tx8x8, TxType::AdstAdst, false).unwrap();
+        assert_eq!(
+            block,
+            [
+                0, 0, 0, -1, -1, -3, -3, -4, 0, -1, -2, -3, -5, -7, -10, -12, 0, -1, -2, -5, -8,
+                -12, -15, -18, 0, 0, -3, -6, -11, -15, -19, -21, 0, -1, -3, -7, -12, -17, -21, -23,
+                0, -1, -3, -7, -12, -18, -22, -24, 0, -1, -3, -7, -12, -17, -22, -24, -1, -2, -4,
+                -7, -12, -17, -22, -24,
+            ]
+        );
+    }
+
+    #[test]
+    fn inverse_transform_16x16() {
+        let mut block = [0; 256];
+        block[0] = 1500;
+        block[3] = -220;
+        block[80] = 130;
+        block[17] = -64;
+        inverse_transform_2d(&mut block, TxSize::Tx16x16, TxType::AdstDct, false).unwrap();
+        assert_eq!(
+            block[..16],
+            [1, 1, 1, 2, 2, 2, 2, 2, 2, 1, 1, 2, 2, 2, 2, 2]
+        );
+        assert_eq!(
+            block[240..],
+            [13, 14, 16, 18, 19, 19, 18, 16, 14, 12, 11, 12, 13, 15, 17, 18]
+        );
+    }
+
+    #[test]
+    fn inverse_transform_32x32() {
+        let mut block = vec![0; 1024];
+        block[0] = 2048;
+        // The transform type is ignored for 32x32 transforms.
+        inverse_transform_2d(&mut block, TxSize::Tx32x32, TxType::AdstAdst, false).unwrap();
+        assert!(block.iter().all(|value| *value == 16));
+
+        let mut block = vec![0; 1024];
+        block[0] = 4000;
+        block[1] = -800;
+        block[32] = 600;
+        block[231] = -150;
+        inverse_transform_2d(&mut block, TxSize::Tx32x32, TxType::DctDct, false).unwrap();
+        assert_eq!(
+            block[..32],
+            [
+                27, 28, 30, 31, 32, 32, 31, 30, 30, 30, 32, 34, 36, 38, 38, 38, 38, 37, 38, 39, 42,
+                44, 45, 46, 45, 44, 44, 44, 45, 46, 48, 49,
+            ]
+        );
+        assert_eq!(
+            block[992..],
+            [
+                18, 17, 16, 15, 14, 15, 17, 19, 21, 22, 21, 21, 20, 20, 21, 23, 26, 28, 29, 29, 29,
+                28, 28, 29, 30, 32, 34, 35, 35, 34, 32, 31,
+            ]
+        );
+    }
+
+    /// Forward Walsh-Hadamard transform of the lossless mode, following the libvpx encoder.
+    fn forward_wht(residual: &[i32; 16]) -> [i32; 16] {
+        let mut output = [0; 16];
+        for i in 0..4 {
+            let mut a = residual[i];
+            let mut b = residual[4 + i];
+            let mut c = residual[8 + i];
+            let mut d = residual[12 + i];
+            a += b;
+            d -= c;
+            let e = (a - d) >> 1;
+            b = e - b;
+            c = e - c;
+            a -= c;
+            d += b;
+            output[i] = a;
+            output[4 + i] = c;
+            output[8 + i] = d;
+            output[12 + i] = b;
+        }
+        for row in output.chunks_exact_mut(4) {
+            let [mut a, mut b, mut c, mut d] = <[i32; 4]>::try_from(&*row).unwrap();
+            a += b;
+            d -= c;
+            let e = (a - d) >> 1;
+            b = e - b;
+            c = e - c;
+            a -= c;
+            d += b;
+            row.copy_from_slice(&[a * 4, c * 4, d * 4, b * 4]);
+        }
+        output
+    }
+
+    #[test]
+    fn lossless_round_trip() {
+        for seed in 0..50 {
+            // Residuals of 12 bit content.
+            let residual = <[i32; 16]>::try_from(
+                coefficients(seed, 16)
+                    .iter()
+                    .map(|value| value * 2)
+                    .collect::<Vec<i32>>(),
+            )
+            .unwrap();
+            let mut block = forward_wht(&residual);
+            inverse_transform_2d(&mut block, TxSize::Tx4x4, TxType::DctDct, true).unwrap();
+            assert_eq!(block, residual);
+        }
+    }
+
+    #[test]
+    fn invalid_blocks() {
+        let mut block = [0; 15];
+        assert!(matches!(
+            inverse_transform_2d(&mut block, TxSize::Tx4x4, TxType::DctDct, false),
+            Err(Vp9ParserError::InvalidCoefficientCount(15))
+        ));
+        let mut block = [0; 64];
+        assert!(matches!(
+            inverse_transform_2d(&mut block, TxSize::Tx8x8, TxType::DctDct, true),
+            Err(Vp9ParserError::InvalidTransformSize(TxSize::Tx8x8))
+        ));
+    }
+
+    #[test]
+    fn reconstruction() {
+        let residual: [i32; 16] = [
+            -200, -100, -50, -1, 0, 1, 50, 100, 200, 7, -7, 30, -30, 155, -155, 0,
+        ];
+        for (bit_depth, color_depth) in [
+            (8, crate::ColorDepth::Depth8),
+            (10, crate::ColorDepth::Depth10),
+            (12, crate::ColorDepth::Depth12),
+        ] {
+            let mut plane = Plane::new(64, 64, false, false, color_depth);
+            let base = 100u16 << (bit_depth - 8);
+            plane
+                .data_mut()
+                .iter_mut()
+                .for_each(|sample| *sample = base);
+            let transform_block = TransformBlock {
+                mi_row: 1,
+                mi_col: 1,
+                plane: 0,
+                x: 8,
+                y: 12,
+                tx_size: TxSize::Tx4x4,
+                tx_type: TxType::DctDct,
+                eob: 16,
+                coefficients: forward_wht(&residual).to_vec(),
+            };
+            reconstruct(&mut plane, &transform_block, true).unwrap();
+
+            let stride = plane.stride();
+            let max_value = i32::from(plane.max_value());
+            for (i, value) in residual.iter().enumerate() {
+                let sample = plane.data()[(12 + i / 4) * stride + 8 + i % 4];
+                let expected = (i32::from(base) + value).clamp(0, max_value);
+                assert_eq!(i32::from(sample), expected);
+            }
+            // The samples around the block keep the prediction.
+            assert_eq!(plane.data()[12 * stride + 7], base);
+            assert_eq!(plane.data()[16 * stride + 8], base);
+
+            let empty = TransformBlock {
+                eob: 0,
+                coefficients: Vec::new(),
+                x: 32,
+                ..transform_block
+            };
+            reconstruct(&mut plane, &empty, false).unwrap();
+            assert_eq!(plane.data()[12 * stride + 32], base);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Writer for YUV4MPEG2 (Y4M) streams.
+//!
+//! A Y4M stream starts with a header line holding the size, the frame rate and the chroma
+//! format of all pictures. Every picture follows as a `FRAME` line and the visible samples
+//! of the Y, U and V plane. Samples of high bit depth pictures are written as two bytes in
+//! little endian order.
+
+use std::io::Write;
+
+use crate::{ColorRange, Picture, Result, Subsampling, Vp9ParserError};
+
+/// The format of the pictures of a Y4M stream.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Y4mFormat {
+    width: usize,
+    height: usize,
+    subsampling: Subsampling,
+    bit_depth: u8,
+}
+
+impl Y4mFormat {
+    fn from_picture(picture: &Picture) -> Self {
+        Self {
+            width: picture.width(),
+            height: picture.height(),
+            subsampling: picture.frame().subsampling(),
+            bit_depth: picture.bit_depth(),
+        }
+    }
+}
+
+/// Writes decoded pictures as a Y4M stream.
+///
+/// The stream header is written with the first picture. It holds the size, the chroma tag
+/// derived from the subsampling and the bit depth and the color range of that picture. All
+/// following pictures need to have the same size, subsampling and bit depth.
+#[derive(Debug)]
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    frame_rate_rate: u32,
+    frame_rate_scale: u32,
+    format: Option<Y4mFormat>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Creates a writer with a frame rate of 30 frames per second.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            frame_rate_rate: 30,
+            frame_rate_scale: 1,
+            format: None,
+        }
+    }
+
+    /// Sets the frame rate as `rate / scale` frames per second, for example from
+    /// `Ivf::frame_rate_rate()` and `Ivf::frame_rate_scale()`.
+    ///
+    /// Only has an effect before the first picture is written.
+    pub fn set_frame_rate(&mut self, rate: u32, scale: u32) {
+        self.frame_rate_rate = rate;
+        self.frame_rate_scale = scale;
+    }
+
+    /// Writes a picture. The stream header is written before the first picture.
+    ///
+    /// Returns an error if the size, subsampling or bit depth of the picture differs from
+    /// the first picture.
+    pub fn write_picture(&mut self, picture: &Picture) -> Result<()> {
+        let format = Y4mFormat::from_picture(picture);
+        match self.format {
+            Some(stream_format) if stream_format != format => {
+                return Err(Vp9ParserError::Y4mFormatChanged);
+            }
+            Some(_) => {}
+            None => {
+                let color_range = match picture.frame().color_range() {
+                    ColorRange::StudioSwing => "LIMITED",
+                    ColorRange::FullSwing => "FULL",
+                };
+                writeln!(
+                    self.writer,
+                    "YUV4MPEG2 W{} H{} F{}:{} Ip A0:0 C{} XCOLORRANGE={}",
+                    format.width,
+                    format.height,
+                    self.frame_rate_rate,
+                    self.frame_rate_scale,
+                    chroma_tag(format.subsampling, format.bit_depth),
+                    color_range
+                )?;
+                self.format = Some(format);
+            }
+        }
+
+        self.writer.write_all(b"FRAME\n")?;
+        let mut bytes = Vec::new();
+        for plane in picture.planes() {
+            for y in 0..plane.height() {
+                let row = plane.row(y).ok_or(Vp9ParserError::Y4mFormatChanged)?;
+                bytes.clear();
+                if plane.bit_depth() == 8 {
+                    bytes.extend(row.iter().map(|sample| sample.to_le_bytes()[0]));
+                } else {
+                    bytes.extend(row.iter().flat_map(|sample| sample.to_le_bytes()));
+                }
+                self.writer.write_all(&bytes)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Returns the chroma tag of the stream header (`C` parameter).
+fn chroma_tag(subsampling: Subsampling, bit_depth: u8) -> String {
+    let subsampling = match subsampling {
+        Subsampling::Yuv420 if bit_depth == 8 => "420jpeg",
+        Subsampling::Yuv420 => "420",
+        Subsampling::Yuv422 => "422",
+        Subsampling::Yuv440 => "440",
+        Subsampling::Yuv444 => "444",
+    };
+    if bit_depth == 8 {
+        subsampling.to_string()
+    } else {
+        format!("{}p{}", subsampling, bit_depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use std::fs::File;
+
+    use super::*;
+    use crate::{ivf::Ivf, Vp9Decoder};
+
+    #[test]
+    fn chroma_tags() {
+        assert_eq!(chroma_tag(Subsampling::Yuv420, 8), "420jpeg");
+        assert_eq!(chroma_tag(Subsampling::Yuv422, 8), "422");
+        assert_eq!(chroma_tag(Subsampling::Yuv440, 8), "440");
+        assert_eq!(chroma_tag(Subsampling::Yuv444, 8), "444");
+        assert_eq!(chroma_tag(Subsampling::Yuv420, 10), "420p10");
+        assert_eq!(chroma_tag(Subsampling::Yuv444, 12), "444p12");
+    }
+
+    #[test]
+    fn write_pictures() {
+        let file = File::open("tests/data/320-24-crf.ivf").unwrap();
+        let mut ivf = Ivf::new(file).unwrap();
+        let mut decoder = Vp9Decoder::new();
+        let mut writer = Y4mWriter::new(Vec::new());
+        writer.set_frame_rate(ivf.frame_rate_rate(), ivf.frame_rate_scale());
+        for _ in 0..2 {
+            let packet = ivf.read_frame().unwrap().unwrap().packet;
+            for picture in decoder.decode_packet(packet).unwrap() {
+                writer.write_picture(&picture).unwrap();
+            }
+        }
+        writer.flush().unwrap();
+        let data = writer.into_inner();
+
+        let header = format!(
+            "YUV4MPEG2 W320 H180 F{}:{} Ip A0:0 C420jpeg XCOLORRANGE=LIMITED\n",
+            ivf.frame_rate_rate(),
+            ivf.frame_rate_scale()
+        );
+        assert!(data.starts_with(header.as_bytes()));
+        let frame_size = b"FRAME\n".len() + 320 * 180 + 2 * 160 * 90;
+        assert_eq!(data.len(), header.len() + 2 * frame_size);
+        assert_eq!(&data[header.len()..header.len() + 6], b"FRAME\n");
+        assert_eq!(&data[header.len() + frame_size..][..6], b"FRAME\n");
+    }
+
+    #[test]
+    fn write_high_bit_depth_picture() {
+        let file = File::open("tests/data/320-444-10bit.ivf").unwrap();
+        let mut ivf = Ivf::new(file).unwrap();
+        let mut decoder = Vp9Decoder::new();
+        let packet = ivf.read_frame().unwrap().unwrap().packet;
+        let pictures = decoder.decode_packet(packet).unwrap();
+
+        let mut writer = Y4mWriter::new(Vec::new());
+        writer.write_picture(&pictures[0]).unwrap();
+        let data = writer.into_inner();
+
+        let header = "YUV4MPEG2 W320 H180 F30:1 Ip A0:0 C444p10 XCOLORRANGE=LIMITED\n";
+        assert!(data.starts_with(header.as_bytes()));
+        assert_eq!(data.len(), header.len() + 6 + 3 * 320 * 180 * 2);
+        let first = u16::from_le_bytes([data[header.len() + 6], data[header.len() + 7]]);
+        assert_eq!(first, pictures[0].planes()[0].row(0).unwrap()[0]);
+    }
+}